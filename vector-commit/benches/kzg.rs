@@ -1,10 +1,10 @@
-use ark_poly::GeneralEvaluationDomain;
+use ark_poly::{EvaluationDomain, GeneralEvaluationDomain};
 use rand::Rng;
 use sha2::Sha256;
 use vector_commit::{
     kzg::{kzg_point_generator::KZGRandomPointGenerator, *},
     lagrange_basis::LagrangeBasis,
-    HasPrecompute, VCUniversalParams, VectorCommitment,
+    VCUniversalParams, VectorCommitment,
 };
 
 use ark_bn254::Bn254;
@@ -37,7 +37,7 @@ fn setup(n: usize, max_degree: usize) -> (LagrangeBasis<F, D>, KZGKey<F, G1, G2>
     let point_gen = KZGRandomPointGenerator::<G1>::default();
 
     let crs = KZGT::setup(max_degree, &point_gen).unwrap();
-    let prep = LagrangeBasis::from_vec_and_domain(data, *crs.precompute().domain());
+    let prep = LagrangeBasis::from_vec_and_domain(data, D::new(max_degree).unwrap());
 
     (prep, crs)
 }
@@ -0,0 +1,569 @@
+//! The Banderwagon group used by Ethereum verkle trees.
+//!
+//! Banderwagon is the quotient of the Bandersnatch curve by its 2-torsion point `(0, -1)`: the points `(x, y)`
+//! and `(-x, -y)` are the same element. This makes the group prime order without any cofactor clearing, and
+//! lets an element be serialized to 32 bytes with only its x coordinate.
+
+use std::{
+    fmt::{Display, Formatter},
+    hash::{Hash, Hasher},
+    iter::Sum,
+    ops::{Add, AddAssign, Mul, MulAssign, Neg, Sub, SubAssign},
+};
+
+use ark_ec::{
+    twisted_edwards::TECurveConfig, AffineRepr, CurveGroup, Group, ScalarMul, VariableBaseMSM,
+};
+use ark_ed_on_bls12_381_bandersnatch::{BandersnatchConfig, EdwardsAffine, EdwardsProjective};
+use ark_ff::{BigInteger, Field, One, PrimeField, UniformRand, Zero};
+use ark_serialize::{
+    CanonicalDeserialize, CanonicalSerialize, Compress, Read, SerializationError, Valid, Validate,
+    Write,
+};
+use rand::Rng;
+use zeroize::Zeroize;
+
+use crate::VCCommitment;
+
+pub use ark_ed_on_bls12_381_bandersnatch::{Fq, Fr};
+
+/// The length of a serialized Banderwagon element
+pub const SERIALIZED_SIZE: usize = 32;
+
+/// An element of the Banderwagon group in projective form
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Banderwagon(EdwardsProjective);
+
+/// An element of the Banderwagon group in affine form
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BanderwagonAffine(EdwardsAffine);
+
+impl Banderwagon {
+    /// Maps the element to the base field as `x/y`. This is the same for both representatives of the element.
+    pub fn map_to_field(&self) -> Fq {
+        self.0.x * self.0.y.inverse().unwrap()
+    }
+
+    /// Maps the element to the scalar field by reducing the little-endian bytes of `map_to_field`
+    pub fn map_to_scalar_field(&self) -> Fr {
+        Fr::from_le_bytes_mod_order(&self.map_to_field().into_bigint().to_bytes_le())
+    }
+
+    /// Serialize to the 32 big-endian bytes of the x coordinate of the representative whose y coordinate is
+    /// lexicographically largest
+    pub fn to_bytes(&self) -> [u8; SERIALIZED_SIZE] {
+        self.into_affine().to_bytes()
+    }
+
+    /// Deserialize the canonical serialization of an element. Returns `None` if the bytes are not a canonical
+    /// base field element, or do not represent an element of the group.
+    pub fn from_bytes(bytes: &[u8; SERIALIZED_SIZE]) -> Option<Self> {
+        BanderwagonAffine::from_bytes(bytes).map(Self::from)
+    }
+}
+
+impl BanderwagonAffine {
+    pub fn to_bytes(&self) -> [u8; SERIALIZED_SIZE] {
+        let x = if is_lexicographically_largest(&self.0.y) {
+            self.0.x
+        } else {
+            -self.0.x
+        };
+
+        let mut bytes = [0u8; SERIALIZED_SIZE];
+        bytes.copy_from_slice(&x.into_bigint().to_bytes_be());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; SERIALIZED_SIZE]) -> Option<Self> {
+        let x = Fq::from_be_bytes_mod_order(bytes);
+        if x.into_bigint().to_bytes_be() != bytes {
+            return None;
+        }
+
+        Self::from_x(x)
+    }
+
+    /// Recover the element with the x coordinate `x`, choosing the lexicographically largest y coordinate
+    fn from_x(x: Fq) -> Option<Self> {
+        if !subgroup_check(&x) {
+            return None;
+        }
+
+        // a*x^2 + y^2 = 1 + d*x^2*y^2  =>  y^2 = (1 - a*x^2) / (1 - d*x^2)
+        let x_sq = x.square();
+        let numerator = Fq::one() - <BandersnatchConfig as TECurveConfig>::COEFF_A * x_sq;
+        let denominator = Fq::one() - <BandersnatchConfig as TECurveConfig>::COEFF_D * x_sq;
+        let y = (numerator * denominator.inverse()?).sqrt()?;
+        let y = if is_lexicographically_largest(&y) {
+            y
+        } else {
+            -y
+        };
+
+        Some(Self(EdwardsAffine::new_unchecked(x, y)))
+    }
+}
+
+/// Returns true if `x` is the x coordinate of an element of the group, i.e if `1 - a*x^2` is a quadratic residue
+pub fn subgroup_check(x: &Fq) -> bool {
+    (Fq::one() - <BandersnatchConfig as TECurveConfig>::COEFF_A * x.square())
+        .legendre()
+        .is_qr()
+}
+
+/// Returns true if `y > -y` when both are interpreted as integers
+fn is_lexicographically_largest(y: &Fq) -> bool {
+    *y > -*y
+}
+
+impl VCCommitment<Fr> for Banderwagon {
+    fn to_data_item(&self) -> Fr {
+        self.map_to_scalar_field()
+    }
+}
+
+impl PartialEq for Banderwagon {
+    /// `(x1, y1)` and `(x2, y2)` are the same element if `x1*y2 == x2*y1`
+    fn eq(&self, other: &Self) -> bool {
+        self.0.x * other.0.y == other.0.x * self.0.y
+    }
+}
+
+impl Eq for Banderwagon {}
+
+impl PartialEq for BanderwagonAffine {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.x * other.0.y == other.0.x * self.0.y
+    }
+}
+
+impl Eq for BanderwagonAffine {}
+
+impl Hash for Banderwagon {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.to_bytes().hash(state)
+    }
+}
+
+impl Hash for BanderwagonAffine {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.to_bytes().hash(state)
+    }
+}
+
+impl Display for Banderwagon {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.into_affine().fmt(f)
+    }
+}
+
+impl Display for BanderwagonAffine {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for byte in self.to_bytes() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl Zeroize for Banderwagon {
+    fn zeroize(&mut self) {
+        self.0.zeroize()
+    }
+}
+
+impl Zeroize for BanderwagonAffine {
+    fn zeroize(&mut self) {
+        self.0.zeroize()
+    }
+}
+
+impl UniformRand for Banderwagon {
+    fn rand<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        Self::generator() * Fr::rand(rng)
+    }
+}
+
+impl UniformRand for BanderwagonAffine {
+    fn rand<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        Banderwagon::rand(rng).into()
+    }
+}
+
+impl Zero for Banderwagon {
+    fn zero() -> Self {
+        Self(EdwardsProjective::zero())
+    }
+
+    /// Both `(0, 1)` and `(0, -1)` are the identity
+    fn is_zero(&self) -> bool {
+        self.0.x.is_zero()
+    }
+}
+
+impl From<BanderwagonAffine> for Banderwagon {
+    fn from(p: BanderwagonAffine) -> Self {
+        Self(p.0.into())
+    }
+}
+
+impl From<Banderwagon> for BanderwagonAffine {
+    fn from(p: Banderwagon) -> Self {
+        Self(p.0.into())
+    }
+}
+
+impl Valid for Banderwagon {
+    fn check(&self) -> Result<(), SerializationError> {
+        Ok(())
+    }
+}
+
+impl Valid for BanderwagonAffine {
+    fn check(&self) -> Result<(), SerializationError> {
+        Ok(())
+    }
+}
+
+/// Both compressed and uncompressed serialization use the 32 byte encoding of the spec
+impl CanonicalSerialize for Banderwagon {
+    fn serialize_with_mode<W: Write>(
+        &self,
+        mut writer: W,
+        _compress: Compress,
+    ) -> Result<(), SerializationError> {
+        writer.write_all(&self.to_bytes())?;
+        Ok(())
+    }
+
+    fn serialized_size(&self, _compress: Compress) -> usize {
+        SERIALIZED_SIZE
+    }
+}
+
+impl CanonicalSerialize for BanderwagonAffine {
+    fn serialize_with_mode<W: Write>(
+        &self,
+        mut writer: W,
+        _compress: Compress,
+    ) -> Result<(), SerializationError> {
+        writer.write_all(&self.to_bytes())?;
+        Ok(())
+    }
+
+    fn serialized_size(&self, _compress: Compress) -> usize {
+        SERIALIZED_SIZE
+    }
+}
+
+/// Deserialization always performs the subgroup check, as it is needed to recover the y coordinate
+impl CanonicalDeserialize for Banderwagon {
+    fn deserialize_with_mode<R: Read>(
+        reader: R,
+        compress: Compress,
+        validate: Validate,
+    ) -> Result<Self, SerializationError> {
+        BanderwagonAffine::deserialize_with_mode(reader, compress, validate).map(Self::from)
+    }
+}
+
+impl CanonicalDeserialize for BanderwagonAffine {
+    fn deserialize_with_mode<R: Read>(
+        mut reader: R,
+        _compress: Compress,
+        _validate: Validate,
+    ) -> Result<Self, SerializationError> {
+        let mut bytes = [0u8; SERIALIZED_SIZE];
+        reader.read_exact(&mut bytes)?;
+        Self::from_bytes(&bytes).ok_or(SerializationError::InvalidData)
+    }
+}
+
+impl Group for Banderwagon {
+    type ScalarField = Fr;
+
+    fn generator() -> Self {
+        Self(EdwardsProjective::generator())
+    }
+
+    fn double_in_place(&mut self) -> &mut Self {
+        self.0.double_in_place();
+        self
+    }
+
+    fn mul_bigint(&self, other: impl AsRef<[u64]>) -> Self {
+        Self(self.0.mul_bigint(other))
+    }
+}
+
+impl CurveGroup for Banderwagon {
+    type Config = BandersnatchConfig;
+    type BaseField = Fq;
+    type Affine = BanderwagonAffine;
+    type FullGroup = BanderwagonAffine;
+
+    fn normalize_batch(v: &[Self]) -> Vec<Self::Affine> {
+        let inner: Vec<EdwardsProjective> = v.iter().map(|p| p.0).collect();
+        EdwardsProjective::normalize_batch(&inner)
+            .into_iter()
+            .map(BanderwagonAffine)
+            .collect()
+    }
+}
+
+impl ScalarMul for Banderwagon {
+    type MulBase = BanderwagonAffine;
+    const NEGATION_IS_CHEAP: bool = true;
+
+    fn batch_convert_to_mul_base(bases: &[Self]) -> Vec<Self::MulBase> {
+        Self::normalize_batch(bases)
+    }
+}
+
+impl VariableBaseMSM for Banderwagon {}
+
+impl AffineRepr for BanderwagonAffine {
+    type Config = BandersnatchConfig;
+    type ScalarField = Fr;
+    type BaseField = Fq;
+    type Group = Banderwagon;
+
+    fn xy(&self) -> Option<(&Self::BaseField, &Self::BaseField)> {
+        self.0.xy()
+    }
+
+    fn zero() -> Self {
+        Self(EdwardsAffine::zero())
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0.x.is_zero()
+    }
+
+    fn generator() -> Self {
+        Self(EdwardsAffine::generator())
+    }
+
+    /// Interprets `bytes` as a big-endian base field element (reduced modulo the field size), and returns the
+    /// element with that x coordinate if there is one. This is how the Ethereum CRS is generated.
+    fn from_random_bytes(bytes: &[u8]) -> Option<Self> {
+        Self::from_x(Fq::from_be_bytes_mod_order(bytes))
+    }
+
+    fn mul_bigint(&self, by: impl AsRef<[u64]>) -> Self::Group {
+        Banderwagon(self.0.mul_bigint(by))
+    }
+
+    /// The group is prime order, so there is no cofactor to clear
+    fn clear_cofactor(&self) -> Self {
+        *self
+    }
+
+    fn mul_by_cofactor_to_group(&self) -> Self::Group {
+        (*self).into()
+    }
+
+    fn mul_by_cofactor_inv(&self) -> Self {
+        *self
+    }
+}
+
+/// The inner Bandersnatch value of an operand
+trait Inner {
+    type Output;
+    fn inner(self) -> Self::Output;
+}
+
+impl Inner for Banderwagon {
+    type Output = EdwardsProjective;
+    fn inner(self) -> Self::Output {
+        self.0
+    }
+}
+
+impl Inner for BanderwagonAffine {
+    type Output = EdwardsAffine;
+    fn inner(self) -> Self::Output {
+        self.0
+    }
+}
+
+impl Inner for Fr {
+    type Output = Fr;
+    fn inner(self) -> Self::Output {
+        self
+    }
+}
+
+/// Forward a binary operation (and its by-reference version) to the inner Bandersnatch points
+macro_rules! forward_binop {
+    ($trait:ident, $method:ident, $lhs:ty, $rhs:ty) => {
+        impl $trait<$rhs> for $lhs {
+            type Output = Banderwagon;
+
+            fn $method(self, rhs: $rhs) -> Banderwagon {
+                Banderwagon(self.0.$method(rhs.inner()))
+            }
+        }
+
+        impl<'a> $trait<&'a $rhs> for $lhs {
+            type Output = Banderwagon;
+
+            fn $method(self, rhs: &'a $rhs) -> Banderwagon {
+                Banderwagon(self.0.$method(rhs.inner()))
+            }
+        }
+    };
+}
+
+/// Forward an assigning operation (and its by-reference version) to the inner Bandersnatch points
+macro_rules! forward_assign_op {
+    ($trait:ident, $method:ident, $rhs:ty) => {
+        impl $trait<$rhs> for Banderwagon {
+            fn $method(&mut self, rhs: $rhs) {
+                self.0.$method(rhs.inner())
+            }
+        }
+
+        impl<'a> $trait<&'a $rhs> for Banderwagon {
+            fn $method(&mut self, rhs: &'a $rhs) {
+                self.0.$method(rhs.inner())
+            }
+        }
+    };
+}
+
+forward_binop!(Add, add, Banderwagon, Banderwagon);
+forward_binop!(Sub, sub, Banderwagon, Banderwagon);
+forward_binop!(Mul, mul, Banderwagon, Fr);
+forward_binop!(Add, add, Banderwagon, BanderwagonAffine);
+forward_binop!(Sub, sub, Banderwagon, BanderwagonAffine);
+forward_binop!(Add, add, BanderwagonAffine, BanderwagonAffine);
+forward_binop!(Add, add, BanderwagonAffine, Banderwagon);
+forward_binop!(Mul, mul, BanderwagonAffine, Fr);
+
+forward_assign_op!(AddAssign, add_assign, Banderwagon);
+forward_assign_op!(SubAssign, sub_assign, Banderwagon);
+forward_assign_op!(MulAssign, mul_assign, Fr);
+forward_assign_op!(AddAssign, add_assign, BanderwagonAffine);
+forward_assign_op!(SubAssign, sub_assign, BanderwagonAffine);
+
+impl Neg for Banderwagon {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self(-self.0)
+    }
+}
+
+impl Neg for BanderwagonAffine {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self(-self.0)
+    }
+}
+
+impl Sum<Banderwagon> for Banderwagon {
+    fn sum<I: Iterator<Item = Banderwagon>>(iter: I) -> Self {
+        iter.fold(Self::zero(), |acc, p| acc + p)
+    }
+}
+
+impl<'a> Sum<&'a Banderwagon> for Banderwagon {
+    fn sum<I: Iterator<Item = &'a Banderwagon>>(iter: I) -> Self {
+        iter.fold(Self::zero(), |acc, p| acc + p)
+    }
+}
+
+impl Sum<BanderwagonAffine> for Banderwagon {
+    fn sum<I: Iterator<Item = BanderwagonAffine>>(iter: I) -> Self {
+        iter.fold(Self::zero(), |acc, p| acc + p)
+    }
+}
+
+impl<'a> Sum<&'a BanderwagonAffine> for Banderwagon {
+    fn sum<I: Iterator<Item = &'a BanderwagonAffine>>(iter: I) -> Self {
+        iter.fold(Self::zero(), |acc, p| acc + p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ark_ff::field_hashers::DefaultFieldHasher;
+    use rand::thread_rng;
+    use sha2::{Digest, Sha256};
+
+    use crate::{ipa::IPA, linear_domain::LinearDomain, PointGenerator, VectorCommitment};
+
+    type Hasher = DefaultFieldHasher<Sha256>;
+    type TestIPA = IPA<256, Banderwagon, Hasher, LinearDomain<Fr>>;
+
+    #[test]
+    fn test_serialization() {
+        let p = Banderwagon::rand(&mut thread_rng());
+        let bytes = p.to_bytes();
+        assert!(Banderwagon::from_bytes(&bytes).unwrap() == p);
+
+        // Both representatives serialize to the same bytes
+        let torsion = Banderwagon(EdwardsAffine::new_unchecked(Fq::zero(), -Fq::one()).into());
+        assert!(p + torsion == p);
+        assert!((p + torsion).to_bytes() == bytes);
+        assert!((p + torsion).map_to_field() == p.map_to_field());
+
+        assert!(Banderwagon::zero().to_bytes() == [0u8; SERIALIZED_SIZE]);
+        assert!(Banderwagon::from_bytes(&[0u8; SERIALIZED_SIZE])
+            .unwrap()
+            .is_zero());
+        assert!(Banderwagon::zero().map_to_scalar_field() == Fr::zero());
+
+        let mut serialized = Vec::new();
+        p.serialize_compressed(&mut serialized).unwrap();
+        assert!(Banderwagon::deserialize_compressed(&serialized[..]).unwrap() == p);
+    }
+
+    #[test]
+    fn test_subgroup_check() {
+        // A point of the Bandersnatch curve that is outside of the prime order subgroup
+        let outside = (0u64..)
+            .map(Fq::from)
+            .find(|x| {
+                let x_sq = x.square();
+                let num = Fq::one() - <BandersnatchConfig as TECurveConfig>::COEFF_A * x_sq;
+                let den = Fq::one() - <BandersnatchConfig as TECurveConfig>::COEFF_D * x_sq;
+                (num / den).sqrt().is_some() && !subgroup_check(x)
+            })
+            .unwrap();
+        let mut bytes = [0u8; SERIALIZED_SIZE];
+        bytes.copy_from_slice(&outside.into_bigint().to_bytes_be());
+        assert!(Banderwagon::from_bytes(&bytes).is_none());
+
+        // Non-canonical field elements are rejected
+        assert!(Banderwagon::from_bytes(&[0xff; SERIALIZED_SIZE]).is_none());
+
+        let p = Banderwagon::rand(&mut thread_rng());
+        assert!(subgroup_check(&p.into_affine().0.x));
+    }
+
+    #[test]
+    fn test_ethereum_crs() {
+        let point_gen = <TestIPA as VectorCommitment>::PointGenerator::default();
+        let points = point_gen.gen(256).unwrap();
+        assert!(
+            points[0].to_string()
+                == "01587ad1336675eb912550ec2a28eb8923b824b490dd2ba82e48f14590a298a0"
+        );
+
+        let mut hasher = Sha256::new();
+        points.iter().for_each(|p| hasher.update(p.to_bytes()));
+        let digest: String = hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        assert!(digest == "1fcaea10bf24f750200e06fa473c76ff0468007291fa548e2d99f09ba9256fdb");
+    }
+}
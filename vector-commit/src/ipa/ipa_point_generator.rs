@@ -54,9 +54,10 @@ impl<G: CurveGroup, H: HashToCurve<G>> PointGenerator for IPAPointGenerator<G, H
         }
         let hasher = H::new(&self.seed).unwrap();
         let mut res: Vec<G> = Vec::with_capacity(num);
-        let mut i = 0usize;
+        // The counter is hashed as a big-endian u64, as in the Ethereum CRS
+        let mut i = 0u64;
         while res.len() < num {
-            if let Ok(point) = hasher.hash(&i.to_le_bytes()) {
+            if let Ok(point) = hasher.hash(&i.to_be_bytes()) {
                 res.push(point.into());
             }
 
@@ -72,7 +73,7 @@ impl<G: CurveGroup, H: HashToCurve<G>> PointGenerator for IPAPointGenerator<G, H
         }
         let hasher = H::new(&self.seed).unwrap();
         hasher
-            .hash(&index.to_le_bytes())
+            .hash(&(index as u64).to_be_bytes())
             .map_err(|_| PointGeneratorError::InvalidPoint)
             .map(|p| p.into())
     }
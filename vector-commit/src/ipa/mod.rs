@@ -8,10 +8,14 @@ use thiserror::Error;
 
 use crate::{
     lagrange_basis::LagrangeBasis,
+    multiproof::{
+        Multiproof, MultiproofProverQuery, MultiproofVerifierQuery, VectorCommitmentMultiproof,
+    },
     precompute::PrecomputedLagrange,
     transcript::{Transcript, TranscriptError, TranscriptHasher},
     utils::*,
-    HasPrecompute, PointGenerator, VCCommitment, VCUniversalParams, VectorCommitment,
+    HasPrecompute, PointGenerator, VCCommitment, VCProof, VCProofWithEvaluation, VCUniversalParams,
+    VectorCommitment,
 };
 
 mod ipa_point_generator;
@@ -28,26 +32,21 @@ pub struct IPAUniversalParams<const N: usize, G: Group, D: HashToField<G::Scalar
 }
 
 impl<const N: usize, G: Group, D: HashToField<G::ScalarField>> IPAUniversalParams<N, G, D> {
-    fn new(g: [G; N], q: G) -> Self {
+    fn new<ED: EvaluationDomain<G::ScalarField>>(g: [G; N], q: G) -> Self {
         Self {
             g,
             q,
-            precompute: PrecomputedLagrange::new(N),
+            precompute: PrecomputedLagrange::new::<ED>(N),
             digest: PhantomData,
         }
     }
 
-    fn new_from_vec(all: Vec<G>) -> Self {
+    fn new_from_vec<ED: EvaluationDomain<G::ScalarField>>(all: Vec<G>, q: G) -> Self {
         let mut real_g = [G::zero(); N];
         for i in 0..N {
             real_g[i] = all[i];
         }
-        Self {
-            g: real_g,
-            q: all[N],
-            precompute: PrecomputedLagrange::new(N),
-            digest: PhantomData,
-        }
+        Self::new::<ED>(real_g, q)
     }
 }
 
@@ -83,6 +82,65 @@ pub struct IPAProof<G: Group> {
     y: G::ScalarField,
 }
 
+impl<G: Group> IPAProof<G> {
+    /// A proof from its parts, e.g. as decoded from a wire format
+    pub fn new(l: Vec<G>, r: Vec<G>, tip: G::ScalarField, y: G::ScalarField) -> Self {
+        Self { l, r, tip, y }
+    }
+
+    /// The left commitments of each folding round
+    pub fn l(&self) -> &[G] {
+        &self.l
+    }
+
+    /// The right commitments of each folding round
+    pub fn r(&self) -> &[G] {
+        &self.r
+    }
+
+    /// The single element the vector is folded into
+    pub fn tip(&self) -> G::ScalarField {
+        self.tip
+    }
+}
+
+impl<G: Group> VCProof<G::ScalarField> for IPAProof<G> {
+    fn evaluation(&self) -> G::ScalarField {
+        self.y
+    }
+}
+
+impl<G: Group> VCProofWithEvaluation<G::ScalarField> for IPAProof<G> {
+    fn with_evaluation(&self, y: G::ScalarField) -> Self {
+        Self {
+            l: self.l.clone(),
+            r: self.r.clone(),
+            tip: self.tip,
+            y,
+        }
+    }
+}
+
+/// A proof of several items of a single vector: a multiproof opening the vector at each of the `indexes` to its
+/// item in `values`, which costs a single IPA proof whatever the number of items
+pub struct IPABatchProof<G: Group> {
+    indexes: Vec<usize>,
+    values: Vec<G::ScalarField>,
+    proof: Multiproof<IPAProof<G>, G>,
+}
+
+impl<G: Group> IPABatchProof<G> {
+    /// The opened indexes, in the order they were requested
+    pub fn indexes(&self) -> &[usize] {
+        &self.indexes
+    }
+
+    /// The item at each of the opened indexes
+    pub fn values(&self) -> &[G::ScalarField] {
+        &self.values
+    }
+}
+
 #[derive(Error, Clone, Debug)]
 pub enum IPAError {
     #[error("Attempting to use an in-domain function outside of the domain")]
@@ -93,6 +151,9 @@ pub enum IPAError {
 
     #[error("Transcript error")]
     TranscriptError(#[from] TranscriptError),
+
+    #[error("A transcript challenge is zero, and cannot be inverted")]
+    ZeroChallenge,
 }
 
 pub struct IPA<const N: usize, G, H, D> {
@@ -103,9 +164,9 @@ pub struct IPA<const N: usize, G, H, D> {
 
 impl<const N: usize, G, H, D> VectorCommitment for IPA<N, G, H, D>
 where
-    G: CurveGroup,
+    G: CurveGroup + VCCommitment<G::ScalarField>,
     H: HashToField<G::ScalarField> + Sync,
-    D: EvaluationDomain<G::ScalarField>,
+    D: EvaluationDomain<G::ScalarField> + Sync + Send,
 {
     type UniversalParams = IPAUniversalParams<N, G, H>;
     //type PreparedData = IPAPreparedData<N, G::ScalarField>;
@@ -113,7 +174,7 @@ where
     type Commitment = IPACommitment<G>;
     type Data = LagrangeBasis<G::ScalarField, D>;
     type Proof = IPAProof<G>;
-    type BatchProof = Vec<Self::Proof>;
+    type BatchProof = IPABatchProof<G>;
     type Error = IPAError;
     type PointGenerator = IPAPointGenerator<G, EthereumHashToCurve>;
     type Transcript = TranscriptHasher<G::ScalarField, H>;
@@ -122,9 +183,13 @@ where
         max_items: usize,
         gen: &Self::PointGenerator,
     ) -> Result<Self::UniversalParams, crate::PointGeneratorError> {
-        let gens = gen.gen(max_items + 1)?;
+        // The generator of the group is used as the point committing to the inner product, as in the Ethereum spec
+        let gens = gen.gen(max_items)?;
         // TODO: Perhaps the PointGenerator should also have a generic bound on its max size
-        Ok(Self::UniversalParams::new_from_vec(gens))
+        Ok(Self::UniversalParams::new_from_vec::<D>(
+            gens,
+            G::generator(),
+        ))
     }
 
     fn commit(
@@ -134,6 +199,20 @@ where
         Ok(inner_product(&key.g, data.elements_ref()))
     }
 
+    /// The commitment is `sum(a_i * G_i)`, so changing `a_i` adds `(new - old) * G_i`
+    fn update_commitment(
+        key: &Self::UniversalParams,
+        commitment: &Self::Commitment,
+        changes: &[(usize, G::ScalarField, G::ScalarField)],
+    ) -> Result<Option<Self::Commitment>, Self::Error> {
+        let mut updated = *commitment;
+        for &(index, old, new) in changes {
+            let g = key.g.get(index).ok_or(IPAError::OutOfCRS)?;
+            updated += *g * (new - old);
+        }
+        Ok(Some(updated))
+    }
+
     fn prove_point(
         key: &Self::UniversalParams,
         commitment: &Self::Commitment,
@@ -141,7 +220,8 @@ where
         data: &LagrangeBasis<G::ScalarField, D>,
         transcript: Option<Self::Transcript>,
     ) -> Result<Self::Proof, Self::Error> {
-        let mut b = key.precompute.compute_barycentric_coefficients(point);
+        let b = key.precompute.compute_barycentric_coefficients(point);
+        let mut transcript = transcript.unwrap_or_else(|| Self::Transcript::new("ipa"));
         low_level_ipa::<G, G::ScalarField, Self::Transcript>(
             &key.g,
             &key.q,
@@ -149,7 +229,7 @@ where
             &b,
             commitment,
             point,
-            transcript,
+            &mut transcript,
         )
     }
 
@@ -159,7 +239,22 @@ where
         indexes: Vec<usize>,
         data: &LagrangeBasis<G::ScalarField, D>,
     ) -> Result<Self::BatchProof, Self::Error> {
-        todo!()
+        if indexes.iter().any(|&index| index >= N) {
+            return Err(IPAError::OutOfDomain);
+        }
+        let values: Vec<_> = indexes.iter().map(|&index| data[index]).collect();
+        let queries: Vec<_> = indexes
+            .iter()
+            .zip(values.iter())
+            .map(|(&index, &value)| MultiproofProverQuery::new(data, commitment, index, value))
+            .collect();
+        let proof = Self::prove_multiproof(key, &queries)?;
+
+        Ok(IPABatchProof {
+            indexes,
+            values,
+            proof,
+        })
     }
 
     fn verify_point(
@@ -169,6 +264,7 @@ where
         proof: &Self::Proof,
         transcript: Option<Self::Transcript>,
     ) -> Result<bool, Self::Error> {
+        let mut transcript = transcript.unwrap_or_else(|| Self::Transcript::new("ipa"));
         low_level_verify_ipa::<G, G::ScalarField, Self::Transcript>(
             &key.g,
             &key.q,
@@ -176,7 +272,7 @@ where
             commitment,
             point,
             proof,
-            transcript,
+            &mut transcript,
         )
     }
 
@@ -185,15 +281,24 @@ where
         commitment: &Self::Commitment,
         proof: &Self::BatchProof,
     ) -> Result<bool, Self::Error> {
-        todo!()
+        if proof.indexes.iter().any(|&index| index >= N) {
+            return Err(IPAError::OutOfDomain);
+        }
+        let queries: Vec<_> = proof
+            .indexes
+            .iter()
+            .zip(proof.values.iter())
+            .map(|(&index, &value)| MultiproofVerifierQuery::new(commitment, index, value))
+            .collect();
+        Self::verify_multiproof(key, &queries, &proof.proof)
     }
 }
 
 impl<const N: usize, G, H, D> IPA<N, G, H, D>
 where
-    G: CurveGroup,
+    G: CurveGroup + VCCommitment<G::ScalarField>,
     H: HashToField<G::ScalarField> + Sync,
-    D: EvaluationDomain<G::ScalarField>,
+    D: EvaluationDomain<G::ScalarField> + Sync + Send,
 {
     /// Prove that we have made a valid commitment
     pub fn prove_commitment(
@@ -265,6 +370,8 @@ where
     }
 }
 
+/// Proves the inner product of `a` and `b` in `commitment` under the `transcript`, folding the halves of `a`
+/// together with each challenge, and the halves of `b` and `gens` with its inverse, as in the Ethereum verkle spec
 fn low_level_ipa<G: Group<ScalarField = F>, F: PrimeField, T: Transcript<F>>(
     gens: &[G],
     q: &G,
@@ -272,42 +379,40 @@ fn low_level_ipa<G: Group<ScalarField = F>, F: PrimeField, T: Transcript<F>>(
     b: &[F],
     commitment: &IPACommitment<G>,
     input_point: F,
-    prev_transcript: Option<T>,
+    transcript: &mut T,
 ) -> Result<IPAProof<G>, IPAError> {
     let eval = inner_product(&a, &b);
 
     let mut gens = gens[0..a.len()].to_vec();
-    let mut data = a.clone().to_vec();
-    let mut other = b.clone().to_vec();
-    let mut transcript = match prev_transcript {
-        Some(t) => t,
-        None => T::new("ipa"),
-    };
+    let mut data = a.to_vec();
+    let mut other = b.to_vec();
+    transcript.domain_sep("ipa");
     transcript.append(commitment, "C")?;
     transcript.append(&input_point, "input point")?;
     transcript.append(&eval, "output point")?;
 
     let mut l: Vec<G> = Vec::new();
     let mut r: Vec<G> = Vec::new();
-    let mut ra = transcript.digest("w", true);
+    let w = transcript.digest("w", true);
 
-    let q = *q * ra;
+    let q = *q * w;
     while data.len() > 1 {
         let (data_l, data_r) = split(&data);
         let (gens_l, gens_r) = split(&gens);
         let (b_l, b_r) = split(&other);
-        let y_l = inner_product(&gens_r, &data_l) + q * inner_product(&data_l, &b_r);
-        let y_r = inner_product(&gens_l, &data_r) + q * inner_product(&data_r, &b_l);
+        let y_l = inner_product(&gens_l, &data_r) + q * inner_product(&data_r, &b_l);
+        let y_r = inner_product(&gens_r, &data_l) + q * inner_product(&data_l, &b_r);
 
         l.push(y_l);
         r.push(y_r);
         transcript.append(&y_l, "L")?;
         transcript.append(&y_r, "R")?;
-        ra = transcript.digest("x", true);
+        let x = transcript.digest("x", true);
+        let x_inv = x.inverse().ok_or(IPAError::ZeroChallenge)?;
 
-        data = vec_add_and_distribute(&data_l, &data_r, ra);
-        gens = vec_add_and_distribute(&gens_r, &gens_l, ra);
-        other = vec_add_and_distribute(&b_r, &b_l, ra);
+        data = vec_add_and_distribute(&data_l, &data_r, x);
+        gens = vec_add_and_distribute(&gens_l, &gens_r, x_inv);
+        other = vec_add_and_distribute(&b_l, &b_r, x_inv);
     }
 
     Ok(IPAProof {
@@ -325,31 +430,31 @@ fn low_level_verify_ipa<G: Group<ScalarField = F>, F: PrimeField, T: Transcript<
     commitment: &IPACommitment<G>,
     input_point: F,
     proof: &IPAProof<G>,
-    prev_transcript: Option<T>,
+    transcript: &mut T,
 ) -> Result<bool, IPAError> {
-    let mut c = commitment.clone();
-    let mut transcript = match prev_transcript {
-        Some(t) => t,
-        None => T::new("ipa"),
-    };
+    let mut c = *commitment;
+    transcript.domain_sep("ipa");
     transcript.append(commitment, "C")?;
     transcript.append(&input_point, "input point")?;
     transcript.append(&proof.y, "output point")?;
-    let mut ra = transcript.digest("w", true);
+    let w = transcript.digest("w", true);
     let mut points_coeffs = vec![G::ScalarField::one()];
-    let q = *q * ra;
+    let q = *q * w;
     c += q * proof.y;
 
     for i in 0..proof.l.len() {
         transcript.append(&proof.l[i], "L")?;
         transcript.append(&proof.r[i], "R")?;
-        ra = transcript.digest("x", true);
+        let x = transcript.digest("x", true);
+        let x_inv = match x.inverse() {
+            Some(x_inv) => x_inv,
+            None => return Ok(false),
+        };
 
-        c = proof.l[i] + c * ra + proof.r[i] * ra.square();
+        c += proof.l[i] * x + proof.r[i] * x_inv;
         points_coeffs = points_coeffs
             .into_iter()
-            .map(|x| vec![x * ra, x])
-            .flatten()
+            .flat_map(|coeff| [coeff, coeff * x_inv])
             .collect();
     }
 
@@ -401,6 +506,27 @@ mod tests {
         assert!(!IPAT::verify_commitment_proof(&crs, &commit, &proof));
     }
 
+    #[test]
+    fn test_update_commitment() {
+        let data_raw: Vec<F> = (0..SIZE as u64).map(F::from).collect();
+
+        let point_gen = IPAPointGenerator::default();
+        let crs = IPAT::setup(SIZE, &point_gen).unwrap();
+        let mut data = LagrangeBasis::<F, GeneralEvaluationDomain<F>>::from_vec(data_raw);
+        let commit = IPAT::commit(&crs, &data).unwrap();
+
+        let changes = [(3, data[3], F::from(100u64)), (7, data[7], F::zero())];
+        for (index, _, new) in changes {
+            data.set_evaluation(index, new);
+        }
+        let updated = IPAT::update_commitment(&crs, &commit, &changes)
+            .unwrap()
+            .unwrap();
+        assert!(updated == IPAT::commit(&crs, &data).unwrap());
+
+        assert!(IPAT::update_commitment(&crs, &commit, &[(SIZE, F::zero(), F::one())]).is_err());
+    }
+
     #[test]
     fn test_eval_proof() {
         let data_raw: Vec<F> = (0..SIZE as u64).map(|i| F::from(i)).collect();
@@ -419,4 +545,119 @@ mod tests {
         assert!(IPAT::verify(&crs, &commit, index_outside, &proof_outside).unwrap());
         assert!(!IPAT::verify(&crs, &commit, index, &proof_outside).unwrap());
     }
+
+    #[test]
+    fn test_batch_proof() {
+        type D = GeneralEvaluationDomain<F>;
+        let point_gen = IPAPointGenerator::default();
+        let crs = IPAT::setup(SIZE, &point_gen).unwrap();
+        let data = LagrangeBasis::<F, D>::from_vec(
+            (0..SIZE).map(|_| F::rand(&mut thread_rng())).collect(),
+        );
+        let commit = IPAT::commit(&crs, &data).unwrap();
+
+        let indexes = vec![3, 17, 0, SIZE - 1, 17];
+        let mut proof = IPAT::prove_batch(&crs, &commit, indexes.clone(), &data).unwrap();
+        assert!(proof.indexes() == indexes);
+        assert!(proof
+            .values()
+            .iter()
+            .zip(&indexes)
+            .all(|(v, &i)| *v == data[i]));
+        assert!(IPAT::verify_batch(&crs, &commit, &proof).unwrap());
+        assert!(!IPAT::verify_batch(&crs, &(commit + G::generator()), &proof).unwrap());
+
+        proof.values[1] += F::one();
+        assert!(!IPAT::verify_batch(&crs, &commit, &proof).unwrap());
+
+        assert!(IPAT::prove_batch(&crs, &commit, vec![SIZE], &data).is_err());
+    }
+
+    #[test]
+    fn test_banderwagon_eval_proof() {
+        use crate::{
+            banderwagon::{Banderwagon, Fr},
+            linear_domain::LinearDomain,
+        };
+        type BanderwagonIPA = IPA<256, Banderwagon, Hasher, LinearDomain<Fr>>;
+
+        let point_gen = IPAPointGenerator::default();
+        let crs = BanderwagonIPA::setup(256, &point_gen).unwrap();
+        let data = LagrangeBasis::<Fr, LinearDomain<Fr>>::from_vec(
+            (0..256u64).map(|i| Fr::from(i * i + 1)).collect(),
+        );
+        let commit = BanderwagonIPA::commit(&crs, &data).unwrap();
+
+        let index = thread_rng().gen_range(0..256);
+        let proof = BanderwagonIPA::prove(&crs, &commit, index, &data).unwrap();
+        assert!(proof.y == data[index]);
+        assert!(BanderwagonIPA::verify(&crs, &commit, index, &proof).unwrap());
+
+        // Outside of the domain, the vector is evaluated as the polynomial over 0..256
+        let point = Fr::from(1000u64);
+        let proof = BanderwagonIPA::prove_point(&crs, &commit, point, &data, None).unwrap();
+        assert!(proof.y == Fr::from(1000u64 * 1000 + 1));
+        assert!(BanderwagonIPA::verify_point(&crs, &commit, point, &proof, None).unwrap());
+        assert!(!BanderwagonIPA::verify(&crs, &commit, index, &proof).unwrap());
+    }
+
+    #[test]
+    fn test_banderwagon_transcript_consistency() {
+        use crate::{
+            banderwagon::{Banderwagon, Fr},
+            linear_domain::LinearDomain,
+            transcript::EthereumHashToField,
+        };
+        type BanderwagonIPA = IPA<256, Banderwagon, EthereumHashToField, LinearDomain<Fr>>;
+        type EthereumTranscript = TranscriptHasher<Fr, EthereumHashToField>;
+
+        fn to_hex(x: &impl ark_serialize::CanonicalSerialize) -> String {
+            let mut bytes = Vec::new();
+            x.serialize_compressed(&mut bytes).unwrap();
+            bytes.iter().map(|b| format!("{:02x}", b)).collect()
+        }
+
+        // The IPA consistency vectors of the Ethereum verkle spec implementations (go-ipa, rust-verkle)
+        let point_gen = IPAPointGenerator::default();
+        let crs = BanderwagonIPA::setup(256, &point_gen).unwrap();
+        let data = LagrangeBasis::<Fr, LinearDomain<Fr>>::from_vec(
+            (0..256u64).map(|i| Fr::from(i % 32 + 1)).collect(),
+        );
+        let commit = BanderwagonIPA::commit(&crs, &data).unwrap();
+        assert!(
+            commit.to_string()
+                == "1b9dff8f5ebbac250d291dfe90e36283a227c64b113c37f1bfb9e7a743cdb128"
+        );
+
+        let point = Fr::from(2101u64);
+        let b = crs.precompute.compute_barycentric_coefficients(point);
+        let mut transcript = EthereumTranscript::new("test");
+        let proof = low_level_ipa(
+            &crs.g,
+            &crs.q,
+            data.elements_ref(),
+            &b,
+            &commit,
+            point,
+            &mut transcript,
+        )
+        .unwrap();
+        assert!(
+            to_hex(&proof.y) == "4a353e70b03c89f161de002e8713beec0d740a5e20722fd5bd68b30540a33208"
+        );
+        assert!(
+            to_hex(&transcript.digest("state", true))
+                == "0a81881cbfd7d7197a54ebd67ed6a68b5867f3c783706675b34ece43e85e7306"
+        );
+
+        let mut transcript = EthereumTranscript::new("test");
+        assert!(
+            low_level_verify_ipa(&crs.g, &crs.q, &b, &commit, point, &proof, &mut transcript)
+                .unwrap()
+        );
+        assert!(
+            to_hex(&transcript.digest("state", true))
+                == "0a81881cbfd7d7197a54ebd67ed6a68b5867f3c783706675b34ece43e85e7306"
+        );
+    }
 }
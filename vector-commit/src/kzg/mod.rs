@@ -1,27 +1,33 @@
-use std::marker::PhantomData;
+use std::{collections::HashSet, marker::PhantomData};
 
 use ark_ec::{pairing::Pairing, Group};
 use ark_ff::{field_hashers::HashToField, FftField, Field, One, PrimeField, Zero};
 use ark_poly::{
-    univariate::DensePolynomial, DenseUVPolynomial, EvaluationDomain, GeneralEvaluationDomain,
-    Polynomial,
+    univariate::{DenseOrSparsePolynomial, DensePolynomial},
+    DenseUVPolynomial, EvaluationDomain, GeneralEvaluationDomain,
 };
+use rand::thread_rng;
 use thiserror::Error;
 
 use crate::{
     precompute::PrecomputedLagrange,
     transcript::TranscriptHasher,
     utils::{elementwise_mul, inner_product, to_usize},
-    HasPrecompute, LagrangeBasis, PointGenerator, VCCommitment, VCUniversalParams,
-    VectorCommitment,
+    HasPrecompute, LagrangeBasis, PointGenerator, VCCommitment, VCProof, VCProofWithEvaluation,
+    VCUniversalParams, VectorCommitment,
 };
 
 use self::kzg_point_generator::KZGRandomPointGenerator;
 
 pub mod kzg_point_generator;
+mod trusted_setup;
 
 pub type KZGCommitment<G: Group> = G;
 
+/// The largest number of items a batch proof opens, limited by the powers of the secret in G2 the reference string
+/// holds (as in the 65 G2 points of the EIP-4844 setup)
+pub const KZG_MAX_BATCH_SIZE: usize = 64;
+
 /// KZGKey represents the universal parameters, AKA reference string, for both
 /// committing polynomials and verifying commitments
 #[derive(Clone, Debug)]
@@ -29,13 +35,19 @@ pub struct KZGKey<F: FftField, G1: Group, G2: Group> {
     /// The max number of elements this reference string supports
     size: usize,
 
+    /// The powers `α^i*g` of the secret, which the lagrange commitments are derived from
+    monomial_commitments: Vec<G1>,
+
     /// The corresponding `PointGenerator` should commit directly to the lagrange polynomials
     /// as we work in evaluation form.
     lagrange_commitments: Vec<G1>,
 
-    /// For G2, we only need α*g
+    /// For G2, we only need α*g to open single points
     g2: G2,
 
+    /// The powers `α^i*g` for `i` up to `KZG_MAX_BATCH_SIZE`, to open batches of points at once
+    g2_powers: Vec<G2>,
+
     precompute: PrecomputedLagrange<F>,
 }
 
@@ -45,15 +57,96 @@ where
     G1: Group<ScalarField = F>,
     G2: Group<ScalarField = F>,
 {
-    fn from_lagrange_vec(lagrange_g1: Vec<G1>, g2: G2, unity: F) -> Self {
+    fn from_points<D: EvaluationDomain<F>>(
+        monomial_g1: Vec<G1>,
+        lagrange_g1: Vec<G1>,
+        g2_powers: Vec<G2>,
+    ) -> Self {
         let size = lagrange_g1.len();
         Self {
             size,
+            monomial_commitments: monomial_g1,
             lagrange_commitments: lagrange_g1,
-            g2,
-            precompute: PrecomputedLagrange::new(size),
+            g2: g2_powers[1],
+            g2_powers,
+            precompute: PrecomputedLagrange::new::<D>(size),
         }
     }
+
+    /// Commits to the polynomial of coefficients `coeffs` (of a degree below the size of the key), by evaluating
+    /// it over the domain and committing to the evaluations
+    fn commit_coefficients<D: EvaluationDomain<F>>(&self, coeffs: &[F]) -> G1 {
+        let evaluations = D::new(self.size).unwrap().fft(coeffs);
+        inner_product(&self.lagrange_commitments, &evaluations)
+    }
+
+    /// Checks that the key is the reference string of a single secret `s`: the G1 points and the G2 points are the
+    /// powers of `s`, with `g2` the first one, and the lagrange points are the IFFT of the G1 powers. Each check
+    /// compares random linear combinations of the points, so a corrupted or tampered key passes with negligible
+    /// probability.
+    pub fn verify_structure<E, D>(&self) -> Result<(), KZGError>
+    where
+        E: Pairing<ScalarField = F, G1 = G1, G2 = G2>,
+        D: EvaluationDomain<F>,
+    {
+        let invalid = |reason: &str| Err(KZGError::InvalidSetup(reason.to_string()));
+        let n = self.size;
+        let m = self.g2_powers.len();
+        if n < 2
+            || m < 2
+            || self.monomial_commitments.len() != n
+            || self.lagrange_commitments.len() != n
+        {
+            return invalid("the key holds inconsistent numbers of points");
+        }
+        if self.monomial_commitments[0] != G1::generator()
+            || self.g2_powers[0] != G2::generator()
+            || self.g2 != self.g2_powers[1]
+        {
+            return invalid("the first powers are not the generators and g2");
+        }
+
+        let mut rng = thread_rng();
+        let mut random = |len: usize| -> Vec<F> { (0..len).map(|_| F::rand(&mut rng)).collect() };
+
+        // sum r_i [s^(i+1)] = s * sum r_i [s^i], so e(sum r_i [s^(i+1)]_1, 1) = e(sum r_i [s^i]_1, [s]_2)
+        let r = random(n - 1);
+        let shifted = E::pairing(
+            inner_product(&self.monomial_commitments[1..], &r),
+            G2::generator(),
+        );
+        let multiplied = E::pairing(
+            inner_product(&self.monomial_commitments[..n - 1], &r),
+            self.g2,
+        );
+        if shifted != multiplied {
+            return invalid("the G1 points are not the powers of the secret of g2");
+        }
+
+        let r = random(m - 1);
+        let shifted = E::pairing(G1::generator(), inner_product(&self.g2_powers[1..], &r));
+        let multiplied = E::pairing(
+            self.monomial_commitments[1],
+            inner_product(&self.g2_powers[..m - 1], &r),
+        );
+        if shifted != multiplied {
+            return invalid("the G2 points are not the powers of the secret");
+        }
+
+        // L = IFFT(M) is L_i = 1/n sum_j w^(-ij) M_j, so sum r_i L_i = sum_j IFFT(r)_j M_j
+        let domain = match D::new(n) {
+            Some(domain) if domain.size() == n => domain,
+            _ => return Err(KZGError::InvalidDomain),
+        };
+        let r = random(n);
+        if inner_product(&self.lagrange_commitments, &r)
+            != inner_product(&self.monomial_commitments, &domain.ifft(&r))
+        {
+            return invalid("the lagrange points are not the IFFT of the G1 powers");
+        }
+
+        Ok(())
+    }
 }
 
 impl<F, G1, G2> VCUniversalParams for KZGKey<F, G1, G2>
@@ -83,13 +176,61 @@ pub struct KZGProof<F: Field, G: Group> {
     y: F,
 }
 
+impl<F: Field, G: Group> VCProof<F> for KZGProof<F, G> {
+    fn evaluation(&self) -> F {
+        self.y
+    }
+}
+
+impl<F: Field, G: Group> VCProofWithEvaluation<F> for KZGProof<F, G> {
+    fn with_evaluation(&self, y: F) -> Self {
+        Self {
+            proof: self.proof,
+            y,
+        }
+    }
+}
+
+/// A proof of several items of a vector: the commitment to the quotient of the vector's polynomial by the vanishing
+/// polynomial of the opened points, which is a single point whatever the number of items
+pub struct KZGBatchProof<F: Field, G: Group> {
+    indexes: Vec<usize>,
+    values: Vec<F>,
+    proof: KZGCommitment<G>,
+}
+
+impl<F: Field, G: Group> KZGBatchProof<F, G> {
+    /// The opened indexes, in the order they were requested
+    pub fn indexes(&self) -> &[usize] {
+        &self.indexes
+    }
+
+    /// The item at each of the opened indexes
+    pub fn values(&self) -> &[F] {
+        &self.values
+    }
+}
+
+/// The single and batch proofs over the scalar field and G1 of a pairing
+type PairingProof<E> = KZGProof<<E as Pairing>::ScalarField, <E as Pairing>::G1>;
+type PairingBatchProof<E> = KZGBatchProof<<E as Pairing>::ScalarField, <E as Pairing>::G1>;
+
 #[derive(Error, Clone, Debug)]
 pub enum KZGError {
     #[error("An unspecified error occurred")]
     DefaultError,
     #[error("Cannot create the requested domain size")]
     InvalidDomain,
-    //OutOfDomainBounds,
+    #[error("Index is outside of the domain")]
+    OutOfDomainBounds,
+    #[error("Cannot open {0} indexes at once, more than KZG_MAX_BATCH_SIZE")]
+    BatchTooLarge(usize),
+    #[error("Index {0} is opened more than once")]
+    DuplicateIndex(usize),
+    #[error("Cosets of {0} points do not partition the domain")]
+    InvalidCosetSize(usize),
+    #[error("Invalid trusted setup: {0}")]
+    InvalidSetup(String),
 }
 
 /// Implementation of the Feist-Khovratovich technique of "Fast Amortized KZG proofs".
@@ -102,12 +243,14 @@ pub struct KZG<E, H, D> {
 
 impl<E: Pairing, D: EvaluationDomain<E::ScalarField>, H: HashToField<E::ScalarField>>
     VectorCommitment for KZG<E, H, D>
+where
+    E::G1: VCCommitment<E::ScalarField>,
 {
     type UniversalParams = KZGKey<E::ScalarField, E::G1, E::G2>;
     type Commitment = KZGCommitment<E::G1>;
     type Data = LagrangeBasis<E::ScalarField, D>;
     type Proof = KZGProof<E::ScalarField, E::G1>;
-    type BatchProof = Vec<E::G1>;
+    type BatchProof = KZGBatchProof<E::ScalarField, E::G1>;
     type Error = KZGError;
     type PointGenerator = KZGRandomPointGenerator<E::G1>;
     type Transcript = TranscriptHasher<E::ScalarField, H>;
@@ -119,8 +262,16 @@ impl<E: Pairing, D: EvaluationDomain<E::ScalarField>, H: HashToField<E::ScalarFi
         let g1_points = gen.gen(max_items)?;
         let domain = D::new(max_items).unwrap();
         let points = domain.ifft(&g1_points);
-        let g2 = E::G2::generator() * gen.secret().unwrap();
-        Ok(KZGKey::from_lagrange_vec(points, g2, domain.group_gen()))
+        let secret = gen.secret().unwrap();
+        let mut power = E::ScalarField::one();
+        let g2_powers = (0..=KZG_MAX_BATCH_SIZE)
+            .map(|_| {
+                let point = E::G2::generator() * power;
+                power *= secret;
+                point
+            })
+            .collect();
+        Ok(KZGKey::from_points::<D>(g1_points, points, g2_powers))
     }
 
     fn commit(
@@ -133,6 +284,23 @@ impl<E: Pairing, D: EvaluationDomain<E::ScalarField>, H: HashToField<E::ScalarFi
         ))
     }
 
+    /// The commitment is `sum(a_i * [L_i(s)])`, so changing `a_i` adds `(new - old) * [L_i(s)]`
+    fn update_commitment(
+        key: &Self::UniversalParams,
+        commitment: &Self::Commitment,
+        changes: &[(usize, E::ScalarField, E::ScalarField)],
+    ) -> Result<Option<Self::Commitment>, Self::Error> {
+        let mut updated = *commitment;
+        for &(index, old, new) in changes {
+            let l = key
+                .lagrange_commitments
+                .get(index)
+                .ok_or(KZGError::OutOfDomainBounds)?;
+            updated += *l * (new - old);
+        }
+        Ok(Some(updated))
+    }
+
     fn prove_point(
         key: &Self::UniversalParams,
         commitment: &Self::Commitment,
@@ -159,7 +327,23 @@ impl<E: Pairing, D: EvaluationDomain<E::ScalarField>, H: HashToField<E::ScalarFi
         indexes: Vec<usize>,
         data: &LagrangeBasis<E::ScalarField, D>,
     ) -> Result<Self::BatchProof, Self::Error> {
-        todo!()
+        let points = Self::batch_points(key, &indexes)?;
+        let values = indexes
+            .iter()
+            .map(|&index| data.evaluate(key.precompute(), E::ScalarField::from(index as u64)))
+            .collect();
+
+        // f(X) - I(X) vanishes on the opened points, so q(X) is the quotient of f(X) by their vanishing polynomial
+        let vanishing = vanishing_polynomial(&points);
+        let (q, _) = DenseOrSparsePolynomial::from(data.interpolate())
+            .divide_with_q_and_r(&vanishing.into())
+            .ok_or(KZGError::DefaultError)?;
+
+        Ok(KZGBatchProof {
+            indexes,
+            values,
+            proof: key.commit_coefficients::<D>(q.coeffs()),
+        })
     }
 
     fn verify_point(
@@ -171,10 +355,7 @@ impl<E: Pairing, D: EvaluationDomain<E::ScalarField>, H: HashToField<E::ScalarFi
     ) -> Result<bool, Self::Error> {
         let p = if point < E::ScalarField::from(key.max_size() as u64) {
             //key.precompute().unity().pow(&[to_usize(point) as u64])
-            key.precompute()
-                .domain()
-                .group_gen()
-                .pow(&[to_usize(&point) as u64])
+            key.precompute().point(to_usize(&point))
         } else {
             point
         };
@@ -193,45 +374,222 @@ impl<E: Pairing, D: EvaluationDomain<E::ScalarField>, H: HashToField<E::ScalarFi
         commitment: &Self::Commitment,
         proof: &Self::BatchProof,
     ) -> Result<bool, Self::Error> {
-        todo!()
+        let points = Self::batch_points(key, &proof.indexes)?;
+        let vanishing = vanishing_polynomial(&points);
+        let interpolation = interpolate(&points, &proof.values, &vanishing);
+
+        // e(q(s), Z(s)) = e(f(s) - I(s), 1)
+        let vanishing_g2: E::G2 = inner_product(&key.g2_powers, vanishing.coeffs());
+        let pairing1 = E::pairing(proof.proof, vanishing_g2);
+        let pairing2 = E::pairing(
+            *commitment - key.commit_coefficients::<D>(interpolation.coeffs()),
+            E::G2::generator(),
+        );
+
+        Ok(pairing1 == pairing2)
     }
 }
 
-impl<E: Pairing, D: EvaluationDomain<E::ScalarField>, H: HashToField<E::ScalarField>> KZG<E, H, D> {
-    fn prove_all_points(
+impl<E: Pairing, D: EvaluationDomain<E::ScalarField>, H: HashToField<E::ScalarField>> KZG<E, H, D>
+where
+    E::G1: VCCommitment<E::ScalarField>,
+{
+    /// The points of the domain at `indexes`, which must be distinct and at most `KZG_MAX_BATCH_SIZE`
+    fn batch_points(
         key: &KZGKey<E::ScalarField, E::G1, E::G2>,
-        data: &LagrangeBasis<E::ScalarField, D>,
-    ) -> Result<Vec<KZGProof<E::ScalarField, E::G1>>, KZGError> {
-        let poly = data.interpolate();
-        let coeffs = poly.coeffs();
-        let degree = poly.degree();
-        let domain = D::new(degree * 2).ok_or(KZGError::InvalidDomain)?;
-
-        let mut c_hat = vec![coeffs[degree]];
-        c_hat.extend(vec![E::ScalarField::zero(); degree + 1]);
-        c_hat.extend(&coeffs[0..degree]);
+        indexes: &[usize],
+    ) -> Result<Vec<E::ScalarField>, KZGError> {
+        if indexes.len() > KZG_MAX_BATCH_SIZE {
+            return Err(KZGError::BatchTooLarge(indexes.len()));
+        }
+        let mut opened = HashSet::new();
+        indexes
+            .iter()
+            .map(|&index| {
+                if index >= key.size {
+                    return Err(KZGError::OutOfDomainBounds);
+                }
+                if !opened.insert(index) {
+                    return Err(KZGError::DuplicateIndex(index));
+                }
+                Ok(key.precompute().point(index))
+            })
+            .collect()
+    }
 
-        // Use iFFT to transform the lagrange commitments back to their non-lagrange counterparts
-        let g1 = key.precompute().domain().ifft(&key.lagrange_commitments);
-        let mut s_hat = g1[0..degree].to_vec();
-        s_hat.reverse();
-        s_hat.extend(vec![E::G1::zero(); domain.size() - degree]);
+    /// Proves every item of the vector at once with the Feist-Khovratovich technique, in O(n log n) group
+    /// operations for the n points of the reference string's domain. The proof at index `i` verifies as the proof
+    /// of that single index.
+    pub fn prove_all_points(
+        key: &KZGKey<E::ScalarField, E::G1, E::G2>,
+        data: &LagrangeBasis<E::ScalarField, D>,
+    ) -> Result<Vec<PairingProof<E>>, KZGError> {
+        let proofs = Self::coset_quotients(key, data, 1)?;
 
-        let y = domain.fft(&c_hat);
-        let v = domain.fft(&s_hat);
-        let u = elementwise_mul(&v, &y);
+        Ok(proofs
+            .into_iter()
+            .enumerate()
+            .map(|(i, proof)| KZGProof {
+                proof,
+                y: data.evaluate(key.precompute(), E::ScalarField::from(i as u64)),
+            })
+            .collect())
+    }
 
-        let h_hat = domain.ifft(&u);
+    /// Proves the items of every coset of `coset_size` points of the domain at once, in O(n log n) group
+    /// operations. The `k`-th proof opens the indexes `k + j * n / coset_size` for `j` in `0..coset_size`, the
+    /// points `w^k * v^j` where `v` generates the subgroup of `coset_size` elements, and verifies as a batch proof.
+    pub fn prove_all_cosets(
+        key: &KZGKey<E::ScalarField, E::G1, E::G2>,
+        data: &LagrangeBasis<E::ScalarField, D>,
+        coset_size: usize,
+    ) -> Result<Vec<PairingBatchProof<E>>, KZGError> {
+        if coset_size > KZG_MAX_BATCH_SIZE {
+            return Err(KZGError::BatchTooLarge(coset_size));
+        }
+        let proofs = Self::coset_quotients(key, data, coset_size)?;
+        let cosets = proofs.len();
 
-        Ok(h_hat
+        Ok(proofs
             .into_iter()
             .enumerate()
-            .map(|(i, p)| KZGProof {
-                proof: p,
-                y: data[i],
+            .map(|(k, proof)| {
+                let indexes: Vec<usize> = (0..coset_size).map(|j| k + j * cosets).collect();
+                let values = indexes
+                    .iter()
+                    .map(|&index| {
+                        data.evaluate(key.precompute(), E::ScalarField::from(index as u64))
+                    })
+                    .collect();
+                KZGBatchProof {
+                    indexes,
+                    values,
+                    proof,
+                }
             })
             .collect())
     }
+
+    /// Commits to the quotients of f(X) by X^l - w^(kl), the vanishing polynomial of the `k`-th coset of `l`
+    /// points, for every coset.
+    ///
+    /// With f(X) = sum c_i X^i, the quotient by X^l - a is sum_i X^i sum_{m >= 1} a^(m-1) c_(i+ml), so its
+    /// commitment is P(a) = sum_{t >= 0} h_t a^t with h_t = sum_i c_(i+(t+1)l) [s^i]. The h_t are the sum over
+    /// the residues i mod l of Toeplitz products, and P is evaluated at every a = w^(kl) with one FFT.
+    fn coset_quotients(
+        key: &KZGKey<E::ScalarField, E::G1, E::G2>,
+        data: &LagrangeBasis<E::ScalarField, D>,
+        coset_size: usize,
+    ) -> Result<Vec<E::G1>, KZGError> {
+        let n = key.size;
+        if coset_size == 0 || !n.is_multiple_of(coset_size) {
+            return Err(KZGError::InvalidCosetSize(coset_size));
+        }
+        if data.domain_size() != n {
+            return Err(KZGError::InvalidDomain);
+        }
+        let cosets = n / coset_size;
+
+        let domain = D::new(n).ok_or(KZGError::InvalidDomain)?;
+        let coset_domain = D::new(cosets).ok_or(KZGError::InvalidDomain)?;
+        let toeplitz_domain = D::new(2 * cosets).ok_or(KZGError::InvalidDomain)?;
+        if domain.size() != n
+            || coset_domain.size() != cosets
+            || toeplitz_domain.size() != 2 * cosets
+            || coset_domain.group_gen() != domain.group_gen().pow([coset_size as u64])
+        {
+            return Err(KZGError::InvalidDomain);
+        }
+
+        let mut coeffs = data.interpolate().coeffs().to_vec();
+        coeffs.resize(n, E::ScalarField::zero());
+
+        let powers = &key.monomial_commitments;
+
+        let mut h = vec![E::G1::zero(); cosets];
+        for residue in 0..coset_size {
+            let c: Vec<_> = coeffs[residue..]
+                .iter()
+                .step_by(coset_size)
+                .copied()
+                .collect();
+            let s: Vec<_> = powers[residue..]
+                .iter()
+                .step_by(coset_size)
+                .copied()
+                .collect();
+            let product = toeplitz_product(&toeplitz_domain, &c, &s);
+            h.iter_mut().zip(product).for_each(|(h, p)| *h += p);
+        }
+
+        Ok(coset_domain.fft(&h))
+    }
+}
+
+/// Computes h_i = sum_k c_(i+1+k) s_k for every i below the length m of `c` and `s`, with a circular convolution
+/// of size 2m: u = (c_1, .., c_(m-1), 0, ..) and w = (s_0, 0, .., 0, s_(m-1), .., s_1) give
+/// (u * w)_i = sum_k u_(i+k) s_k.
+fn toeplitz_product<F: FftField, G: Group<ScalarField = F>, D: EvaluationDomain<F>>(
+    domain: &D,
+    c: &[F],
+    s: &[G],
+) -> Vec<G> {
+    let m = c.len();
+    let mut u = vec![F::zero(); 2 * m];
+    u[..m - 1].copy_from_slice(&c[1..]);
+    let mut w = vec![G::zero(); 2 * m];
+    w[0] = s[0];
+    for k in 1..m {
+        w[2 * m - k] = s[k];
+    }
+
+    let u = domain.fft(&u);
+    let w = domain.fft(&w);
+    let mut h = domain.ifft(&elementwise_mul(&w, &u));
+    h.truncate(m);
+    h
+}
+
+/// Z(X) = prod (X - x_i) over the `points`
+fn vanishing_polynomial<F: PrimeField>(points: &[F]) -> DensePolynomial<F> {
+    let mut coeffs = vec![F::one()];
+    for point in points {
+        // Multiply by (X - point)
+        coeffs.insert(0, F::zero());
+        for i in 0..coeffs.len() - 1 {
+            let next = coeffs[i + 1];
+            coeffs[i] -= next * point;
+        }
+    }
+    DensePolynomial::from_coefficients_vec(coeffs)
+}
+
+/// The polynomial I(X) of the lowest degree with I(x_i) = y_i, as `sum y_i * Z(X) / ((X - x_i) * Z'(x_i))` for the
+/// `vanishing` polynomial Z(X) of the `points`
+fn interpolate<F: PrimeField>(
+    points: &[F],
+    values: &[F],
+    vanishing: &DensePolynomial<F>,
+) -> DensePolynomial<F> {
+    let mut coeffs = vec![F::zero(); points.len()];
+    for (point, value) in points.iter().zip(values) {
+        // Z(X) / (X - x_i) by synthetic division, and its value at x_i, which is Z'(x_i)
+        let mut quotient = vec![F::zero(); points.len()];
+        let mut carry = F::zero();
+        for i in (0..points.len()).rev() {
+            carry = vanishing.coeffs()[i + 1] + carry * point;
+            quotient[i] = carry;
+        }
+        let derivative = quotient
+            .iter()
+            .rev()
+            .fold(F::zero(), |acc, c| acc * point + c);
+        let scale = *value / derivative;
+        for (c, q) in coeffs.iter_mut().zip(quotient) {
+            *c += q * scale;
+        }
+    }
+    DensePolynomial::from_coefficients_vec(coeffs)
 }
 
 #[cfg(test)]
@@ -270,7 +628,7 @@ mod tests {
         let point_gen = KZGRandomPointGenerator::<G1>::default();
 
         let crs = TKZG::setup(max_degree, &point_gen).unwrap();
-        let prep = LagrangeBasis::from_vec_and_domain(data, *crs.precompute().domain());
+        let prep = LagrangeBasis::from_vec_and_domain(data, D::new(max_degree).unwrap());
 
         (prep, crs)
     }
@@ -296,14 +654,88 @@ mod tests {
         assert!(TKZG::verify(&crs, &commit, outside_index, &outside_proof).unwrap());
     }
 
+    #[test]
+    fn test_batch_proof() {
+        let (data, crs) = setup(DATA_SIZE, MAX_CRS);
+        let commit = TKZG::commit(&crs, &data).unwrap();
+
+        // Unset items past the data open to zero
+        let indexes = vec![5, 1, 7, 12, 0];
+        let mut proof = TKZG::prove_batch(&crs, &commit, indexes.clone(), &data).unwrap();
+        assert!(proof.indexes() == indexes);
+        assert!(proof.values()[0..3] == [data[5], data[1], data[7]]);
+        assert!(proof.values()[3] == F::zero());
+        assert!(TKZG::verify_batch(&crs, &commit, &proof).unwrap());
+        assert!(!TKZG::verify_batch(&crs, &(commit + G1::generator()), &proof).unwrap());
+
+        proof.values[2] += F::one();
+        assert!(!TKZG::verify_batch(&crs, &commit, &proof).unwrap());
+
+        let all = TKZG::prove_batch(&crs, &commit, (0..MAX_CRS).collect(), &data).unwrap();
+        assert!(TKZG::verify_batch(&crs, &commit, &all).unwrap());
+
+        assert!(TKZG::prove_batch(&crs, &commit, vec![1, 1], &data).is_err());
+        assert!(TKZG::prove_batch(&crs, &commit, vec![MAX_CRS], &data).is_err());
+    }
+
+    #[test]
     fn test_amortized_proof() {
         let (data, crs) = setup(DATA_SIZE, MAX_CRS);
         let commit = TKZG::commit(&crs, &data).unwrap();
 
         let proofs = TKZG::prove_all_points(&crs, &data).unwrap();
+        assert!(proofs.len() == MAX_CRS);
 
-        for i in 0..DATA_SIZE {
-            assert!(TKZG::verify(&crs, &commit, i, &proofs[i]).unwrap())
+        for (i, proof) in proofs.iter().enumerate() {
+            assert!(TKZG::verify(&crs, &commit, i, proof).unwrap());
+            assert!(proof.proof == TKZG::prove(&crs, &commit, i, &data).unwrap().proof);
+        }
+        assert!(proofs[DATA_SIZE].y == F::zero());
+        assert!(!TKZG::verify(&crs, &commit, 1, &proofs[0]).unwrap());
+    }
+
+    #[test]
+    fn test_verify_structure() {
+        let (_, crs) = setup(DATA_SIZE, MAX_CRS);
+        assert!(crs.verify_structure::<Bn254, D>().is_ok());
+
+        let mut bad_monomial = crs.clone();
+        bad_monomial.monomial_commitments[5] += G1::generator();
+        assert!(bad_monomial.verify_structure::<Bn254, D>().is_err());
+
+        let mut bad_lagrange = crs.clone();
+        bad_lagrange.lagrange_commitments.swap(2, 3);
+        assert!(bad_lagrange.verify_structure::<Bn254, D>().is_err());
+
+        let mut bad_g2 = crs.clone();
+        bad_g2.g2_powers[KZG_MAX_BATCH_SIZE] = G2::generator();
+        assert!(bad_g2.verify_structure::<Bn254, D>().is_err());
+
+        let other = TKZG::setup(MAX_CRS, &KZGRandomPointGenerator::new(F::from(7u64))).unwrap();
+        let mut mixed = crs.clone();
+        mixed.g2 = other.g2;
+        mixed.g2_powers = other.g2_powers;
+        assert!(mixed.verify_structure::<Bn254, D>().is_err());
+    }
+
+    #[test]
+    fn test_coset_proofs() {
+        let (data, crs) = setup(DATA_SIZE, MAX_CRS);
+        let commit = TKZG::commit(&crs, &data).unwrap();
+
+        for coset_size in [1, 4, MAX_CRS] {
+            let proofs = TKZG::prove_all_cosets(&crs, &data, coset_size).unwrap();
+            assert!(proofs.len() == MAX_CRS / coset_size);
+            for proof in &proofs {
+                assert!(TKZG::verify_batch(&crs, &commit, proof).unwrap());
+            }
         }
+
+        let proofs = TKZG::prove_all_cosets(&crs, &data, 4).unwrap();
+        assert!(proofs[1].indexes() == [1, 5, 9, 13]);
+        assert!(proofs[1].values()[0..2] == [data[1], data[5]]);
+
+        assert!(TKZG::prove_all_cosets(&crs, &data, 3).is_err());
+        assert!(TKZG::prove_all_cosets(&crs, &data, 0).is_err());
     }
 }
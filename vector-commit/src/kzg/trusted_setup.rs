@@ -0,0 +1,314 @@
+//! Loading a reference string from the trusted setup of EIP-4844, whose secret nobody knows.
+//!
+//! The setup is published as `trusted_setup.json`, with the hex strings of the compressed points under
+//! `g1_monomial`, `g1_lagrange` and `g2_monomial`, or as `trusted_setup.txt`: the number of G1 points and the number
+//! of G2 points, followed by the G1 lagrange points, the G2 points and the G1 monomial points, one per line.
+//!
+//! The lagrange points are listed in the bit-reversed order of the domain, and are reordered to the order of the
+//! domain `D`. Its roots of unity must be those of the setup, as is the case for the radix-2 domain of BLS12-381.
+
+use std::path::Path;
+
+use ark_ec::Group;
+use ark_ff::PrimeField;
+use ark_poly::EvaluationDomain;
+use ark_serialize::CanonicalDeserialize;
+use serde_json::Value;
+
+use super::{KZGError, KZGKey, KZG_MAX_BATCH_SIZE};
+
+impl<F, G1, G2> KZGKey<F, G1, G2>
+where
+    F: PrimeField,
+    G1: Group<ScalarField = F>,
+    G2: Group<ScalarField = F>,
+{
+    /// Loads the trusted setup at `path`, in the json format if its extension is `json` and in the text format
+    /// otherwise
+    pub fn from_trusted_setup_file<D: EvaluationDomain<F>>(
+        path: impl AsRef<Path>,
+    ) -> Result<Self, KZGError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| KZGError::InvalidSetup(format!("cannot read {}: {e}", path.display())))?;
+
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            Self::from_trusted_setup_json::<D>(&contents)
+        } else {
+            Self::from_trusted_setup_txt::<D>(&contents)
+        }
+    }
+
+    /// Loads a trusted setup in the format of `trusted_setup.json`
+    pub fn from_trusted_setup_json<D: EvaluationDomain<F>>(json: &str) -> Result<Self, KZGError> {
+        let value: Value = serde_json::from_str(json)
+            .map_err(|e| KZGError::InvalidSetup(format!("invalid json: {e}")))?;
+        let points = |name: &str| -> Result<Vec<&str>, KZGError> {
+            value
+                .get(name)
+                .and_then(Value::as_array)
+                .ok_or_else(|| KZGError::InvalidSetup(format!("missing the {name} list")))?
+                .iter()
+                .map(|point| {
+                    point
+                        .as_str()
+                        .ok_or_else(|| KZGError::InvalidSetup(format!("{name} holds a non-string")))
+                })
+                .collect()
+        };
+
+        Self::from_trusted_setup::<D>(
+            decode_points("g1_monomial", &points("g1_monomial")?)?,
+            decode_points("g1_lagrange", &points("g1_lagrange")?)?,
+            decode_points("g2_monomial", &points("g2_monomial")?)?,
+        )
+    }
+
+    /// Loads a trusted setup in the format of `trusted_setup.txt`
+    pub fn from_trusted_setup_txt<D: EvaluationDomain<F>>(txt: &str) -> Result<Self, KZGError> {
+        let mut tokens = txt.split_whitespace();
+        let mut count = |name: &str| -> Result<usize, KZGError> {
+            tokens
+                .next()
+                .and_then(|token| token.parse().ok())
+                .ok_or_else(|| {
+                    KZGError::InvalidSetup(format!("missing the number of {name} points"))
+                })
+        };
+        let g1_count = count("G1")?;
+        let g2_count = count("G2")?;
+
+        let tokens: Vec<&str> = tokens.collect();
+        if tokens.len() != 2 * g1_count + g2_count {
+            return Err(KZGError::InvalidSetup(format!(
+                "expected {} points, found {}",
+                2 * g1_count + g2_count,
+                tokens.len()
+            )));
+        }
+        let (lagrange, rest) = tokens.split_at(g1_count);
+        let (g2, monomial) = rest.split_at(g2_count);
+
+        Self::from_trusted_setup::<D>(
+            decode_points("g1_monomial", monomial)?,
+            decode_points("g1_lagrange", lagrange)?,
+            decode_points("g2_monomial", g2)?,
+        )
+    }
+
+    /// Checks the sizes of the decoded points, and reorders the bit-reversed lagrange points
+    fn from_trusted_setup<D: EvaluationDomain<F>>(
+        g1_monomial: Vec<G1>,
+        g1_lagrange: Vec<G1>,
+        g2_monomial: Vec<G2>,
+    ) -> Result<Self, KZGError> {
+        let size = g1_lagrange.len();
+        if !size.is_power_of_two() || D::new(size).map(|domain| domain.size()) != Some(size) {
+            return Err(KZGError::InvalidSetup(format!(
+                "{size} lagrange points do not fill a domain"
+            )));
+        }
+        if g1_monomial.len() != size {
+            return Err(KZGError::InvalidSetup(format!(
+                "{} monomial points for {size} lagrange points",
+                g1_monomial.len()
+            )));
+        }
+        if g2_monomial.len() != KZG_MAX_BATCH_SIZE + 1 {
+            return Err(KZGError::InvalidSetup(format!(
+                "expected {} G2 points, found {}",
+                KZG_MAX_BATCH_SIZE + 1,
+                g2_monomial.len()
+            )));
+        }
+        if g1_monomial[0] != G1::generator() || g2_monomial[0] != G2::generator() {
+            return Err(KZGError::InvalidSetup(
+                "the zeroth powers are not the generators".to_string(),
+            ));
+        }
+
+        let bits = size.trailing_zeros();
+        let lagrange = (0..size)
+            .map(|i| g1_lagrange[bit_reverse(i, bits)])
+            .collect();
+
+        Ok(Self::from_points::<D>(g1_monomial, lagrange, g2_monomial))
+    }
+}
+
+/// Decodes the hex strings of compressed points, which must be in the prime-order subgroup
+fn decode_points<G: CanonicalDeserialize>(name: &str, points: &[&str]) -> Result<Vec<G>, KZGError> {
+    points
+        .iter()
+        .enumerate()
+        .map(|(i, point)| {
+            let invalid =
+                |reason: &str| KZGError::InvalidSetup(format!("{name} point {i} {reason}"));
+
+            let bytes = hex::decode(point.strip_prefix("0x").unwrap_or(point))
+                .map_err(|_| invalid("is not hex"))?;
+            let mut reader = bytes.as_slice();
+            let decoded = G::deserialize_compressed_unchecked(&mut reader)
+                .map_err(|_| invalid("is not a point"))?;
+            if !reader.is_empty() {
+                return Err(invalid("has trailing bytes"));
+            }
+            decoded
+                .check()
+                .map_err(|_| invalid("is not in the prime-order subgroup"))?;
+
+            Ok(decoded)
+        })
+        .collect()
+}
+
+/// Reverses the lowest `bits` bits of `index`
+fn bit_reverse(index: usize, bits: u32) -> usize {
+    index
+        .reverse_bits()
+        .checked_shr(usize::BITS - bits)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        kzg::{kzg_point_generator::KZGRandomPointGenerator, KZG},
+        LagrangeBasis, VectorCommitment,
+    };
+    use ark_bls12_381::{Bls12_381, G1Affine, G1Projective, G2Projective};
+    use ark_ec::pairing::Pairing;
+    use ark_ff::{field_hashers::DefaultFieldHasher, One};
+    use ark_poly::GeneralEvaluationDomain;
+    use ark_serialize::CanonicalSerialize;
+    use sha2::Sha256;
+
+    type F = <Bls12_381 as Pairing>::ScalarField;
+    type D = GeneralEvaluationDomain<F>;
+    type TKZG = KZG<Bls12_381, DefaultFieldHasher<Sha256>, D>;
+    type Key = KZGKey<F, G1Projective, G2Projective>;
+
+    const SIZE: usize = 16;
+
+    fn encode<G: CanonicalSerialize>(point: &G) -> String {
+        let mut bytes = Vec::new();
+        point.serialize_compressed(&mut bytes).unwrap();
+        hex::encode(bytes)
+    }
+
+    /// The points of a setup of secret 1234, as listed in the setup files
+    fn setup_points() -> (Vec<String>, Vec<String>, Vec<String>) {
+        let secret = F::from(1234u64);
+        let mut power = F::one();
+        let mut g1_monomial = vec![];
+        for _ in 0..SIZE {
+            g1_monomial.push(G1Projective::generator() * power);
+            power *= secret;
+        }
+        let mut power = F::one();
+        let mut g2_monomial = vec![];
+        for _ in 0..=KZG_MAX_BATCH_SIZE {
+            g2_monomial.push(G2Projective::generator() * power);
+            power *= secret;
+        }
+        let lagrange = D::new(SIZE).unwrap().ifft(&g1_monomial);
+        let bit_reversed = (0..SIZE)
+            .map(|i| lagrange[bit_reverse(i, 4)])
+            .collect::<Vec<_>>();
+
+        (
+            g1_monomial.iter().map(encode).collect(),
+            bit_reversed.iter().map(encode).collect(),
+            g2_monomial.iter().map(encode).collect(),
+        )
+    }
+
+    fn to_json(g1_monomial: &[String], g1_lagrange: &[String], g2_monomial: &[String]) -> String {
+        let prefixed =
+            |points: &[String]| points.iter().map(|p| format!("0x{p}")).collect::<Vec<_>>();
+        serde_json::json!({
+            "g1_monomial": prefixed(g1_monomial),
+            "g1_lagrange": prefixed(g1_lagrange),
+            "g2_monomial": prefixed(g2_monomial),
+        })
+        .to_string()
+    }
+
+    fn to_txt(g1_monomial: &[String], g1_lagrange: &[String], g2_monomial: &[String]) -> String {
+        let mut lines = vec![g1_lagrange.len().to_string(), g2_monomial.len().to_string()];
+        lines.extend_from_slice(g1_lagrange);
+        lines.extend_from_slice(g2_monomial);
+        lines.extend_from_slice(g1_monomial);
+        lines.join("\n")
+    }
+
+    #[test]
+    fn test_load_trusted_setup() {
+        let (monomial, lagrange, g2) = setup_points();
+        let expected = TKZG::setup(SIZE, &KZGRandomPointGenerator::new(F::from(1234u64))).unwrap();
+
+        let from_json =
+            Key::from_trusted_setup_json::<D>(&to_json(&monomial, &lagrange, &g2)).unwrap();
+        let from_txt =
+            Key::from_trusted_setup_txt::<D>(&to_txt(&monomial, &lagrange, &g2)).unwrap();
+        for key in [&from_json, &from_txt] {
+            key.verify_structure::<Bls12_381, D>().unwrap();
+            assert!(key.lagrange_commitments == expected.lagrange_commitments);
+            assert!(key.monomial_commitments == expected.monomial_commitments);
+            assert!(key.g2_powers == expected.g2_powers);
+        }
+
+        let data = LagrangeBasis::from_vec_and_domain(
+            (0..SIZE as u64).map(F::from).collect(),
+            D::new(SIZE).unwrap(),
+        );
+        let commit = TKZG::commit(&from_txt, &data).unwrap();
+        let proof = TKZG::prove(&from_txt, &commit, 3, &data).unwrap();
+        assert!(TKZG::verify(&from_txt, &commit, 3, &proof).unwrap());
+    }
+
+    #[test]
+    fn test_invalid_trusted_setup() {
+        let (monomial, lagrange, g2) = setup_points();
+
+        assert!(
+            Key::from_trusted_setup_json::<D>(&to_json(&monomial, &lagrange, &g2[1..])).is_err()
+        );
+        assert!(
+            Key::from_trusted_setup_json::<D>(&to_json(&monomial[1..], &lagrange, &g2)).is_err()
+        );
+        assert!(Key::from_trusted_setup_txt::<D>(&to_txt(&monomial[1..], &lagrange, &g2)).is_err());
+        assert!(
+            Key::from_trusted_setup_json::<D>(&to_json(&monomial, &lagrange, &g2[..0])).is_err()
+        );
+
+        let mut bad_encoding = lagrange.clone();
+        bad_encoding[5].replace_range(0..2, "00");
+        assert!(
+            Key::from_trusted_setup_json::<D>(&to_json(&monomial, &bad_encoding, &g2)).is_err()
+        );
+
+        let mut truncated = lagrange.clone();
+        truncated[5].truncate(94);
+        assert!(Key::from_trusted_setup_txt::<D>(&to_txt(&monomial, &truncated, &g2)).is_err());
+
+        // A point of the curve outside of the prime-order subgroup
+        let outside = (1u64..)
+            .filter_map(|x| G1Affine::get_point_from_x_unchecked(x.into(), true))
+            .find(|point| !point.is_in_correct_subgroup_assuming_on_curve())
+            .unwrap();
+        let mut bad_subgroup = lagrange.clone();
+        bad_subgroup[5] = encode(&outside);
+        assert!(
+            Key::from_trusted_setup_json::<D>(&to_json(&monomial, &bad_subgroup, &g2)).is_err()
+        );
+
+        let mut shifted = monomial.clone();
+        shifted.rotate_left(1);
+        assert!(Key::from_trusted_setup_json::<D>(&to_json(&shifted, &lagrange, &g2)).is_err());
+    }
+}
@@ -8,20 +8,23 @@
 //! The binding property of these schemes is reliant on no one knowing the secret used in the trusted setup.
 use std::{collections::HashMap, error::Error, fmt::Debug, ops::Index};
 
-use ark_ec::Group;
+use ark_ec::short_weierstrass::{Projective, SWCurveConfig};
 use ark_ff::{FftField, Field, PrimeField, Zero};
 use ark_poly::EvaluationDomain;
+use ark_serialize::CanonicalSerialize;
 use lagrange_basis::LagrangeBasis;
 use precompute::PrecomputedLagrange;
 use thiserror::Error;
 use transcript::Transcript;
 
+pub mod banderwagon;
 pub mod ipa;
 pub mod kzg;
 pub mod lagrange_basis;
+pub mod linear_domain;
 pub mod multiproof;
 pub mod precompute;
-pub(crate) mod transcript;
+pub mod transcript;
 pub(crate) mod utils;
 
 /// The proving and verification parameters for the VC scheme
@@ -35,7 +38,7 @@ pub trait HasPrecompute<F: FftField> {
 }
 
 pub trait VCData: Index<usize> {
-    type Item: From<u64> + Zero;
+    type Item: From<u64> + Zero + Clone;
 
     fn from_vec(data: Vec<Self::Item>) -> Self;
 
@@ -48,24 +51,40 @@ pub trait VCData: Index<usize> {
     fn bytes_to_item(bytes: &[u8]) -> Self::Item;
 }
 
+/// The `(index, old, new)` items of a single changed entry of a `VCData` vector
+pub type ItemChange<D> = (usize, <D as VCData>::Item, <D as VCData>::Item);
+
 pub trait VCCommitment<F> {
     fn to_data_item(&self) -> F;
 }
 
-/// Default implementation when the proof is simply a group element
-impl<G: Group> VCCommitment<G::ScalarField> for G {
-    fn to_data_item(&self) -> G::ScalarField {
+/// Default implementation when the proof is simply a short weierstrass group element
+impl<P: SWCurveConfig> VCCommitment<P::ScalarField> for Projective<P> {
+    fn to_data_item(&self) -> P::ScalarField {
         if self.is_zero() {
-            G::ScalarField::ZERO
+            P::ScalarField::ZERO
         } else {
             let mut bytes: Vec<u8> = Vec::new();
             // TODO: Check
             self.serialize_compressed(&mut bytes).unwrap();
-            G::ScalarField::from_le_bytes_mod_order(&bytes)
+            P::ScalarField::from_le_bytes_mod_order(&bytes)
         }
     }
 }
 
+/// A proof of the evaluation of a committed vector at a single point
+pub trait VCProof<F> {
+    /// The evaluation that the proof claims
+    fn evaluation(&self) -> F;
+}
+
+/// A proof whose claimed evaluation can be replaced. Used by verifiers that compute the evaluation themselves,
+/// e.g. for proofs decoded from a format that omits it.
+pub trait VCProofWithEvaluation<F>: VCProof<F> {
+    /// The same proof, claiming the evaluation `y` instead
+    fn with_evaluation(&self, y: F) -> Self;
+}
+
 /// A vector commitment schemes allows committing to a vector of data and generating proofs of inclusion.
 pub trait VectorCommitment {
     /// The universal parameters for the vector commitment scheme.
@@ -79,7 +98,7 @@ pub trait VectorCommitment {
     type Data: VCData;
 
     /// The proof for a single member of a vector.
-    type Proof;
+    type Proof: VCProof<<Self::Data as VCData>::Item>;
 
     /// The proof for multiple members of a vector.
     type BatchProof;
@@ -106,6 +125,20 @@ pub trait VectorCommitment {
         data: &Self::Data,
     ) -> Result<Self::Commitment, Self::Error>;
 
+    /// Update `commitment` so that it commits to the same vector, with each `(index, old, new)` of `changes`
+    /// replacing the item `old` at `index` by `new`.
+    ///
+    /// Schemes whose commitments are linear in the dataset can apply the difference `new - old` of each item
+    /// directly, and return `Some` updated commitment. By default `None` is returned, meaning the vector must be
+    /// committed to again.
+    fn update_commitment(
+        _key: &Self::UniversalParams,
+        _commitment: &Self::Commitment,
+        _changes: &[ItemChange<Self::Data>],
+    ) -> Result<Option<Self::Commitment>, Self::Error> {
+        Ok(None)
+    }
+
     /// Prove that a piece of data exists inside of `commitment`. The `index` represents the index
     /// of the data inside of `data`.
     fn prove(
@@ -0,0 +1,251 @@
+//! An evaluation domain over the integers `0, 1, ..., n-1`.
+//!
+//! Fields without a large power-of-two multiplicative subgroup (like the scalar field of Bandersnatch) cannot
+//! build a `GeneralEvaluationDomain` of the sizes used in verkle trees, so the Ethereum spec evaluates vectors
+//! over the integers instead. The FFTs of this domain are quadratic, which is fine for the width of a tree node.
+
+use std::{hash::Hash, marker::PhantomData};
+
+use ark_ff::{batch_inversion, FftField};
+use ark_poly::{domain::DomainCoeff, univariate::SparsePolynomial, EvaluationDomain};
+use ark_serialize::{
+    CanonicalDeserialize, CanonicalSerialize, Compress, Read, SerializationError, Valid, Validate,
+    Write,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LinearDomain<F> {
+    size: usize,
+    _f: PhantomData<F>,
+}
+
+impl<F: FftField> LinearDomain<F> {
+    /// The coefficients of the vanishing polynomial `(X - 0)(X - 1)...(X - (n-1))`, lowest degree first
+    fn vanishing_coefficients(&self) -> Vec<F> {
+        let mut coeffs = vec![F::one()];
+        for i in 0..self.size {
+            let root = F::from(i as u64);
+            coeffs.push(F::zero());
+            for k in (0..coeffs.len()).rev() {
+                let lower = if k > 0 { coeffs[k - 1] } else { F::zero() };
+                coeffs[k] = lower - root * coeffs[k];
+            }
+        }
+        coeffs
+    }
+
+    /// The barycentric weights `w_i = 1 / prod_{j != i} (i - j) = (-1)^(n-1-i) / (i! * (n-1-i)!)`
+    fn barycentric_weights(&self) -> Vec<F> {
+        let mut factorials = vec![F::one(); self.size];
+        for i in 1..self.size {
+            factorials[i] = factorials[i - 1] * F::from(i as u64);
+        }
+
+        let mut weights: Vec<F> = (0..self.size)
+            .map(|i| {
+                let w = factorials[i] * factorials[self.size - 1 - i];
+                if (self.size - 1 - i).is_multiple_of(2) {
+                    w
+                } else {
+                    -w
+                }
+            })
+            .collect();
+        batch_inversion(&mut weights);
+        weights
+    }
+}
+
+impl<F: FftField> EvaluationDomain<F> for LinearDomain<F> {
+    type Elements = std::iter::Map<std::ops::Range<u64>, fn(u64) -> F>;
+
+    fn new(num_coeffs: usize) -> Option<Self> {
+        if num_coeffs == 0 {
+            return None;
+        }
+        Some(Self {
+            size: num_coeffs,
+            _f: PhantomData,
+        })
+    }
+
+    /// Cosets of the integers are not supported
+    fn get_coset(&self, _offset: F) -> Option<Self> {
+        None
+    }
+
+    fn compute_size_of_domain(num_coeffs: usize) -> Option<usize> {
+        Some(num_coeffs)
+    }
+
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    fn log_size_of_group(&self) -> u64 {
+        self.size.next_power_of_two().trailing_zeros() as u64
+    }
+
+    fn size_inv(&self) -> F {
+        F::from(self.size as u64).inverse().unwrap()
+    }
+
+    /// The domain is not a multiplicative group, so there is no generator
+    fn group_gen(&self) -> F {
+        F::one()
+    }
+
+    fn group_gen_inv(&self) -> F {
+        F::one()
+    }
+
+    fn coset_offset(&self) -> F {
+        F::one()
+    }
+
+    fn coset_offset_inv(&self) -> F {
+        F::one()
+    }
+
+    fn coset_offset_pow_size(&self) -> F {
+        F::one()
+    }
+
+    /// Evaluate the polynomial with coefficients `coeffs` at every point of the domain
+    fn fft_in_place<T: DomainCoeff<F>>(&self, coeffs: &mut Vec<T>) {
+        let evals = self
+            .elements()
+            .map(|x| {
+                coeffs.iter().rev().fold(T::zero(), |mut acc, c| {
+                    acc *= x;
+                    acc + *c
+                })
+            })
+            .collect();
+        *coeffs = evals;
+    }
+
+    /// Interpolate `evals` with the Lagrange polynomials `L_i(X) = w_i * A(X) / (X - i)`
+    fn ifft_in_place<T: DomainCoeff<F>>(&self, evals: &mut Vec<T>) {
+        evals.resize(self.size, T::zero());
+        let vanishing = self.vanishing_coefficients();
+        let weights = self.barycentric_weights();
+
+        let mut coeffs = vec![T::zero(); self.size];
+        for (i, eval) in evals.iter().enumerate() {
+            // Synthetic division of A(X) by (X - i), from the highest coefficient down
+            let point = F::from(i as u64);
+            let mut quotient = F::zero();
+            for k in (0..self.size).rev() {
+                quotient = vanishing[k + 1] + point * quotient;
+                let mut term = *eval;
+                term *= quotient * weights[i];
+                coeffs[k] += term;
+            }
+        }
+        *evals = coeffs;
+    }
+
+    fn evaluate_all_lagrange_coefficients(&self, tau: F) -> Vec<F> {
+        let mut res: Vec<F> = self.elements().map(|x| tau - x).collect();
+        if let Some(i) = res.iter().position(|d| d.is_zero()) {
+            let mut indicator = vec![F::zero(); self.size];
+            indicator[i] = F::one();
+            return indicator;
+        }
+
+        let vanishing = self.evaluate_vanishing_polynomial(tau);
+        batch_inversion(&mut res);
+        res.iter_mut()
+            .zip(self.barycentric_weights())
+            .for_each(|(r, w)| *r *= vanishing * w);
+        res
+    }
+
+    fn vanishing_polynomial(&self) -> SparsePolynomial<F> {
+        SparsePolynomial::from_coefficients_vec(
+            self.vanishing_coefficients()
+                .into_iter()
+                .enumerate()
+                .filter(|(_, c)| !c.is_zero())
+                .collect(),
+        )
+    }
+
+    fn evaluate_vanishing_polynomial(&self, tau: F) -> F {
+        self.elements().map(|x| tau - x).product()
+    }
+
+    fn element(&self, i: usize) -> F {
+        F::from(i as u64)
+    }
+
+    fn elements(&self) -> Self::Elements {
+        (0..self.size as u64).map(F::from as fn(u64) -> F)
+    }
+}
+
+impl<F: FftField> Valid for LinearDomain<F> {
+    fn check(&self) -> Result<(), SerializationError> {
+        Ok(())
+    }
+}
+
+impl<F: FftField> CanonicalSerialize for LinearDomain<F> {
+    fn serialize_with_mode<W: Write>(
+        &self,
+        writer: W,
+        compress: Compress,
+    ) -> Result<(), SerializationError> {
+        (self.size as u64).serialize_with_mode(writer, compress)
+    }
+
+    fn serialized_size(&self, compress: Compress) -> usize {
+        (self.size as u64).serialized_size(compress)
+    }
+}
+
+impl<F: FftField> CanonicalDeserialize for LinearDomain<F> {
+    fn deserialize_with_mode<R: Read>(
+        reader: R,
+        compress: Compress,
+        validate: Validate,
+    ) -> Result<Self, SerializationError> {
+        let size = u64::deserialize_with_mode(reader, compress, validate)?;
+        Ok(Self {
+            size: size as usize,
+            _f: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ark_ed_on_bls12_381_bandersnatch::Fr;
+    use ark_ff::UniformRand;
+    use ark_poly::{univariate::DensePolynomial, DenseUVPolynomial, Polynomial};
+    use rand::thread_rng;
+
+    #[test]
+    fn test_fft_roundtrip() {
+        let domain = LinearDomain::<Fr>::new(16).unwrap();
+        let poly = DensePolynomial::<Fr>::rand(15, &mut thread_rng());
+
+        let evals = domain.fft(poly.coeffs());
+        for (i, eval) in evals.iter().enumerate() {
+            assert!(*eval == poly.evaluate(&Fr::from(i as u64)));
+        }
+        assert!(domain.ifft(&evals) == poly.coeffs());
+
+        let tau = Fr::rand(&mut thread_rng());
+        let lagrange = domain.evaluate_all_lagrange_coefficients(tau);
+        let eval: Fr = lagrange.iter().zip(evals.iter()).map(|(l, e)| *l * e).sum();
+        assert!(eval == poly.evaluate(&tau));
+        assert!(
+            domain.vanishing_polynomial().evaluate(&tau)
+                == domain.evaluate_vanishing_polynomial(tau)
+        );
+    }
+}
@@ -14,12 +14,9 @@ use itertools::Itertools;
 use rayon::prelude::*;
 
 use crate::{
-    ipa::IPA,
-    kzg::KZG,
-    lagrange_basis::LagrangeBasis,
-    transcript::Transcript,
-    utils::{invert_domain_at, powers_of},
-    HasPrecompute, VCCommitment, VCData, VCUniversalParams, VectorCommitment,
+    ipa::IPA, kzg::KZG, lagrange_basis::LagrangeBasis, transcript::Transcript, utils::powers_of,
+    HasPrecompute, VCCommitment, VCData, VCProof, VCProofWithEvaluation, VCUniversalParams,
+    VectorCommitment,
 };
 
 #[derive(Clone)]
@@ -40,6 +37,9 @@ impl<'a, C, D, F: Clone> MultiproofProverQuery<'a, C, D, F> {
     }
 }
 
+/// A query of a multiproof over the Lagrange basis of the domain `D`
+pub type LagrangeProverQuery<'a, C, F, D> = MultiproofProverQuery<'a, C, LagrangeBasis<F, D>, F>;
+
 pub struct MultiproofVerifierQuery<'a, C, F> {
     commit: &'a C,
     z: usize,
@@ -57,6 +57,23 @@ pub struct Multiproof<P, D> {
     d: D,
 }
 
+impl<P, D> Multiproof<P, D> {
+    /// A multiproof from its parts, e.g. as decoded from a wire format
+    pub fn new(proof: P, d: D) -> Self {
+        Self { proof, d }
+    }
+
+    /// The opening of `h - g` at the challenge point `t`
+    pub fn proof(&self) -> &P {
+        &self.proof
+    }
+
+    /// The commitment `D` to the quotient polynomial `g`
+    pub fn d(&self) -> &D {
+        &self.d
+    }
+}
+
 /// Vector Commitments used in Multiproofs must certain execute mathematical operations
 /// and other attributes.
 pub trait VCCommitmentMultiProof<F>:
@@ -98,17 +115,24 @@ where
     /// Create a multiproof that proves multiple datasets at (possibly) multiple different evaluation points
     fn prove_multiproof<'a>(
         key: &Self::UniversalParams,
-        queries: &[MultiproofProverQuery<
-            'a,
-            Self::Commitment,
-            LagrangeBasis<G::ScalarField, D>,
-            G::ScalarField,
-        >],
+        queries: &[LagrangeProverQuery<'a, Self::Commitment, G::ScalarField, D>],
+    ) -> Result<Multiproof<Self::Proof, Self::Commitment>, Self::Error> {
+        let transcript = <Self as VectorCommitment>::Transcript::new("multiproof");
+        Self::prove_multiproof_with_transcript(key, queries, transcript)
+    }
+
+    /// Create a multiproof continuing `transcript`, e.g. the transcript of a verkle proof. The queries are appended
+    /// after the "multiproof" domain separator, with their evaluation points as field elements, as in the Ethereum
+    /// verkle spec.
+    fn prove_multiproof_with_transcript<'a>(
+        key: &Self::UniversalParams,
+        queries: &[LagrangeProverQuery<'a, Self::Commitment, G::ScalarField, D>],
+        mut transcript: Self::Transcript,
     ) -> Result<Multiproof<Self::Proof, Self::Commitment>, Self::Error> {
-        let mut transcript = <Self as VectorCommitment>::Transcript::new("multiproof");
+        transcript.domain_sep("multiproof");
         for query in queries.iter() {
             transcript.append(query.commit, "C");
-            transcript.append(&query.z, "z");
+            transcript.append(&G::ScalarField::from(query.z as u64), "z");
             transcript.append(&query.y, "y");
         }
 
@@ -155,7 +179,7 @@ where
         let t = transcript.digest("t", true);
 
         // Calculate all the t-z_i inversions at once
-        let inversions = invert_domain_at::<G::ScalarField>(t, key.max_size());
+        let inversions = key.precompute().inverted_distances(t);
 
         // Calculate h(x)
         let mut h = LagrangeBasis::new_zero(key.max_size());
@@ -179,11 +203,28 @@ where
         key: &Self::UniversalParams,
         queries: &[MultiproofVerifierQuery<'a, Self::Commitment, G::ScalarField>],
         proof: &Multiproof<Self::Proof, Self::Commitment>,
-    ) -> Result<bool, Self::Error> {
-        let mut transcript = <Self as VectorCommitment>::Transcript::new("multiproof");
+    ) -> Result<bool, Self::Error>
+    where
+        Self::Proof: VCProofWithEvaluation<G::ScalarField>,
+    {
+        let transcript = <Self as VectorCommitment>::Transcript::new("multiproof");
+        Self::verify_multiproof_with_transcript(key, queries, proof, transcript)
+    }
+
+    /// Verify a multiproof continuing `transcript`, as created by `prove_multiproof_with_transcript`
+    fn verify_multiproof_with_transcript<'a>(
+        key: &Self::UniversalParams,
+        queries: &[MultiproofVerifierQuery<'a, Self::Commitment, G::ScalarField>],
+        proof: &Multiproof<Self::Proof, Self::Commitment>,
+        mut transcript: Self::Transcript,
+    ) -> Result<bool, Self::Error>
+    where
+        Self::Proof: VCProofWithEvaluation<G::ScalarField>,
+    {
+        transcript.domain_sep("multiproof");
         for query in queries {
             transcript.append(query.commit, "C");
-            transcript.append(&query.z, "z");
+            transcript.append(&G::ScalarField::from(query.z as u64), "z");
             transcript.append(&query.y, "y");
         }
 
@@ -195,7 +236,7 @@ where
         let mut r_pow = G::ScalarField::one();
         let mut e_coeffs = HashMap::<&Self::Commitment, G::ScalarField>::new();
 
-        let inversions = invert_domain_at::<G::ScalarField>(t, key.max_size());
+        let inversions = key.precompute().inverted_distances(t);
 
         for query in queries {
             let e_coeff = r_pow * inversions[query.z];
@@ -211,13 +252,16 @@ where
         let e: Self::Commitment = e_coeffs.into_iter().map(|(c, coeff)| *c * coeff).sum();
         transcript.append(&e, "E");
 
-        Self::verify_point(key, &(e - proof.d), t, &proof.proof, Some(transcript))
+        // (h - g)(t) must equal g2(t), otherwise the proof does not open the queried evaluations. The opening is
+        // verified at g2(t) whatever evaluation the proof claims, so proofs encoded without it verify too.
+        let opening = proof.proof.with_evaluation(g2_of_t);
+        Self::verify_point(key, &(e - proof.d), t, &opening, Some(transcript))
     }
 }
 
 impl<const N: usize, G, H, D> VectorCommitmentMultiproof<G, D> for IPA<N, G, H, D>
 where
-    G: CurveGroup,
+    G: CurveGroup + VCCommitment<G::ScalarField>,
     H: HashToField<G::ScalarField> + Sync,
     D: EvaluationDomain<G::ScalarField> + Sync + Send,
     <Self as VectorCommitment>::Commitment: VCCommitmentMultiProof<G::ScalarField>,
@@ -227,6 +271,7 @@ where
 impl<E, H, D> VectorCommitmentMultiproof<E::G1, D> for KZG<E, H, D>
 where
     E: Pairing,
+    E::G1: VCCommitment<E::ScalarField>,
     H: HashToField<E::ScalarField> + Sync,
     D: EvaluationDomain<E::ScalarField> + Sync + Send,
     <Self as VectorCommitment>::Commitment: VCCommitmentMultiProof<E::ScalarField>,
@@ -355,4 +400,100 @@ mod tests {
         //assert!(!IPAT::verify_multiproof(&crs, &verifier_queries, &proof).unwrap());
         //proof.proof.l[0] -= G::generator();
     }
+
+    #[test]
+    fn test_banderwagon_multiproof() {
+        use crate::{
+            banderwagon::{Banderwagon, Fr},
+            linear_domain::LinearDomain,
+        };
+        type D = LinearDomain<Fr>;
+        type BanderwagonIPA = IPA<256, Banderwagon, Hasher, D>;
+
+        let point_gen = IPAPointGenerator::default();
+        let crs = BanderwagonIPA::setup(256, &point_gen).unwrap();
+
+        let all_data: Vec<(LagrangeBasis<Fr, D>, Banderwagon)> = (0..4)
+            .map(|_| {
+                let data = LagrangeBasis::<Fr, D>::from_vec(
+                    (0..256).map(|_| Fr::rand(&mut thread_rng())).collect(),
+                );
+                let commit = BanderwagonIPA::commit(&crs, &data).unwrap();
+                (data, commit)
+            })
+            .collect();
+
+        let queries: Vec<_> = all_data
+            .iter()
+            .map(|(data, commit)| {
+                let z = thread_rng().gen_range(0..256);
+                MultiproofProverQuery::new(data, commit, z, data[z])
+            })
+            .collect();
+        let mut verifier_queries: Vec<_> = queries.iter().map(|q| q.to_verifier_query()).collect();
+
+        let proof = BanderwagonIPA::prove_multiproof(&crs, &queries).unwrap();
+        assert!(BanderwagonIPA::verify_multiproof(&crs, &verifier_queries, &proof).unwrap());
+
+        verifier_queries[0].y += Fr::one();
+        assert!(!BanderwagonIPA::verify_multiproof(&crs, &verifier_queries, &proof).unwrap());
+    }
+
+    #[test]
+    fn test_banderwagon_multiproof_consistency() {
+        use crate::{
+            banderwagon::{Banderwagon, Fr},
+            linear_domain::LinearDomain,
+            transcript::{EthereumHashToField, TranscriptHasher},
+        };
+        type D = LinearDomain<Fr>;
+        type BanderwagonIPA = IPA<256, Banderwagon, EthereumHashToField, D>;
+        type EthereumTranscript = TranscriptHasher<Fr, EthereumHashToField>;
+
+        // The multiproof consistency vector of the Ethereum verkle spec implementations (go-ipa, rust-verkle),
+        // serialized as D, the L and R of each round, and the tip
+        const EXPECTED: &str = "4f53588244efaf07a370ee3f9c467f933eed360d4fbf7a19dfc8bc49b67df4711bf1d0a720717cd6a8c75f1a668cb7cbdd63b48c676b89a7aee4298e71bd7f4013d7657146aa9736817da47051ed6a45fc7b5a61d00eb23e5df82a7f285cc10e67d444e91618465ca68d8ae4f2c916d1942201b7e2aae491ef0f809867d00e83468fb7f9af9b42ede76c1e90d89dd789ff22eb09e8b1d062d8a58b6f88b3cbe80136fc68331178cd45a1df9496ded092d976911b5244b85bc3de41e844ec194256b39aeee4ea55538a36139211e9910ad6b7a74e75d45b869d0a67aa4bf600930a5f760dfb8e4df9938d1f47b743d71c78ba8585e3b80aba26d24b1f50b36fa1458e79d54c05f58049245392bc3e2b5c5f9a1b99d43ed112ca82b201fb143d401741713188e47f1d6682b0bf496a5d4182836121efff0fd3b030fc6bfb5e21d6314a200963fe75cb856d444a813426b2084dfdc49dca2e649cb9da8bcb47859a4c629e97898e3547c591e39764110a224150d579c33fb74fa5eb96427036899c04154feab5344873d36a53a5baefd78c132be419f3f3a8dd8f60f72eb78dd5f43c53226f5ceb68947da3e19a750d760fb31fa8d4c7f53bfef11c4b89158aa56b1f4395430e16a3128f88e234ce1df7ef865f2d2c4975e8c82225f578310c31fd41d265fd530cbfa2b8895b228a510b806c31dff3b1fa5c08bffad443d567ed0e628febdd22775776e0cc9cebcaea9c6df9279a5d91dd0ee5e7a0434e989a160005321c97026cb559f71db23360105460d959bcdf74bee22c4ad8805a1d497507";
+
+        let point_gen = IPAPointGenerator::default();
+        let crs = BanderwagonIPA::setup(256, &point_gen).unwrap();
+        let data_a =
+            LagrangeBasis::<Fr, D>::from_vec((0..256u64).map(|i| Fr::from(i % 32 + 1)).collect());
+        let data_b = LagrangeBasis::<Fr, D>::from_vec(
+            (0..256u64).rev().map(|i| Fr::from(i % 32 + 1)).collect(),
+        );
+        let commit_a = BanderwagonIPA::commit(&crs, &data_a).unwrap();
+        let commit_b = BanderwagonIPA::commit(&crs, &data_b).unwrap();
+        let queries = vec![
+            MultiproofProverQuery::new(&data_a, &commit_a, 0, data_a[0]),
+            MultiproofProverQuery::new(&data_b, &commit_b, 0, data_b[0]),
+        ];
+        let verifier_queries: Vec<_> = queries.iter().map(|q| q.to_verifier_query()).collect();
+
+        let proof = BanderwagonIPA::prove_multiproof_with_transcript(
+            &crs,
+            &queries,
+            EthereumTranscript::new("test"),
+        )
+        .unwrap();
+        let mut bytes = Vec::new();
+        proof.d().serialize_compressed(&mut bytes).unwrap();
+        for point in proof.proof().l().iter().chain(proof.proof().r()) {
+            point.serialize_compressed(&mut bytes).unwrap();
+        }
+        proof
+            .proof()
+            .tip()
+            .serialize_compressed(&mut bytes)
+            .unwrap();
+        let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        assert!(hex == EXPECTED);
+
+        assert!(BanderwagonIPA::verify_multiproof_with_transcript(
+            &crs,
+            &verifier_queries,
+            &proof,
+            EthereumTranscript::new("test"),
+        )
+        .unwrap());
+    }
 }
@@ -1,8 +1,11 @@
 //! Working in a domain with d-th roots of unity enables a large computational efficiency increase
 //! when working with polynomials in evaluation form.
+//!
+//! Any `EvaluationDomain` can be used. The roots of unity give cheap vanishing polynomials, while domains such as
+//! `LinearDomain` support fields without large multiplicative subgroups.
 
 use ark_ff::{batch_inversion, FftField, PrimeField};
-use ark_poly::{EvaluationDomain, GeneralEvaluationDomain};
+use ark_poly::{univariate::SparsePolynomial, EvaluationDomain, Polynomial};
 
 use crate::utils::to_usize;
 
@@ -12,46 +15,50 @@ use crate::utils::to_usize;
 pub struct PrecomputedLagrange<F: FftField> {
     size: usize,
 
-    //unity: F,
-    domain: GeneralEvaluationDomain<F>,
+    /// The points x_i of the domain
+    points: Vec<F>,
 
+    /// A(X) = prod (X - x_i)
+    vanishing_polynomial: SparsePolynomial<F>,
+
+    /// A'(x_i)
     vanishing_evaluations: Vec<F>,
 
+    /// 1 / A'(x_i), which are the barycentric weights
     vanishing_evaluations_inv: Vec<F>,
-    //barycentric_weights: [F; N],
 }
 
 impl<F: PrimeField> PrecomputedLagrange<F> {
-    pub(crate) fn new(size: usize) -> Self {
-        let domain = GeneralEvaluationDomain::<F>::new(size).unwrap();
-        let (evals, inv) = Self::compute_vanishing_evaluations(size, &domain.group_gen());
+    pub(crate) fn new<D: EvaluationDomain<F>>(size: usize) -> Self {
+        let domain = D::new(size).unwrap();
+        let points: Vec<F> = domain.elements().collect();
+        let vanishing_polynomial = domain.vanishing_polynomial();
+        let (evals, inv) = Self::compute_vanishing_evaluations(&points, &vanishing_polynomial);
         Self {
-            size,
-            domain,
+            size: points.len(),
+            points,
+            vanishing_polynomial,
             vanishing_evaluations: evals,
             vanishing_evaluations_inv: inv,
         }
     }
 
-    // pub(crate) fn new_with_unity(size: usize, unity: F) -> Self {
-    //     let (evals, inv) = Self::compute_vanishing_evaluations(size, &unity);
-    //     Self {
-    //         size,
-    //         unity,
-    //         vanishing_evaluations: evals,
-    //         vanishing_evaluations_inv: inv,
-    //     }
-    // }
-
-    fn compute_vanishing_evaluations(size: usize, unity: &F) -> (Vec<F>, Vec<F>) {
-        let mut evals = vec![F::zero(); size];
-        let mut inv = vec![F::zero(); size];
-
-        let n_f = F::from(size as u64);
-        for i in 0..size {
-            evals[i] = n_f * unity.pow(&[i as u64]).inverse().unwrap();
-            inv[i] = evals[i]; // Batch invert after loop
-        }
+    /// Evaluates A'(x_i) = sum_k k * a_k * x_i^(k-1) at every point
+    fn compute_vanishing_evaluations(
+        points: &[F],
+        vanishing: &SparsePolynomial<F>,
+    ) -> (Vec<F>, Vec<F>) {
+        let evals: Vec<F> = points
+            .iter()
+            .map(|x| {
+                vanishing
+                    .iter()
+                    .filter(|(k, _)| *k > 0)
+                    .map(|(k, a)| F::from(*k as u64) * a * x.pow([*k as u64 - 1]))
+                    .sum()
+            })
+            .collect();
+        let mut inv = evals.clone();
         batch_inversion(&mut inv);
 
         (evals, inv)
@@ -68,7 +75,7 @@ impl<F: PrimeField> PrecomputedLagrange<F> {
     /// Computes the b vector in IPA. When this vector is inner product'd by the evaluations in the domain,
     /// the result is the evaluation F(point).
     ///
-    /// b_i = l(point) / l'(x_i)(z-x_i)
+    /// b_i = A(point) / A'(x_i)(point-x_i)
     pub(crate) fn compute_barycentric_coefficients(&self, point: F) -> Vec<F> {
         let mut res = vec![F::zero(); self.size];
         if point < F::from(self.size as u64) {
@@ -77,22 +84,25 @@ impl<F: PrimeField> PrecomputedLagrange<F> {
             return res;
         }
 
-        let unity = self.domain.group_gen();
-
-        // t is the constant outside the summation in PCS multiproofs article
-        let t = (point.pow(&[self.size as u64]) - F::one()) / F::from(self.size as u64);
+        let vanishing = self.vanishing_polynomial.evaluate(&point);
+        let inversions = self.inverted_distances(point);
         for i in 0..self.size {
-            let pow = unity.pow(&[i as u64]);
-            res[i] = (t * pow) / (point - pow);
+            res[i] = vanishing * self.vanishing_evaluations_inv[i] * inversions[i];
         }
 
         res
     }
 
-    // pub(crate) fn unity(&self) -> F {
-    //     self.unity
-    // }
-    pub fn domain(&self) -> &GeneralEvaluationDomain<F> {
-        &self.domain
+    /// Computes 1 / (t - x_i) for every point of the domain
+    pub(crate) fn inverted_distances(&self, t: F) -> Vec<F> {
+        let mut res: Vec<F> = self.points.iter().map(|x| t - x).collect();
+        batch_inversion(&mut res);
+
+        res
+    }
+
+    /// Returns the point x_i of the domain
+    pub fn point(&self, index: usize) -> F {
+        self.points[index]
     }
 }
@@ -1,7 +1,9 @@
 use std::marker::PhantomData;
 
-use ark_ff::{field_hashers::HashToField, Field};
+use ark_ff::{field_hashers::HashToField, Field, PrimeField};
 use ark_serialize::CanonicalSerialize;
+use digest::Digest;
+use sha2::Sha256;
 use thiserror::Error;
 
 #[derive(Error, Debug, Clone)]
@@ -21,10 +23,20 @@ pub trait Transcript<F> {
         label: &str,
     ) -> Result<(), TranscriptError>;
 
-    /// Digest the current transcript to a field element. `clear` will clear the state and append the output to the state
+    /// Append a `label` alone to the state, separating the messages of a sub-protocol
+    fn domain_sep(&mut self, label: &str) {
+        // The unit serializes to no bytes
+        let _ = self.append(&(), label);
+    }
+
+    /// Digest the current transcript to a field element. `clear` will clear the state and append the label and output
+    /// to the state
     fn digest(&mut self, label: &str, clear: bool) -> F;
 }
 
+/// A transcript hashing its state to field elements with `H`. The state starts with the transcript's label, every
+/// element is appended after its label, and a cleared state holds the label and output of the last digest, as in
+/// the transcript of the Ethereum verkle spec (with `EthereumHashToField`).
 pub struct TranscriptHasher<F: Field, H: HashToField<F>> {
     state: Vec<u8>,
     hasher: H,
@@ -34,7 +46,7 @@ pub struct TranscriptHasher<F: Field, H: HashToField<F>> {
 impl<F: Field, D: HashToField<F>> Transcript<F> for TranscriptHasher<F, D> {
     fn new(label: &str) -> Self {
         Self {
-            state: Vec::new(),
+            state: label.as_bytes().to_vec(),
             hasher: D::new(label.as_bytes()),
             _f: PhantomData,
         }
@@ -54,13 +66,38 @@ impl<F: Field, D: HashToField<F>> Transcript<F> for TranscriptHasher<F, D> {
         self.state.append(&mut label.as_bytes().to_vec());
         let res = self.hasher.hash_to_field(&self.state, 1)[0];
         if clear {
-            self.state = serialize(&res).unwrap();
-            self.state.append(&mut label.as_bytes().to_vec());
+            self.state = label.as_bytes().to_vec();
+            self.state.append(&mut serialize(&res).unwrap());
         }
         res
     }
 }
 
+/// Hashes to field elements as the transcript of the Ethereum verkle spec: the SHA-256 digest of the message, as a
+/// little-endian integer reduced modulo the field size. The domain is ignored, as the transcript holds its labels.
+///
+/// Each element past the first hashes the message followed by its index.
+pub struct EthereumHashToField;
+
+impl<F: PrimeField> HashToField<F> for EthereumHashToField {
+    fn new(_domain: &[u8]) -> Self {
+        Self
+    }
+
+    fn hash_to_field(&self, msg: &[u8], count: usize) -> Vec<F> {
+        (0..count)
+            .map(|i| {
+                let mut hasher = Sha256::new();
+                hasher.update(msg);
+                if i > 0 {
+                    hasher.update((i as u64).to_le_bytes());
+                }
+                F::from_le_bytes_mod_order(&hasher.finalize())
+            })
+            .collect()
+    }
+}
+
 fn serialize<T: CanonicalSerialize>(x: &T) -> Result<Vec<u8>, TranscriptError> {
     let mut b = Vec::new();
     if x.serialize_compressed(&mut b).is_err() {
@@ -3,7 +3,7 @@ use std::{
     ops::{Add, Mul},
 };
 
-use ark_ff::{batch_inversion, Field, One, PrimeField};
+use ark_ff::{One, PrimeField};
 use ark_serialize::CanonicalSerialize;
 
 pub(crate) fn serialize<T: CanonicalSerialize>(x: &T) -> Vec<u8> {
@@ -54,13 +54,6 @@ pub(crate) fn powers_of<T: Mul<T, Output = T> + One + Copy>(a: T, n: usize) -> V
     res
 }
 
-pub(crate) fn invert_domain_at<F: Field>(t: F, N: usize) -> Vec<F> {
-    let mut res: Vec<F> = (0..N as u64).map(|i| t - F::from(i)).collect::<Vec<F>>();
-    batch_inversion(&mut res);
-
-    res
-}
-
 pub(crate) fn max<'a, T: Ord>(l: &'a T, r: &'a T) -> &'a T {
     if l < r {
         r
@@ -0,0 +1,175 @@
+//! The node cache bounds the number of extension nodes a `VerkleTree` keeps in memory. Internal nodes, along with
+//! their cached commitments, are never evicted, while the extension nodes accessed least recently are evicted to
+//! the tree's `NodeStore` (e.g. a `FileStore` as the spill file) once there are more than the cache's capacity.
+//!
+//! Eviction leaves only the commitment of an extension in its parent, as `unload` does, so the extension is loaded
+//! again from the store the next time one of its keys is accessed. An extension that changed since it was last
+//! written is written to the store before it is evicted. Extensions that changed since the commitment of the tree
+//! was generated have no commitment for their parent to keep, so they stay loaded until it is generated again.
+//! They are pinned until then, out of the way of the eviction of the others.
+
+use std::{hash::Hash, sync::Arc};
+
+use im::{HashMap, OrdMap};
+
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use bytemuck::Pod;
+use num::Zero;
+use vector_commit::{VCData, VectorCommitment};
+
+use crate::{node::Node, Key, NodeStore, SplittableValue, VerkleError};
+
+/// Counters of how accesses to the tree were served by its node cache
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Accesses to keys whose path was entirely loaded
+    pub hits: u64,
+    /// Accesses to keys that had to load nodes from the store
+    pub misses: u64,
+    /// Extension nodes evicted to the store
+    pub evictions: u64,
+}
+
+/// The recency of the extension nodes in memory, by their stem. Its maps are persistent, so cloning the cache along
+/// with its tree is O(1).
+#[derive(Clone)]
+pub(crate) struct NodeCache<const N: usize, K> {
+    capacity: usize,
+    tick: u64,
+    recency: HashMap<Key<N, K>, u64>,
+    /// The stems that may be evicted, by their last access
+    order: OrdMap<u64, Key<N, K>>,
+    /// The stems whose extension cannot be evicted until the commitment of the tree is generated, by their last
+    /// access
+    pub(crate) pinned: OrdMap<u64, Key<N, K>>,
+    pub(crate) stats: CacheStats,
+}
+
+/// What evicting the extension node of a stem did
+pub(crate) enum Eviction {
+    Evicted,
+    /// The extension has no commitment for its parent to keep
+    Pinned,
+    /// There is no extension node with the stem in memory
+    Missing,
+}
+
+impl<const N: usize, K: Eq + Hash + Copy> NodeCache<N, K> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tick: 0,
+            recency: HashMap::new(),
+            order: OrdMap::new(),
+            pinned: OrdMap::new(),
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+    }
+
+    /// Marks the extension node of `stem` as the most recently accessed
+    pub(crate) fn touch(&mut self, stem: &Key<N, K>) {
+        self.tick += 1;
+        if let Some(old) = self.recency.insert(*stem, self.tick) {
+            self.order.remove(&old);
+            self.pinned.remove(&old);
+        }
+        self.order.insert(self.tick, *stem);
+    }
+
+    /// Stops tracking the extension node of `stem`, e.g. after it was evicted or removed
+    pub(crate) fn forget(&mut self, stem: &Key<N, K>) {
+        if let Some(old) = self.recency.remove(stem) {
+            self.order.remove(&old);
+            self.pinned.remove(&old);
+        }
+    }
+
+    /// Keeps the extension node of `stem` out of eviction until `unpin_all`
+    pub(crate) fn pin(&mut self, stem: &Key<N, K>) {
+        if let Some(&tick) = self.recency.get(stem) {
+            if let Some(stem) = self.order.remove(&tick) {
+                self.pinned.insert(tick, stem);
+            }
+        }
+    }
+
+    /// Returns the pinned extension nodes to eviction, at their last access, e.g. once they have a commitment
+    pub(crate) fn unpin_all(&mut self) {
+        let pinned = std::mem::take(&mut self.pinned);
+        self.order = std::mem::take(&mut self.order).union(pinned);
+    }
+
+    /// Whether more extension nodes are tracked than the cache holds
+    pub(crate) fn is_over_capacity(&self) -> bool {
+        self.recency.len() > self.capacity
+    }
+
+    /// The least recently accessed stem that may be evicted
+    pub(crate) fn coldest(&self) -> Option<Key<N, K>> {
+        self.order.get_min().map(|(_, stem)| *stem)
+    }
+
+    /// Every tracked stem, pinned or not
+    pub(crate) fn stems(&self) -> Vec<Key<N, K>> {
+        self.recency.keys().copied().collect()
+    }
+}
+
+impl<const N: usize, K, VC, T> Node<N, K, VC, T>
+where
+    K: Eq + Hash + Zero + Copy + Pod + Into<usize>,
+    VC: VectorCommitment,
+    VC::Commitment: CanonicalSerialize + CanonicalDeserialize,
+    <VC::Data as VCData>::Item: Copy + num::One,
+    T: SplittableValue<Output = <VC::Data as VCData>::Item>
+        + Zero
+        + Clone
+        + PartialEq
+        + CanonicalSerialize
+        + CanonicalDeserialize,
+{
+    /// Evicts the extension node with `stem` below this internal node at `depth`, keeping only its commitment.
+    /// The extension is written to `store` first if its record is not up to date.
+    pub(crate) fn evict_stem<S: NodeStore>(
+        &mut self,
+        stem: &Key<N, K>,
+        depth: usize,
+        store: &mut S,
+    ) -> Result<Eviction, VerkleError> {
+        let (children, unloaded) = match self {
+            Self::Internal {
+                children, unloaded, ..
+            } => (children, unloaded),
+            Self::Extension { .. } | Self::Stub { .. } => return Ok(Eviction::Missing),
+        };
+
+        let k = stem[depth];
+        let child = match children.get_mut(&k) {
+            Some(c) => c,
+            None => return Ok(Eviction::Missing),
+        };
+        match child.as_ref() {
+            Self::Internal { .. } => Arc::make_mut(child).evict_stem(stem, depth + 1, store),
+            Self::Extension {
+                stem: child_stem, ..
+            } if child_stem != stem => Ok(Eviction::Missing),
+            Self::Stub { .. } => Ok(Eviction::Missing),
+            Self::Extension { .. } => {
+                let commit = match child.clean_commitment() {
+                    Some(c) => c.clone(),
+                    None => return Ok(Eviction::Pinned),
+                };
+                if !child.is_persisted() {
+                    Arc::make_mut(child).flush(&mut stem[0..=depth].to_vec(), store, vec![])?;
+                }
+                children.remove(&k);
+                unloaded.insert(k, commit);
+                Ok(Eviction::Evicted)
+            }
+        }
+    }
+}
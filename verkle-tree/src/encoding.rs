@@ -0,0 +1,63 @@
+use bytemuck::{bytes_of, Pod};
+use num::Zero;
+use vector_commit::VCData;
+
+use crate::{Key, KeyMethods, SplittableValue};
+
+/// How an extension node encodes its stem and leaves as the items of the vectors it commits to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExtensionEncoding {
+    /// Leaves are the two items of `SplittableValue::split`, and the stem is the bytes of all of its units.
+    /// A leaf set to a value that splits into zeroes cannot be told apart from a missing leaf.
+    #[default]
+    Generic,
+
+    /// The encoding of the Ethereum spec (EIP-6800). The stem is the bytes of its first `N - 1` units (31 bytes
+    /// for 32 byte keys), and 2^128 is added to the lower item of every leaf to mark it as present.
+    Ethereum,
+}
+
+impl ExtensionEncoding {
+    /// The item the `stem` of an extension is committed as
+    pub(crate) fn stem_item<D, const N: usize, K>(&self, stem: &Key<N, K>) -> D::Item
+    where
+        D: VCData,
+        K: Zero + Copy + PartialEq + Pod,
+    {
+        match self {
+            Self::Generic => D::bytes_to_item(&stem.to_bytes()),
+            Self::Ethereum => {
+                let bytes: Vec<u8> = stem[..N - 1]
+                    .iter()
+                    .flat_map(|u| bytes_of(u).to_owned())
+                    .collect();
+                D::bytes_to_item(&bytes)
+            }
+        }
+    }
+
+    /// The lower and upper items a leaf is committed as. Both are zero for a missing leaf
+    pub(crate) fn leaf_items<D, T>(&self, leaf: Option<&T>) -> (D::Item, D::Item)
+    where
+        D: VCData,
+        T: SplittableValue<Output = D::Item>,
+    {
+        let leaf = match leaf {
+            Some(l) => l,
+            None => return (D::Item::zero(), D::Item::zero()),
+        };
+
+        let (low, high) = leaf.split();
+        match self {
+            Self::Generic => (low, high),
+            Self::Ethereum => (low + Self::leaf_marker::<D>(), high),
+        }
+    }
+
+    /// 2^128, which marks a leaf as present
+    fn leaf_marker<D: VCData>() -> D::Item {
+        let mut bytes = [0u8; 17];
+        bytes[16] = 1;
+        D::bytes_to_item(&bytes)
+    }
+}
@@ -0,0 +1,273 @@
+//! The journal records how a `VerkleTree` changed since each open checkpoint, so the changes can be reverted.
+//!
+//! The first time a key changes after a checkpoint, its previous value is recorded, along with the cached
+//! commitments (and changed items) of every node on the path to its stem, by their path. Reverting to the checkpoint
+//! sets every recorded key back to its previous value, which puts every node back in the same place (the shape of
+//! the tree only depends on the stems it holds), and then restores the recorded state of each node. Nothing is
+//! committed to again.
+
+use std::{collections::HashMap, hash::Hash, sync::Arc};
+
+use bytemuck::Pod;
+use num::{One, Zero};
+use vector_commit::{VCData, VectorCommitment};
+
+use crate::{node::Node, Key, SplittableValue};
+
+/// Identifies a checkpoint of a tree
+pub type CheckpointId = usize;
+
+/// The cached commitments of a node, and the items it changed by since they were generated
+pub(crate) enum NodeState<K, VC: VectorCommitment, T> {
+    Internal {
+        commit: Option<VC::Commitment>,
+        changed: HashMap<K, <VC::Data as VCData>::Item>,
+    },
+    Extension {
+        commit: Option<VC::Commitment>,
+        c1: Option<VC::Commitment>,
+        c2: Option<VC::Commitment>,
+        changed: HashMap<K, Option<T>>,
+    },
+}
+
+/// The state of the tree when a checkpoint was opened, for the keys and nodes that changed since
+pub(crate) struct Checkpoint<const N: usize, K, VC: VectorCommitment, T> {
+    id: CheckpointId,
+    /// The value of each `(stem, unit)` when the checkpoint was opened
+    pub(crate) leaves: HashMap<(Key<N, K>, K), Option<T>>,
+    /// The state of each node when the checkpoint was opened, by its path
+    pub(crate) nodes: HashMap<Vec<K>, NodeState<K, VC, T>>,
+}
+
+/// The open checkpoints of a tree, from the oldest to the newest. Changes are recorded in the newest one.
+pub(crate) struct Journal<const N: usize, K, VC: VectorCommitment, T> {
+    next_id: CheckpointId,
+    checkpoints: Vec<Checkpoint<N, K, VC, T>>,
+}
+
+impl<K: Copy, VC: VectorCommitment, T: Clone> Clone for NodeState<K, VC, T> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Internal { commit, changed } => Self::Internal {
+                commit: commit.clone(),
+                changed: changed.clone(),
+            },
+            Self::Extension {
+                commit,
+                c1,
+                c2,
+                changed,
+            } => Self::Extension {
+                commit: commit.clone(),
+                c1: c1.clone(),
+                c2: c2.clone(),
+                changed: changed.clone(),
+            },
+        }
+    }
+}
+
+impl<const N: usize, K: Copy, VC: VectorCommitment, T: Clone> Clone for Checkpoint<N, K, VC, T> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            leaves: self.leaves.clone(),
+            nodes: self.nodes.clone(),
+        }
+    }
+}
+
+impl<const N: usize, K: Copy, VC: VectorCommitment, T: Clone> Clone for Journal<N, K, VC, T> {
+    fn clone(&self) -> Self {
+        Self {
+            next_id: self.next_id,
+            checkpoints: self.checkpoints.clone(),
+        }
+    }
+}
+
+impl<const N: usize, K, VC, T> Journal<N, K, VC, T>
+where
+    K: Eq + Hash + Copy,
+    VC: VectorCommitment,
+{
+    pub(crate) fn new() -> Self {
+        Self {
+            next_id: 0,
+            checkpoints: vec![],
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.checkpoints.is_empty()
+    }
+
+    pub(crate) fn open(&mut self) -> CheckpointId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.checkpoints.push(Checkpoint {
+            id,
+            leaves: HashMap::new(),
+            nodes: HashMap::new(),
+        });
+        id
+    }
+
+    /// The newest checkpoint, which changes are recorded in
+    pub(crate) fn newest(&mut self) -> Option<&mut Checkpoint<N, K, VC, T>> {
+        self.checkpoints.last_mut()
+    }
+
+    /// Removes the checkpoint `id` and all newer ones, returning them from the newest to the oldest
+    pub(crate) fn close(&mut self, id: CheckpointId) -> Option<Vec<Checkpoint<N, K, VC, T>>> {
+        let position = self.checkpoints.iter().position(|c| c.id == id)?;
+        Some(self.checkpoints.drain(position..).rev().collect())
+    }
+
+    /// Records the changes of the `closed` checkpoints (newest first) in the newest remaining one, where the oldest
+    /// recorded state of each key and node is kept
+    pub(crate) fn merge(&mut self, closed: Vec<Checkpoint<N, K, VC, T>>) {
+        let newest = match self.checkpoints.last_mut() {
+            Some(c) => c,
+            None => return,
+        };
+        for checkpoint in closed.into_iter().rev() {
+            for (key, value) in checkpoint.leaves {
+                newest.leaves.entry(key).or_insert(value);
+            }
+            for (path, state) in checkpoint.nodes {
+                newest.nodes.entry(path).or_insert(state);
+            }
+        }
+    }
+
+    /// Drops every commitment recorded in the journal, so that nodes reverted to their recorded state are
+    /// committed to again
+    pub(crate) fn clear_commitments(&mut self) {
+        for checkpoint in self.checkpoints.iter_mut() {
+            for state in checkpoint.nodes.values_mut() {
+                *state = match state {
+                    NodeState::Internal { .. } => NodeState::Internal {
+                        commit: None,
+                        changed: HashMap::new(),
+                    },
+                    NodeState::Extension { .. } => NodeState::Extension {
+                        commit: None,
+                        c1: None,
+                        c2: None,
+                        changed: HashMap::new(),
+                    },
+                };
+            }
+        }
+    }
+}
+
+impl<const N: usize, K, VC, T> Node<N, K, VC, T>
+where
+    K: Eq + Hash + Zero + Copy + Pod + Into<usize>,
+    VC: VectorCommitment,
+    <VC::Data as VCData>::Item: Copy + One,
+    T: SplittableValue<Output = <VC::Data as VCData>::Item> + Zero + Clone + PartialEq,
+{
+    fn state(&self) -> NodeState<K, VC, T> {
+        match self {
+            Self::Internal {
+                commit, changed, ..
+            } => NodeState::Internal {
+                commit: commit.clone(),
+                changed: changed.clone(),
+            },
+            Self::Extension {
+                commit,
+                c1,
+                c2,
+                changed,
+                ..
+            } => NodeState::Extension {
+                commit: commit.clone(),
+                c1: c1.clone(),
+                c2: c2.clone(),
+                changed: changed.clone(),
+            },
+            Self::Stub { .. } => unreachable!("Pruned nodes have no state to record"),
+        }
+    }
+
+    /// Records the state of this node at `stem[0..depth]` and of the loaded nodes below it on the path to `stem`
+    /// in `nodes`, unless their path already has a recorded state. Pruned nodes never change, so are not recorded.
+    pub(crate) fn record_path(
+        &self,
+        stem: &Key<N, K>,
+        depth: usize,
+        nodes: &mut HashMap<Vec<K>, NodeState<K, VC, T>>,
+    ) {
+        if let Self::Stub { .. } = self {
+            return;
+        }
+        nodes
+            .entry(stem[0..depth].to_vec())
+            .or_insert_with(|| self.state());
+        if let Self::Internal { children, .. } = self {
+            if let Some(child) = children.get(&stem[depth]) {
+                child.record_path(stem, depth + 1, nodes);
+            }
+        }
+    }
+
+    /// Sets the node at `path` below this node at `depth` back to its recorded `state`. Does nothing if there is no
+    /// such node, or it is not of the recorded type. The record of the node in the store is written again.
+    pub(crate) fn restore_path(&mut self, path: &[K], depth: usize, state: NodeState<K, VC, T>) {
+        if depth < path.len() {
+            if let Self::Internal { children, .. } = self {
+                if let Some(child) = children.get_mut(&path[depth]) {
+                    Arc::make_mut(child).restore_path(path, depth + 1, state);
+                }
+            }
+            return;
+        }
+
+        match (self, state) {
+            (
+                Self::Internal {
+                    commit,
+                    changed,
+                    persisted,
+                    ..
+                },
+                NodeState::Internal {
+                    commit: old_commit,
+                    changed: old_changed,
+                },
+            ) => {
+                *commit = old_commit;
+                *changed = old_changed;
+                *persisted = false;
+            }
+            (
+                Self::Extension {
+                    commit,
+                    c1,
+                    c2,
+                    changed,
+                    persisted,
+                    ..
+                },
+                NodeState::Extension {
+                    commit: old_commit,
+                    c1: old_c1,
+                    c2: old_c2,
+                    changed: old_changed,
+                },
+            ) => {
+                *commit = old_commit;
+                *c1 = old_c1;
+                *c2 = old_c2;
+                *changed = old_changed;
+                *persisted = false;
+            }
+            _ => {}
+        }
+    }
+}
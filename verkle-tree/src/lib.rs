@@ -14,17 +14,60 @@
 
 use ark_ec::Group;
 use ark_poly::EvaluationDomain;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 use bytemuck::{bytes_of, Pod};
 use num::{One, Zero};
-use std::{fmt::Debug, hash::Hash, marker::PhantomData};
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    hash::Hash,
+    io::{Read, Write},
+    marker::PhantomData,
+    sync::Arc,
+};
 
 use vector_commit::{
     multiproof::{VCCommitmentMultiProof, VectorCommitmentMultiproof},
-    HasPrecompute, VCData, VectorCommitment,
+    transcript::Transcript,
+    HasPrecompute, VCCommitment, VCData, VCProofWithEvaluation, VCUniversalParams,
+    VectorCommitment,
 };
 
+mod cache;
+pub use cache::CacheStats;
+use cache::{Eviction, NodeCache};
+
+mod encoding;
+pub use encoding::ExtensionEncoding;
+
+mod journal;
+pub use journal::CheckpointId;
+use journal::Journal;
+
 mod node;
-use node::{Node, VerkleError};
+pub use node::VerkleError;
+use node::{leaf_indexes, suffix_half, Node, NodePath};
+
+mod partial;
+pub use partial::PartialVerkleTree;
+
+mod proof;
+pub use proof::{ExtensionStatus, VerkleProof};
+use proof::{ProverOpenings, VectorId, VerifierOpenings};
+
+mod snapshot;
+use snapshot::SnapshotHeader;
+
+mod store;
+use store::path_bytes;
+pub use store::{FileStore, MemoryStore, NodeStore};
+
+pub mod tree_key;
+
+mod versions;
+pub use versions::TreeVersions;
+
+pub mod witness;
 
 /// KeyMethods defines methods that a key must implement
 trait KeyMethods<const N: usize, UnitType> {
@@ -32,7 +75,8 @@ trait KeyMethods<const N: usize, UnitType> {
     /// searching, and caller SHOULD assume that the keys are the same up to `cur_depth`
     fn next_diff_depth(&self, other: &Self, cur_depth: usize) -> usize;
 
-    /// Splits a key into its stem and final unit
+    /// Splits a key into its stem and final unit. The final unit of the returned stem is zeroed so that all
+    /// keys sharing a stem return an equal stem.
     fn split(self) -> ([UnitType; N], UnitType);
 
     /// Convert this key to a byte array
@@ -62,7 +106,8 @@ impl<const N: usize, UnitType: Zero + Copy + PartialEq + Pod> KeyMethods<N, Unit
     fn split(self) -> ([UnitType; N], UnitType) {
         // TODO: Not too efficient
         let unit = self[N - 1];
-        let stem = self;
+        let mut stem = self;
+        stem[N - 1] = UnitType::zero();
 
         (stem, unit)
     }
@@ -83,72 +128,920 @@ pub trait SplittableValue {
     fn split(&self) -> (Self::Output, Self::Output);
 }
 
+/// The width of a tree when none is given, as used by Ethereum
+pub const DEFAULT_WIDTH: usize = 256;
+
+/// Checks that every unit of `key` indexes a child of a node `width` wide
+fn check_key_units<const N: usize, K: Copy + Into<usize>>(
+    key: &Key<N, K>,
+    width: usize,
+) -> Result<(), VerkleError> {
+    match key.iter().map(|&u| u.into()).find(|&u| u >= width) {
+        Some(unit) => Err(VerkleError::UnitOutOfRange { unit, width }),
+        None => Ok(()),
+    }
+}
+
 /// A Verkle Tree implements convience functions that operate on a single root `Node`.
-pub struct VerkleTree<const N: usize, K, VC, T, G, Domain>
+///
+/// Every node of the tree is `width` wide, which must equal the `max_size` of the parameters that the tree is
+/// committed with. Each unit of a key indexes a child, so units must be less than the width.
+///
+/// The nodes of the tree are persisted to its `NodeStore` by `flush`, after which `unload` drops the subtrees that
+/// are up to date in the store from memory. Unloaded subtrees are loaded again when a key below them is accessed.
+/// With a node cache (see `set_cache_capacity`), cold extension nodes are also evicted to the store as the tree
+/// is accessed.
+///
+/// Clones of a tree share the nodes held in memory and the records of their store, which are copied on write. See
+/// `TreeVersions` to keep several versions of a tree.
+///
+/// Changes can be made tentatively by opening a `checkpoint`, and undone with `revert_to` (see the `journal`
+/// module). Checkpoints nest, and the changes since one are kept with `commit_checkpoint`.
+///
+/// Rarely accessed subtrees can be dropped with `prune`, keeping only their commitment. Keys below a pruned
+/// subtree cannot be accessed or proven, while the commitments of its ancestors are still generated.
+pub struct VerkleTree<const N: usize, K, VC, T, G, Domain, S = MemoryStore>
 where
     K: Eq + Hash,
     VC: VectorCommitment,
     T: SplittableValue<Output = <VC::Data as VCData>::Item> + Zero + Clone + PartialEq + Debug,
 {
-    root: Node<N, K, VC, T>,
+    root: Arc<Node<N, K, VC, T>>,
+    width: usize,
+    encoding: ExtensionEncoding,
+    store: S,
+    /// The paths of the nodes removed since the last flush, whose records are deleted from the store
+    removed: Arc<Vec<Vec<K>>>,
+    cache: Option<NodeCache<N, K>>,
+    journal: Arc<Journal<N, K, VC, T>>,
     _g: PhantomData<G>,
     _domain: PhantomData<Domain>,
 }
 
-/// Operations in this implementation block include all functionality outside of proving
+/// This implementation block creates trees held in memory
 impl<const N: usize, K, VC, T, G, Domain> VerkleTree<N, K, VC, T, G, Domain>
 where
-    K: Eq + Hash + Into<usize> + Zero + Copy + Pod + Into<usize>,
+    K: Eq + Hash + Into<usize> + Zero + Copy + Pod,
     VC: VectorCommitment,
+    VC::Commitment: CanonicalSerialize + CanonicalDeserialize,
     <VC::Data as VCData>::Item: Copy + One,
-    T: SplittableValue<Output = <VC::Data as VCData>::Item> + Zero + Clone + PartialEq + Debug,
+    T: SplittableValue<Output = <VC::Data as VCData>::Item>
+        + Zero
+        + Clone
+        + PartialEq
+        + Debug
+        + CanonicalSerialize
+        + CanonicalDeserialize,
 {
+    /// Creates an empty tree of `DEFAULT_WIDTH`
     pub fn new() -> Self {
-        Self {
-            root: Node::new_internal(vec![]),
+        Self::with_width(DEFAULT_WIDTH).expect("Key unit type cannot index the default width")
+    }
+
+    /// Creates an empty tree with nodes `width` wide, using the generic extension encoding
+    pub fn with_width(width: usize) -> Result<Self, VerkleError> {
+        Self::with_encoding(width, ExtensionEncoding::Generic)
+    }
+
+    /// Creates an empty tree with nodes `width` wide, whose extensions commit to their stem and leaves with
+    /// `encoding`. The width must be even (each half of an extension's leaves is committed separately), hold at
+    /// least the 4 items of an extension, and not be larger than the number of values of the key unit type `K`.
+    pub fn with_encoding(width: usize, encoding: ExtensionEncoding) -> Result<Self, VerkleError> {
+        Self::with_store(width, encoding, MemoryStore::default())
+    }
+}
+
+/// Operations in this implementation block include all functionality outside of proving
+impl<const N: usize, K, VC, T, G, Domain, S> VerkleTree<N, K, VC, T, G, Domain, S>
+where
+    K: Eq + Hash + Into<usize> + Zero + Copy + Pod + Into<usize>,
+    VC: VectorCommitment,
+    VC::Commitment: CanonicalSerialize + CanonicalDeserialize,
+    <VC::Data as VCData>::Item: Copy + One,
+    T: SplittableValue<Output = <VC::Data as VCData>::Item>
+        + Zero
+        + Clone
+        + PartialEq
+        + Debug
+        + CanonicalSerialize
+        + CanonicalDeserialize,
+    S: NodeStore,
+{
+    /// Opens the tree held by `store`, or creates an empty tree if the store is empty. Only the root is loaded,
+    /// along with any nodes that were stored before their commitment was generated.
+    ///
+    /// Errors if the store holds a tree of a different width or encoding. See `with_encoding` for the
+    /// requirements of the width.
+    pub fn with_store(
+        width: usize,
+        encoding: ExtensionEncoding,
+        store: S,
+    ) -> Result<Self, VerkleError> {
+        let unit_bits = std::mem::size_of::<K>() * 8;
+        let unit_fits = unit_bits >= usize::BITS as usize || width <= 1 << unit_bits;
+        if width < 4 || !width.is_multiple_of(2) || !unit_fits {
+            return Err(VerkleError::InvalidWidth(width));
+        }
+
+        let root = Node::load_root(&store, width, |reader| {
+            SnapshotHeader::read::<N, K, _>(reader)
+        })?;
+        let root = match root {
+            Some((header, _)) if header.width != width || header.encoding != encoding => {
+                return Err(VerkleError::StoreError(format!(
+                    "The store holds a tree of width {} with the {:?} encoding",
+                    header.width, header.encoding
+                )))
+            }
+            Some((_, root)) => root,
+            None => Node::new_internal(vec![]),
+        };
+
+        Ok(Self {
+            root: Arc::new(root),
+            width,
+            encoding,
+            store,
+            removed: Arc::default(),
+            cache: None,
+            journal: Arc::new(Journal::new()),
             _g: PhantomData,
             _domain: PhantomData,
+        })
+    }
+
+    /// The store the nodes of the tree are persisted to
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Writes every node that changed since the last flush to the store, and deletes the nodes removed since.
+    /// Nodes are written with their cached commitments, so flushing after generating the commitment of the tree
+    /// allows `unload` to drop them from memory.
+    pub fn flush(&mut self) -> Result<(), VerkleError> {
+        self.delete_removed()?;
+
+        let mut header = vec![];
+        SnapshotHeader {
+            width: self.width,
+            encoding: self.encoding,
+            commitments: true,
+        }
+        .write::<N, K, _>(&mut header)?;
+        if !self.root.is_persisted() {
+            Arc::make_mut(&mut self.root).flush(&mut vec![], &mut self.store, header)?;
         }
+        self.store.sync()
     }
 
-    pub fn insert_single(&mut self, key: Key<N, K>, value: T) {
+    /// Flushes the tree, then drops every subtree below the root that is stored with its commitment from memory.
+    /// Subtrees without a commitment (i.e. that changed since the commitment of the tree was generated) stay loaded.
+    ///
+    /// Errors if a checkpoint is open, as reverting to it needs the nodes that changed since in memory.
+    pub fn unload(&mut self) -> Result<(), VerkleError> {
+        if !self.journal.is_empty() {
+            return Err(VerkleError::StoreError(
+                "Nodes cannot be unloaded while a checkpoint is open".to_string(),
+            ));
+        }
+        self.flush()?;
+        Arc::make_mut(&mut self.root).unload_children();
+        if let Some(cache) = &mut self.cache {
+            for stem in cache.stems() {
+                if !matches!(self.root.get_stem(&stem, 0), Ok(Some(_))) {
+                    cache.forget(&stem);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Prunes the subtree at `path` (the units of the keys below it) from memory, leaving a stub of its commitment
+    /// (and its stem, if it is an extension node) so the commitment of the tree can still be generated. Unlike an
+    /// unloaded subtree, a pruned one is not loaded again: accessing a key below it errors with `PrunedPath`, so
+    /// it can be fetched from elsewhere (e.g. a peer). The stub replaces the record of the subtree in the store
+    /// once flushed.
+    ///
+    /// Errors if there is no node at `path`, the subtree changed since the commitment of the tree was generated,
+    /// or a checkpoint is open.
+    pub fn prune(&mut self, path: &[K]) -> Result<(), VerkleError> {
+        if !self.journal.is_empty() {
+            return Err(VerkleError::PruneError(
+                "Nodes cannot be pruned while a checkpoint is open".to_string(),
+            ));
+        }
+        if path.is_empty() || path.len() >= N {
+            return Err(VerkleError::InvalidPath);
+        }
+        // Loading the path to any stem below the subtree loads the subtree's root
+        let mut stem = [K::zero(); N];
+        stem[..path.len()].copy_from_slice(path);
+        check_key_units(&stem, self.width)?;
+        self.load_stem(&stem, false)?;
+
+        let pruned = Arc::make_mut(&mut self.root).prune(path, 0)?;
+        if let Some(cache) = &mut self.cache {
+            let mut stems = vec![];
+            pruned.collect_stems(&mut stems);
+            stems.iter().for_each(|stem| cache.forget(stem));
+        }
+        Ok(())
+    }
+
+    /// Bounds the number of extension nodes kept in memory to `capacity`, evicting the least recently accessed
+    /// ones to the store (see the `cache` module), or removes the bound if `None`. Extension nodes already in memory
+    /// are evicted right away if there are too many.
+    ///
+    /// Errors if `capacity` is zero, as every access needs an extension node in memory.
+    pub fn set_cache_capacity(&mut self, capacity: Option<usize>) -> Result<(), VerkleError> {
+        let capacity = match capacity {
+            Some(0) => {
+                return Err(VerkleError::StoreError(
+                    "The node cache must hold at least one extension node".to_string(),
+                ))
+            }
+            Some(c) => c,
+            None => {
+                self.cache = None;
+                return Ok(());
+            }
+        };
+
+        match &mut self.cache {
+            Some(cache) => cache.set_capacity(capacity),
+            None => {
+                let mut cache = NodeCache::new(capacity);
+                let mut stems = vec![];
+                self.root.collect_stems(&mut stems);
+                stems.iter().for_each(|stem| cache.touch(stem));
+                self.cache = Some(cache);
+            }
+        }
+        self.evict_cold()
+    }
+
+    /// The counters of the node cache, if the tree has one
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(|c| c.stats)
+    }
+
+    /// Loads the nodes on the path to `stem` from the store, counting the access in the cache. See
+    /// `Node::load_path` for `siblings`
+    fn load_stem(&mut self, stem: &Key<N, K>, siblings: bool) -> Result<(), VerkleError> {
+        let mut loaded = vec![];
+        if !self.root.is_path_loaded(stem, 0, siblings) {
+            Arc::make_mut(&mut self.root).load_path(
+                stem,
+                0,
+                &self.store,
+                self.width,
+                siblings,
+                &mut loaded,
+            )?;
+        }
+        if let Some(cache) = &mut self.cache {
+            match loaded.is_empty() {
+                true => cache.stats.hits += 1,
+                false => cache.stats.misses += 1,
+            }
+            loaded.iter().for_each(|stem| cache.touch(stem));
+        }
+        Ok(())
+    }
+
+    /// Marks the extension node of `stem` as the most recently accessed in the cache, or forgets it if the tree
+    /// no longer has one
+    fn touch_stem(&mut self, stem: &Key<N, K>) {
+        if let Some(cache) = &mut self.cache {
+            match self.root.get_stem(stem, 0) {
+                Ok(Some(_)) => cache.touch(stem),
+                _ => cache.forget(stem),
+            }
+        }
+    }
+
+    /// Evicts the least recently accessed extension nodes until the cache is within its capacity. Nothing is evicted
+    /// while a checkpoint is open, as reverting to it needs the nodes that changed since in memory.
+    fn evict_cold(&mut self) -> Result<(), VerkleError> {
+        if !self.journal.is_empty() || !self.cache.as_ref().is_some_and(|c| c.is_over_capacity()) {
+            return Ok(());
+        }
+
+        // An evicted node may be written to the path of a removed one, which must not be deleted afterwards
+        self.delete_removed()?;
+        let cache = self.cache.as_mut().unwrap();
+        while cache.is_over_capacity() {
+            let stem = match cache.coldest() {
+                Some(stem) => stem,
+                None => break,
+            };
+            match Arc::make_mut(&mut self.root).evict_stem(&stem, 0, &mut self.store)? {
+                Eviction::Evicted => {
+                    cache.forget(&stem);
+                    cache.stats.evictions += 1;
+                }
+                Eviction::Missing => cache.forget(&stem),
+                Eviction::Pinned => cache.pin(&stem),
+            }
+        }
+        Ok(())
+    }
+
+    /// Deletes the records of the nodes removed since the last flush from the store
+    fn delete_removed(&mut self) -> Result<(), VerkleError> {
+        for path in std::mem::take(&mut self.removed).iter() {
+            self.store.delete(&path_bytes(path))?;
+        }
+        Ok(())
+    }
+
+    /// The number of children of an internal node, and leaves of an extension node
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The encoding of the stem and leaves of extension nodes
+    pub fn encoding(&self) -> ExtensionEncoding {
+        self.encoding
+    }
+
+    /// Errors if the commitment parameters `crs` do not commit to vectors of the tree's width
+    fn check_width(&self, crs: &VC::UniversalParams) -> Result<(), VerkleError> {
+        if crs.max_size() != self.width {
+            return Err(VerkleError::WidthMismatch {
+                width: self.width,
+                max_size: crs.max_size(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Inserts `value` at `key`, overwriting any previous value. Errors if a unit of the key is outside of the
+    /// tree's width, or the key is below a pruned subtree.
+    pub fn insert_single(&mut self, key: Key<N, K>, value: T) -> Result<(), VerkleError> {
+        check_key_units(&key, self.width)?;
+        let (stem, unit) = key.split();
+        self.load_stem(&stem, false)?;
+        self.root.get_stem(&stem, 0)?;
+        self.record_changes(&stem, [unit]);
+        Arc::make_mut(&mut self.root).insert(stem, vec![(unit, value)], 0);
+        self.touch_stem(&stem);
+        self.evict_cold()
+    }
+
+    /// Removes the value at `key` from the tree, returning it if it was set. Errors if a unit of the key is outside
+    /// of the tree's width, or the key is below a pruned subtree.
+    pub fn remove_single(&mut self, key: &Key<N, K>) -> Result<Option<T>, VerkleError> {
+        check_key_units(key, self.width)?;
         let (stem, unit) = key.split();
-        self.root.insert(stem, vec![(unit, value)], 0);
+        self.load_stem(&stem, true)?;
+        self.root.get_stem(&stem, 0)?;
+        self.record_changes(&stem, [unit]);
+        let removed =
+            Arc::make_mut(&mut self.root).remove(&stem, unit, 0, Arc::make_mut(&mut self.removed));
+        self.touch_stem(&stem);
+        self.evict_cold()?;
+        Ok(removed)
     }
 
-    pub fn get_single(&self, key: &Key<N, K>) -> Option<&T> {
+    /// Gets the value at `key`, loading the path to it from the store if required. Errors with `UnitOutOfRange` if a
+    /// unit of the key is outside of the tree's width, and with `PrunedPath` if the key is below a pruned subtree,
+    /// rather than returning `None` for a value that may be set.
+    pub fn get_single(&mut self, key: &Key<N, K>) -> Result<Option<&T>, VerkleError> {
+        check_key_units(key, self.width)?;
         let (stem, unit) = key.split();
-        match self.root.get_stem(&stem, 0) {
+        self.load_stem(&stem, false)?;
+        self.touch_stem(&stem);
+        self.evict_cold()?;
+        Ok(match self.root.get_stem(&stem, 0)? {
             Some(stem) => stem.get_value(unit),
             None => None,
+        })
+    }
+
+    /// Generates the commitment of the root. Only subtrees that changed since the last call are committed to
+    /// again, in parallel.
+    ///
+    /// Errors if `crs` does not commit to vectors of the tree's width.
+    pub fn commitment(&mut self, crs: &VC::UniversalParams) -> Result<VC::Commitment, VerkleError>
+    where
+        K: Send + Sync,
+        T: Send + Sync,
+        VC::Commitment: Send + Sync,
+        VC::UniversalParams: Sync,
+        VC::Error: Send,
+        <VC::Data as VCData>::Item: Send + Sync,
+    {
+        self.check_width(crs)?;
+        // A root without changes is not copied if it is shared with another version
+        if let Some(commit) = self.root.clean_commitment() {
+            return Ok(commit.clone());
+        }
+        let commit = Arc::make_mut(&mut self.root)
+            .gen_commitment(crs, self.encoding, false)?
+            .clone();
+        // Extension nodes changed since the last commitment can only be evicted once they have one
+        if let Some(cache) = &mut self.cache {
+            cache.unpin_all();
         }
+        self.evict_cold()?;
+        Ok(commit)
     }
 
-    pub fn commitment(&mut self, crs: &VC::UniversalParams) -> Result<VC::Commitment, VC::Error> {
-        self.root.gen_commitment(crs).map(|c| c.clone())
+    /// Checks that every cached commitment in the tree commits to the children or leaves of its node, e.g. after
+    /// restoring the tree from an untrusted snapshot. Changed nodes are committed to again, so are not checked,
+    /// and neither are unloaded subtrees.
+    pub fn check_commitments(&self, crs: &VC::UniversalParams) -> Result<bool, VerkleError> {
+        self.check_width(crs)?;
+        self.root
+            .check_commitments(crs, self.encoding)
+            .map_err(|e| VerkleError::CommitmentError(e.to_string()))
     }
 
-    fn path_to_stem<'a>(
-        &'a self,
-        stem: &Key<N, K>,
-    ) -> Result<Vec<(Vec<K>, K, &'a Node<N, K, VC, T>)>, VerkleError> {
+    /// Drops every cached commitment in the loaded nodes of the tree, so the next call to `commitment` commits to
+    /// them again. Unloaded subtrees keep the commitments they were stored with.
+    pub fn clear_commitments(&mut self) {
+        Arc::make_mut(&mut self.root).clear_commitments();
+        Arc::make_mut(&mut self.journal).clear_commitments();
+    }
+
+    /// Opens a checkpoint, which the tree can be reverted to until it is committed. Checkpoints opened after this
+    /// one are nested in it.
+    pub fn checkpoint(&mut self) -> CheckpointId {
+        Arc::make_mut(&mut self.journal).open()
+    }
+
+    /// Undoes every change since checkpoint `id` was opened, restoring the commitments the tree had cached then,
+    /// and closes it along with every checkpoint nested in it.
+    ///
+    /// Errors if the checkpoint is not open.
+    pub fn revert_to(&mut self, id: CheckpointId) -> Result<(), VerkleError> {
+        let closed = Arc::make_mut(&mut self.journal)
+            .close(id)
+            .ok_or(VerkleError::UnknownCheckpoint(id))?;
+        for checkpoint in closed {
+            for ((stem, unit), value) in checkpoint.leaves {
+                self.load_stem(&stem, value.is_none())?;
+                match value {
+                    Some(value) => {
+                        Arc::make_mut(&mut self.root).insert(stem, vec![(unit, value)], 0)
+                    }
+                    None => {
+                        Arc::make_mut(&mut self.root).remove(
+                            &stem,
+                            unit,
+                            0,
+                            Arc::make_mut(&mut self.removed),
+                        );
+                    }
+                }
+                self.touch_stem(&stem);
+            }
+            // Every node is back in place, so only the commitments cached before the changes are left to restore
+            for (path, state) in checkpoint.nodes {
+                Arc::make_mut(&mut self.root).restore_path(&path, 0, state);
+            }
+        }
+        self.evict_cold()
+    }
+
+    /// Keeps the changes since checkpoint `id` was opened, and closes it along with every checkpoint nested in it.
+    /// The changes can still be undone by reverting to a checkpoint it is nested in.
+    ///
+    /// Errors if the checkpoint is not open.
+    pub fn commit_checkpoint(&mut self, id: CheckpointId) -> Result<(), VerkleError> {
+        let journal = Arc::make_mut(&mut self.journal);
+        let closed = journal
+            .close(id)
+            .ok_or(VerkleError::UnknownCheckpoint(id))?;
+        journal.merge(closed);
+        self.evict_cold()
+    }
+
+    /// Records the values of `units` of `stem`, and the state of the nodes on the path to it, in the newest
+    /// checkpoint before they change
+    fn record_changes(&mut self, stem: &Key<N, K>, units: impl IntoIterator<Item = K>) {
+        // The journal of a clone is only copied once a change is recorded in it
+        if self.journal.is_empty() {
+            return;
+        }
+        let checkpoint = match Arc::make_mut(&mut self.journal).newest() {
+            Some(c) => c,
+            None => return,
+        };
+        self.root.record_path(stem, 0, &mut checkpoint.nodes);
+        let extension = self.root.get_stem(stem, 0).ok().flatten();
+        for unit in units {
+            checkpoint
+                .leaves
+                .entry((*stem, unit))
+                .or_insert_with(|| extension.and_then(|e| e.get_value(unit)).cloned());
+        }
+    }
+
+    fn path_to_stem(&self, stem: &Key<N, K>) -> Result<NodePath<'_, N, K, VC, T>, VerkleError> {
         let mut res = vec![];
         self.root.path_to_stem(stem, &mut res).map(move |_| res)
     }
 }
 
+/// This implementation block implements persisting the tree to snapshots
+impl<const N: usize, K, VC, T, G, Domain, S> VerkleTree<N, K, VC, T, G, Domain, S>
+where
+    K: Eq + Hash + Into<usize> + Zero + Copy + Pod,
+    VC: VectorCommitment,
+    VC::Commitment: CanonicalSerialize + CanonicalDeserialize,
+    <VC::Data as VCData>::Item: Copy + One,
+    T: SplittableValue<Output = <VC::Data as VCData>::Item>
+        + Zero
+        + Clone
+        + PartialEq
+        + Debug
+        + CanonicalSerialize
+        + CanonicalDeserialize,
+    S: NodeStore,
+{
+    /// Writes the tree to `writer` in the snapshot format (see the `snapshot` module). When `commitments` is set,
+    /// the cached commitments of all nodes without changes are included, so they need not be generated again.
+    /// Unloaded subtrees are read from the store one at a time, without being kept in memory.
+    pub fn write_snapshot<W: Write>(
+        &self,
+        mut writer: W,
+        commitments: bool,
+    ) -> Result<(), VerkleError> {
+        let header = SnapshotHeader {
+            width: self.width,
+            encoding: self.encoding,
+            commitments,
+        };
+        header.write::<N, K, _>(&mut writer)?;
+        let load = |path: &[K]| Node::load(&self.store, &mut path.to_vec(), self.width);
+        self.root
+            .write_snapshot(&mut writer, commitments, &mut vec![], &load)
+    }
+
+    /// Restores a tree written by `write_snapshot` into a `store` that holds no tree. Any cached commitments in the snapshot
+    /// are restored as they are: use `check_commitments` to validate them, or `clear_commitments` to generate them
+    /// again. The restored tree is held in memory until it is flushed.
+    pub fn read_snapshot<R: Read>(mut reader: R, store: S) -> Result<Self, VerkleError> {
+        if store.get(&[])?.is_some() {
+            return Err(VerkleError::StoreError(
+                "The store already holds a tree".to_string(),
+            ));
+        }
+        let header = SnapshotHeader::read::<N, K, _>(&mut reader)?;
+        let mut tree = Self::with_store(header.width, header.encoding, store)?;
+        tree.root = Arc::new(Node::read_snapshot(
+            &mut reader,
+            &mut vec![],
+            header.width,
+            header.commitments,
+        )?);
+        if !matches!(tree.root.as_ref(), Node::Internal { .. }) {
+            return Err(VerkleError::InvalidSnapshot(
+                "The root is not an internal node".to_string(),
+            ));
+        }
+
+        Ok(tree)
+    }
+}
+
+/// This implementation block implements inserting many values at once
+impl<const N: usize, K, VC, T, G, Domain, S> VerkleTree<N, K, VC, T, G, Domain, S>
+where
+    K: Eq + Hash + Ord + Into<usize> + Zero + Copy + Pod + Send + Sync,
+    VC: VectorCommitment,
+    VC::Commitment: CanonicalSerialize + CanonicalDeserialize + Send + Sync,
+    <VC::Data as VCData>::Item: Copy + One + Send + Sync,
+    T: SplittableValue<Output = <VC::Data as VCData>::Item>
+        + Zero
+        + Clone
+        + PartialEq
+        + Debug
+        + CanonicalSerialize
+        + CanonicalDeserialize
+        + Send
+        + Sync,
+    S: NodeStore,
+{
+    /// Inserts all `entries` into the tree. Entries are grouped by their stem and sorted, so that every extension
+    /// node is only traversed to once, and the stems under each child of the root are inserted in parallel.
+    ///
+    /// If a key appears more than once, its last value is kept. Nothing is inserted if a unit of any key is outside
+    /// of the tree's width, or any key is below a pruned subtree.
+    pub fn insert_batch(
+        &mut self,
+        entries: impl IntoIterator<Item = (Key<N, K>, T)>,
+    ) -> Result<(), VerkleError> {
+        let mut stems: BTreeMap<Key<N, K>, Vec<(K, T)>> = BTreeMap::new();
+        for (key, value) in entries {
+            check_key_units(&key, self.width)?;
+            let (stem, unit) = key.split();
+            stems.entry(stem).or_default().push((unit, value));
+        }
+        for (stem, values) in stems.iter() {
+            self.load_stem(stem, false)?;
+            self.root.get_stem(stem, 0)?;
+            self.record_changes(stem, values.iter().map(|(unit, _)| *unit));
+        }
+
+        let stems: Vec<_> = stems.into_iter().collect();
+        let keys: Vec<Key<N, K>> = stems.iter().map(|(stem, _)| *stem).collect();
+        Arc::make_mut(&mut self.root).insert_sorted_stems(stems);
+        keys.iter().for_each(|stem| self.touch_stem(stem));
+        self.evict_cold()
+    }
+}
+
 /// This implementation block implements the proving functionality for the verkle tree
-impl<const N: usize, K, VC, T, G, Domain> VerkleTree<N, K, VC, T, G, Domain>
+impl<const N: usize, K, VC, T, G, Domain, S> VerkleTree<N, K, VC, T, G, Domain, S>
 where
-    K: Eq + Hash + Into<usize> + Zero + Copy + Pod + Into<usize>,
+    K: Eq + Hash + Into<usize> + Zero + Copy + Pod + Into<usize> + Send + Sync,
     G: Group,
     Domain: EvaluationDomain<G::ScalarField> + Sync + Send,
     VC: VectorCommitmentMultiproof<G, Domain>,
-    <VC::Data as VCData>::Item: Copy + One,
-    VC::Commitment: VCCommitmentMultiProof<G::ScalarField>,
+    VC::Error: Send,
+    <VC::Data as VCData>::Item: Copy + One + Send + Sync,
+    VC::Commitment: VCCommitmentMultiProof<G::ScalarField>
+        + CanonicalSerialize
+        + CanonicalDeserialize
+        + Send
+        + Sync,
     VC::UniversalParams: HasPrecompute<G::ScalarField> + Sync,
+    T: SplittableValue<Output = <VC::Data as VCData>::Item>
+        + Zero
+        + Clone
+        + PartialEq
+        + Debug
+        + CanonicalSerialize
+        + CanonicalDeserialize
+        + Send
+        + Sync,
+    S: NodeStore,
+{
+    /// Generates a proof of the values of all `keys` in the tree, which proves absence for keys that are not stored.
+    /// Every opening is aggregated into a single multiproof:
+    /// - Each internal node on a key's path opens the commitment of its child at the key's unit for that depth.
+    ///   If the stem's path ends at an empty slot, the last internal node opens that slot to zero.
+    /// - An extension node found at the end of the path opens its `[1, stem, c1, c2]` vector at the marker and the stem.
+    ///   If the extension has a different stem, this proves that the key's stem is absent.
+    /// - Otherwise, the extension additionally opens the suffix commitment holding the leaf, which in turn opens
+    ///   the lower and upper items of the leaf (both zero when the leaf is not set).
+    ///
+    /// Each vector is opened once at each point, however many keys share it. The openings are ordered by the path
+    /// of the opened vector and then by point, and the multiproof continues a transcript labelled "vt", as in the
+    /// Ethereum verkle spec.
+    pub fn prove_keys(
+        &mut self,
+        crs: &VC::UniversalParams,
+        keys: &[Key<N, K>],
+    ) -> Result<VerkleProof<N, K, VC>, VerkleError> {
+        for key in keys {
+            check_key_units(key, self.width)?;
+            self.load_stem(&key.split().0, false)?;
+        }
+        self.commitment(crs)?;
+
+        let width = crs.max_size();
+        let mut openings = ProverOpenings::new();
+        let mut key_paths = Vec::with_capacity(keys.len());
+
+        for key in keys {
+            let (stem, unit) = key.split();
+            let path = self.path_to_stem(&stem)?;
+            let depth = path.len();
+            let extension = path[depth - 1].2.get_child(stem[depth - 1]);
+            let mut key_commitments = Vec::with_capacity(depth + 1);
+
+            for (d, (_, index, node)) in path.iter().enumerate() {
+                let child = path.get(d + 1).map(|p| p.2).or(extension);
+                let child_commit = child.map(|c| c.commitment().unwrap());
+                openings.open(
+                    VectorId::Node(stem[0..d].to_vec()),
+                    node.commitment().unwrap(),
+                    || VC::Data::from_vec(node.commitment_values(width, self.encoding)),
+                    (*index).into(),
+                    child_commit.map_or(G::ScalarField::zero(), |c| c.to_data_item()),
+                );
+                key_commitments.extend(child_commit);
+            }
+
+            let extension = match extension {
+                Some(e) => e,
+                None => {
+                    key_paths.push((ExtensionStatus::Absent, key_commitments));
+                    continue;
+                }
+            };
+
+            let extension_path = stem[0..depth].to_vec();
+            let extension_commit = extension.commitment().unwrap();
+            let extension_stem = extension.get_extension_stem();
+            let mut extension_openings = vec![
+                (0, G::ScalarField::one()),
+                (1, self.encoding.stem_item::<VC::Data, N, K>(extension_stem)),
+            ];
+
+            let half = suffix_half(unit.into(), width);
+            let suffix_commit = extension.suffix_commitment(half).unwrap();
+            if extension_stem == &stem {
+                extension_openings.push((2 + half, suffix_commit.to_data_item()));
+            }
+
+            for (z, y) in extension_openings {
+                openings.open(
+                    VectorId::Node(extension_path.clone()),
+                    extension_commit,
+                    || VC::Data::from_vec(extension.commitment_values(width, self.encoding)),
+                    z,
+                    y,
+                );
+            }
+
+            if extension_stem != &stem {
+                let status = ExtensionStatus::OtherStem(*extension_stem);
+                key_paths.push((status, key_commitments));
+                continue;
+            }
+            key_commitments.push(*suffix_commit);
+
+            let (low, high) = self
+                .encoding
+                .leaf_items::<VC::Data, T>(extension.get_value(unit));
+            let (low_index, high_index) = leaf_indexes(unit.into(), width);
+            for (z, y) in [(low_index, low), (high_index, high)] {
+                openings.open(
+                    VectorId::Suffix(extension_path.clone(), half),
+                    suffix_commit,
+                    || VC::Data::from_vec(extension.suffix_values(half, width, self.encoding)),
+                    z,
+                    y,
+                );
+            }
+
+            key_paths.push((ExtensionStatus::Present, key_commitments));
+        }
+
+        let transcript = VC::Transcript::new("vt");
+        let multiproof = VC::prove_multiproof_with_transcript(crs, &openings.queries(), transcript)
+            .map_err(|e| VerkleError::CommitmentError(e.to_string()))?;
+
+        for key in keys {
+            self.touch_stem(&key.split().0);
+        }
+        self.evict_cold()?;
+
+        VerkleProof::from_key_paths(keys, key_paths, multiproof, width)
+    }
+
+    /// Verifies that `proof` proves all `claims` against the `root` commitment of a tree, without requiring
+    /// the tree itself. The openings made by `prove_keys` are rebuilt from the commitments in the proof, which are
+    /// assigned to the paths of the claimed keys.
+    /// A claim of `None` is a claim that the key is absent from the tree. The `encoding` must be the one the tree
+    /// was created with.
+    ///
+    /// Returns false if the proof's stems and commitments are inconsistent with the claimed keys (e.g. a commitment
+    /// too many or too few, or an extension node that has children), or the multiproof is invalid.
+    pub fn verify_proof(
+        crs: &VC::UniversalParams,
+        root: &VC::Commitment,
+        claims: &[(Key<N, K>, Option<T>)],
+        proof: &VerkleProof<N, K, VC>,
+        encoding: ExtensionEncoding,
+    ) -> Result<bool, VerkleError>
+    where
+        VC::Proof: VCProofWithEvaluation<G::ScalarField>,
+    {
+        let width = crs.max_size();
+        for (key, _) in claims {
+            check_key_units(key, width)?;
+        }
+        let keys: Vec<_> = claims.iter().map(|(key, _)| *key).collect();
+        let key_paths = match proof.key_paths(&keys, width) {
+            Some(paths) => paths,
+            None => return Ok(false),
+        };
+        let mut openings = VerifierOpenings::new();
+
+        for ((key, value), (status, commitments)) in claims.iter().zip(key_paths.iter()) {
+            let (stem, unit) = key.split();
+
+            // The number of internal nodes on the path. A present extension has its own and a suffix commitment
+            // included, while an absent one has no commitment, and the last internal node opens to zero.
+            let depth = match status {
+                ExtensionStatus::Absent => commitments.len() + 1,
+                ExtensionStatus::OtherStem(_) => commitments.len(),
+                ExtensionStatus::Present => commitments.len().wrapping_sub(1),
+            };
+            if depth == 0 || depth >= N {
+                return Ok(false);
+            }
+
+            let mut parent = root;
+            for d in 0..depth {
+                let child = commitments.get(d).copied();
+                openings.internal_at(&stem[0..d]);
+                if !openings.open(
+                    VectorId::Node(stem[0..d].to_vec()),
+                    parent,
+                    stem[d].into(),
+                    child.map_or(G::ScalarField::zero(), |c| c.to_data_item()),
+                ) {
+                    return Ok(false);
+                }
+                parent = child.unwrap_or(parent);
+            }
+
+            let extension_stem = match status {
+                ExtensionStatus::Absent => {
+                    if value.is_some() {
+                        return Ok(false);
+                    }
+                    continue;
+                }
+                ExtensionStatus::OtherStem(other) => {
+                    if value.is_some() || other == &stem || other[0..depth] != stem[0..depth] {
+                        return Ok(false);
+                    }
+                    other
+                }
+                ExtensionStatus::Present => &stem,
+            };
+
+            let extension_path = &stem[0..depth];
+            let half = suffix_half(unit.into(), width);
+            let suffix_commit = commitments.get(depth).copied();
+            let mut extension_openings = vec![
+                (0, G::ScalarField::one()),
+                (1, encoding.stem_item::<VC::Data, N, K>(extension_stem)),
+            ];
+            if let Some(c) = suffix_commit {
+                extension_openings.push((2 + half, c.to_data_item()));
+            }
+
+            openings.extension_at(extension_path);
+            for (z, y) in extension_openings {
+                if !openings.open(VectorId::Node(extension_path.to_vec()), parent, z, y) {
+                    return Ok(false);
+                }
+            }
+
+            let suffix_commit = match suffix_commit {
+                Some(c) => c,
+                None => continue,
+            };
+            let (low, high) = encoding.leaf_items::<VC::Data, T>(value.as_ref());
+            let (low_index, high_index) = leaf_indexes(unit.into(), width);
+            for (z, y) in [(low_index, low), (high_index, high)] {
+                if !openings.open(
+                    VectorId::Suffix(extension_path.to_vec(), half),
+                    suffix_commit,
+                    z,
+                    y,
+                ) {
+                    return Ok(false);
+                }
+            }
+        }
+
+        if !openings.is_consistent() {
+            return Ok(false);
+        }
+
+        let transcript = VC::Transcript::new("vt");
+        VC::verify_multiproof_with_transcript(
+            crs,
+            &openings.queries(),
+            &proof.multiproof,
+            transcript,
+        )
+        .map_err(|e| VerkleError::CommitmentError(e.to_string()))
+    }
+}
+
+/// Cloning a tree is O(1). The clone shares every node held in memory with the original, along with the records of
+/// its store, the recency of its node cache and its open checkpoints, until either of them changes them.
+impl<const N: usize, K, VC, T, G, Domain, S> Clone for VerkleTree<N, K, VC, T, G, Domain, S>
+where
+    K: Eq + Hash + Copy,
+    VC: VectorCommitment,
     T: SplittableValue<Output = <VC::Data as VCData>::Item> + Zero + Clone + PartialEq + Debug,
+    S: Clone,
 {
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+            width: self.width,
+            encoding: self.encoding,
+            store: self.store.clone(),
+            removed: self.removed.clone(),
+            cache: self.cache.clone(),
+            journal: self.journal.clone(),
+            _g: PhantomData,
+            _domain: PhantomData,
+        }
+    }
 }
 
 // TODO: Maybe publish a crate with a macro to allow derive(Default)
@@ -156,8 +1049,15 @@ impl<const N: usize, K, VC, T, G, Domain> Default for VerkleTree<N, K, VC, T, G,
 where
     K: Eq + Hash + Into<usize> + Zero + Copy + Pod + Into<usize>,
     VC: VectorCommitment,
+    VC::Commitment: CanonicalSerialize + CanonicalDeserialize,
     <VC::Data as VCData>::Item: Copy + One,
-    T: SplittableValue<Output = <VC::Data as VCData>::Item> + Zero + Clone + PartialEq + Debug,
+    T: SplittableValue<Output = <VC::Data as VCData>::Item>
+        + Zero
+        + Clone
+        + PartialEq
+        + Debug
+        + CanonicalSerialize
+        + CanonicalDeserialize,
 {
     fn default() -> Self {
         Self::new()
@@ -167,43 +1067,50 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::{HashMap, HashSet};
 
     use ark_ec::pairing::Pairing;
     use ark_ff::{field_hashers::DefaultFieldHasher, PrimeField};
     use ark_poly::GeneralEvaluationDomain;
+    use ark_serialize::{Compress, SerializationError, Valid, Validate};
     use rand::{seq::SliceRandom, Fill, Rng};
     use sha2::Sha256;
+    use snapshot::SNAPSHOT_VERSION;
     use std::ops::Add;
 
     use ark_bn254::Bn254;
     use vector_commit::kzg::{kzg_point_generator::KZGRandomPointGenerator, KZG};
 
     const KEY_LEN: usize = 3;
+    #[allow(non_camel_case_types)]
     type KEY_DATA_TYPE = u8;
+    #[allow(clippy::upper_case_acronyms)]
     type KEYT = Key<KEY_LEN, KEY_DATA_TYPE>;
 
     type F = <Bn254 as Pairing>::ScalarField;
     type G1 = <Bn254 as Pairing>::G1;
     type Hasher = DefaultFieldHasher<Sha256>;
 
+    /// A value of 32 bytes, split into two elements of the field `S`
     #[derive(Debug, Clone, PartialEq)]
-    struct U256([u8; 32]);
+    struct U256<S = F>([u8; 32], PhantomData<S>);
+    #[allow(clippy::upper_case_acronyms)]
     type KZGT = KZG<Bn254, Hasher, GeneralEvaluationDomain<F>>;
     type TestTree = VerkleTree<KEY_LEN, KEY_DATA_TYPE, KZGT, U256, G1, GeneralEvaluationDomain<F>>;
 
-    impl SplittableValue for U256 {
-        type Output = F;
+    impl<S: PrimeField> SplittableValue for U256<S> {
+        type Output = S;
         fn split(&self) -> (Self::Output, Self::Output) {
             (
-                F::from_le_bytes_mod_order(&self.0[0..16]),
-                F::from_le_bytes_mod_order(&self.0[16..32]),
+                S::from_le_bytes_mod_order(&self.0[0..16]),
+                S::from_le_bytes_mod_order(&self.0[16..32]),
             )
         }
     }
 
-    impl Zero for U256 {
+    impl<S: PartialEq> Zero for U256<S> {
         fn zero() -> Self {
-            U256([0; 32])
+            U256([0; 32], PhantomData)
         }
 
         fn is_zero(&self) -> bool {
@@ -217,19 +1124,51 @@ mod tests {
         }
     }
 
-    impl Add<Self> for U256 {
+    impl<S> Add<Self> for U256<S> {
         type Output = Self;
         fn add(self, rhs: Self) -> Self::Output {
             let mut res = [0; 32];
-            for i in 0..32 {
-                res[i] = self.0[i] + rhs.0[i];
+            for (i, r) in res.iter_mut().enumerate() {
+                *r = self.0[i] + rhs.0[i];
             }
 
-            U256(res)
+            U256(res, PhantomData)
+        }
+    }
+
+    impl<S: Send + Sync> Valid for U256<S> {
+        fn check(&self) -> Result<(), SerializationError> {
+            Ok(())
+        }
+    }
+
+    impl<S: Send + Sync> CanonicalSerialize for U256<S> {
+        fn serialize_with_mode<W: ark_serialize::Write>(
+            &self,
+            writer: W,
+            compress: Compress,
+        ) -> Result<(), SerializationError> {
+            self.0.serialize_with_mode(writer, compress)
+        }
+
+        fn serialized_size(&self, compress: Compress) -> usize {
+            self.0.serialized_size(compress)
         }
     }
 
-    impl Fill for U256 {
+    impl<S: PartialEq + Send + Sync> CanonicalDeserialize for U256<S> {
+        fn deserialize_with_mode<R: ark_serialize::Read>(
+            mut reader: R,
+            _compress: Compress,
+            _validate: Validate,
+        ) -> Result<Self, SerializationError> {
+            let mut res = U256::zero();
+            reader.read_exact(&mut res.0)?;
+            Ok(res)
+        }
+    }
+
+    impl<S> Fill for U256<S> {
         fn try_fill<R: Rng + ?Sized>(&mut self, rng: &mut R) -> Result<(), rand::Error> {
             self.0.try_fill(rng)
         }
@@ -244,16 +1183,16 @@ mod tests {
             res[0..p_size].copy_from_slice(p);
         }
 
-        for i in p_size..KEY_LEN {
-            res[i] = rng.gen_range(0..arity);
+        for r in res.iter_mut().skip(p_size) {
+            *r = rng.gen_range(0..arity);
         }
 
         res
     }
 
-    fn random_u256() -> U256 {
+    fn random_u256<S: PartialEq>() -> U256<S> {
         let mut res = U256::zero();
-        res.0.try_fill(&mut rand::thread_rng());
+        res.0.try_fill(&mut rand::thread_rng()).unwrap();
         res
     }
 
@@ -271,37 +1210,73 @@ mod tests {
             .map(|_| {
                 let key = random_key(255, Some(&stem));
 
-                (key.clone(), random_u256())
+                (key, random_u256())
             })
             .collect();
 
         while kvs.len() < num_leaves {
             let key = random_key(255, None);
-            kvs.insert(key.clone(), random_u256());
+            kvs.insert(key, random_u256());
         }
 
         let keys: Vec<&KEYT> = kvs.keys().collect();
         let mut keys2 = keys.clone();
         keys2.shuffle(&mut rng);
 
-        for (k1, k2) in keys.into_iter().zip(keys2.into_iter()) {
+        for (k1, k2) in keys.into_iter().zip(keys2) {
             let v1 = kvs.get(k1).unwrap();
             let v2 = kvs.get(k2).unwrap();
-            tree1.insert_single(*k1, v1.clone());
-            tree2.insert_single(*k2, v2.clone());
+            tree1.insert_single(*k1, v1.clone()).unwrap();
+            tree2.insert_single(*k2, v2.clone()).unwrap();
         }
 
         //assert!(tree1 == tree2);
 
         for k in kvs.keys().collect::<Vec<&KEYT>>() {
-            let get1 = tree1.get_single(k);
-            let get2 = tree2.get_single(k);
+            let get1 = tree1.get_single(k).unwrap();
+            let get2 = tree2.get_single(k).unwrap();
 
             assert!(get1 == get2);
             assert!(*get1.unwrap() == *kvs.get(k).unwrap());
         }
     }
 
+    #[test]
+    fn test_insert_batch() {
+        let point_gen = KZGRandomPointGenerator::<G1>::default();
+        let crs = KZGT::setup(256, &point_gen).unwrap();
+
+        let (stem, _) = random_key(255, None).split();
+        let mut entries: Vec<(KEYT, U256)> = (0..10)
+            .map(|_| (random_key(255, Some(&stem[0..KEY_LEN - 1])), random_u256()))
+            .collect();
+        entries.extend((0..10).map(|_| (random_key(255, Some(&stem[0..1])), random_u256())));
+        entries.extend((0..30).map(|_| (random_key(255, None), random_u256())));
+        // A key written twice keeps its last value
+        entries.push((entries[0].0, random_u256()));
+
+        // Both trees start with an existing (committed) extension that the batch may split
+        let existing = (random_key(255, Some(&stem[0..1])), random_u256());
+        let mut expected = TestTree::new();
+        expected
+            .insert_single(existing.0, existing.1.clone())
+            .unwrap();
+        let mut tree = TestTree::new();
+        tree.insert_batch([existing]).unwrap();
+        let _ = tree.commitment(&crs).unwrap();
+
+        for (key, value) in entries.iter() {
+            expected.insert_single(*key, value.clone()).unwrap();
+        }
+        tree.insert_batch(entries.clone()).unwrap();
+
+        for (key, _) in entries.iter() {
+            assert!(tree.get_single(key).unwrap() == expected.get_single(key).unwrap());
+        }
+        assert!(tree.get_single(&entries[0].0).unwrap() == Some(&entries.last().unwrap().1));
+        assert!(tree.commitment(&crs).unwrap() == expected.commitment(&crs).unwrap());
+    }
+
     #[test]
     fn test_overwrite() {
         let mut tree = TestTree::new();
@@ -310,10 +1285,46 @@ mod tests {
         let val1 = random_u256();
         let val2 = random_u256();
 
-        tree.insert_single(key, val1);
-        tree.insert_single(key, val2.clone());
+        tree.insert_single(key, val1).unwrap();
+        tree.insert_single(key, val2.clone()).unwrap();
 
-        assert!(tree.get_single(&key).unwrap() == &val2);
+        assert!(tree.get_single(&key).unwrap().unwrap() == &val2);
+    }
+
+    #[test]
+    fn test_remove() {
+        let point_gen = KZGRandomPointGenerator::<G1>::default();
+        let crs = KZGT::setup(256, &point_gen).unwrap();
+
+        let key1 = random_key(255, None);
+        let key2 = [key1[0], key1[1].wrapping_add(1), key1[2]];
+        let key3 = [key1[0], key1[1], key1[2].wrapping_add(1)];
+        let val1 = random_u256();
+
+        let mut expected = TestTree::new();
+        expected.insert_single(key1, val1.clone()).unwrap();
+        let expected_commit = expected.commitment(&crs).unwrap();
+
+        let mut tree = TestTree::new();
+        tree.insert_single(key1, val1.clone()).unwrap();
+        let _ = tree.commitment(&crs).unwrap();
+        for key in [key2, key3] {
+            tree.insert_single(key, random_u256()).unwrap();
+        }
+        let _ = tree.commitment(&crs).unwrap();
+
+        assert!(tree.remove_single(&key2).unwrap().is_some());
+        assert!(tree.remove_single(&key2).unwrap().is_none());
+        assert!(tree.get_single(&key2).unwrap().is_none());
+        assert!(tree.remove_single(&key3).unwrap().is_some());
+        assert!(tree.get_single(&key1).unwrap() == Some(&val1));
+
+        // The internal node created for key2 is collapsed, leaving key1's extension as a child of the root
+        assert!(tree.path_to_stem(&key1.split().0).unwrap().len() == 1);
+        assert!(tree.commitment(&crs).unwrap() == expected_commit);
+
+        assert!(tree.remove_single(&key1).unwrap() == Some(val1));
+        assert!(tree.commitment(&crs).unwrap() == TestTree::new().commitment(&crs).unwrap());
     }
 
     #[test]
@@ -324,23 +1335,691 @@ mod tests {
 
         let key = random_key(255, None);
         let val = random_u256();
-        tree.insert_single(key, val);
+        tree.insert_single(key, val).unwrap();
 
         let commit = tree.commitment(&crs);
         println!("{:?}", commit);
     }
 
     #[test]
-    fn test_path_to_stem() {
+    fn test_incremental_commitment() {
+        let point_gen = KZGRandomPointGenerator::<G1>::default();
+        let crs = KZGT::setup(256, &point_gen).unwrap();
+
+        let mut tree = TestTree::new();
+        let mut entries = HashMap::new();
+        for _ in 0..20 {
+            let (key, val) = (random_key(255, None), random_u256());
+            tree.insert_single(key, val.clone()).unwrap();
+            entries.insert(key, val);
+        }
+        let _ = tree.commitment(&crs).unwrap();
+
+        // Overwrite, split extensions, remove leaves and fill most of an extension
+        let keys: Vec<KEYT> = entries.keys().cloned().collect();
+        let mut changes: Vec<(KEYT, U256)> = vec![(keys[0], random_u256())];
+        changes.push((random_key(255, Some(&keys[1][0..1])), random_u256()));
+        changes.push((
+            [keys[2][0], keys[2][1], keys[2][2].wrapping_add(1)],
+            random_u256(),
+        ));
+        changes.extend((0..200).map(|unit| ([keys[3][0], keys[3][1], unit], random_u256())));
+        tree.insert_batch(changes.clone()).unwrap();
+        entries.extend(changes);
+        for key in &keys[4..8] {
+            assert!(tree.remove_single(key).unwrap().is_some());
+            entries.remove(key);
+        }
+
+        let mut expected = TestTree::new();
+        expected.insert_batch(entries).unwrap();
+        assert!(tree.commitment(&crs).unwrap() == expected.commitment(&crs).unwrap());
+    }
+
+    #[test]
+    fn test_checkpoints() {
+        let point_gen = KZGRandomPointGenerator::<G1>::default();
+        let crs = KZGT::setup(256, &point_gen).unwrap();
+
+        let mut tree = TestTree::new();
+        let mut entries = HashMap::new();
+        for _ in 0..20 {
+            let (key, val) = (random_key(255, None), random_u256());
+            tree.insert_single(key, val.clone()).unwrap();
+            entries.insert(key, val);
+        }
+        let keys: Vec<KEYT> = entries.keys().cloned().collect();
+        let root = tree.commitment(&crs).unwrap();
+
+        // Overwrite, split an extension, remove a leaf and insert a batch
+        let outer = tree.checkpoint();
+        let split_key = random_key(255, Some(&keys[1][0..1]));
+        tree.insert_single(keys[0], random_u256()).unwrap();
+        tree.insert_single(split_key, random_u256()).unwrap();
+        assert!(tree.remove_single(&keys[2]).unwrap().is_some());
+        tree.insert_batch((0..10).map(|unit| ([keys[3][0], keys[3][1], unit], random_u256())))
+            .unwrap();
+        let outer_root = tree.commitment(&crs).unwrap();
+
+        // Collapse the split extension again, and leave the changes uncommitted
+        let inner = tree.checkpoint();
+        assert!(tree.remove_single(&split_key).unwrap().is_some());
+        tree.insert_single(keys[4], random_u256()).unwrap();
+        tree.insert_single(random_key(255, None), random_u256())
+            .unwrap();
+        tree.revert_to(inner).unwrap();
+        assert!(tree.revert_to(inner).is_err());
+        // The cached commitments are restored rather than generated again
+        assert!(tree.root.clean_commitment() == Some(&outer_root));
+
+        let committed = tree.checkpoint();
+        let nested = tree.checkpoint();
+        tree.insert_single(keys[5], random_u256()).unwrap();
+        tree.commit_checkpoint(committed).unwrap();
+        assert!(tree.commit_checkpoint(nested).is_err());
+        assert!(tree.commitment(&crs).unwrap() != outer_root);
+
+        tree.revert_to(outer).unwrap();
+        assert!(tree.root.clean_commitment() == Some(&root));
+        assert!(tree.check_commitments(&crs).unwrap());
+        assert!(tree.get_single(&split_key).unwrap().is_none());
+        for (key, val) in entries.iter() {
+            assert!(tree.get_single(key).unwrap() == Some(val));
+        }
+
+        let mut expected = TestTree::new();
+        expected.insert_batch(entries).unwrap();
+        assert!(expected.commitment(&crs).unwrap() == root);
+    }
+
+    #[test]
+    fn test_tree_versions() {
+        let point_gen = KZGRandomPointGenerator::<G1>::default();
+        let crs = KZGT::setup(256, &point_gen).unwrap();
+        let child = |tree: &TestTree, unit: KEY_DATA_TYPE| match tree.root.as_ref() {
+            Node::Internal { children, .. } => children[&unit].clone(),
+            _ => unreachable!(),
+        };
+
+        let mut tree = TestTree::new();
+        let changed_key = random_key(255, None);
+        let shared_key = [changed_key[0].wrapping_add(1), 7, 7];
+        let old_val = random_u256();
+        tree.insert_single(changed_key, old_val.clone()).unwrap();
+        tree.insert_single(shared_key, random_u256()).unwrap();
+        let old_root = tree.commitment(&crs).unwrap();
+
+        let mut versions = TreeVersions::new();
+        versions.insert(1, tree.clone());
+        let old_node = Arc::downgrade(&child(&tree, changed_key[0]));
+
+        // Only the path to the changed key is copied
+        tree.insert_single(changed_key, random_u256()).unwrap();
+        let new_root = tree.commitment(&crs).unwrap();
+        versions.insert(2, tree.clone());
+        let (old, new) = (versions.get(&1).unwrap(), versions.get(&2).unwrap());
+        assert!(Arc::ptr_eq(
+            &child(old, shared_key[0]),
+            &child(new, shared_key[0])
+        ));
+        assert!(!Arc::ptr_eq(
+            &child(old, changed_key[0]),
+            &child(new, changed_key[0])
+        ));
+        assert!(Arc::ptr_eq(&new.root, &tree.root));
+
+        let old = versions.get_mut(&1).unwrap();
+        assert!(old.commitment(&crs).unwrap() == old_root);
+        assert!(old.get_single(&changed_key).unwrap() == Some(&old_val));
+
+        // Building on an old version leaves it unchanged
+        let mut fork = versions.checkout(&1).unwrap();
+        fork.insert_single(shared_key, random_u256()).unwrap();
+        assert!(fork.commitment(&crs).unwrap() != old_root);
+        assert!(versions.get_mut(&1).unwrap().commitment(&crs).unwrap() == old_root);
+        drop(fork);
+
+        // Nodes only held by dropped versions are released
+        assert!(old_node.upgrade().is_some());
+        versions.prune_before(&2);
+        assert!(versions.names().eq([2].iter()));
+        assert!(old_node.upgrade().is_none());
+        assert!(versions.latest().unwrap().root.clean_commitment() == Some(&new_root));
+    }
+
+    #[test]
+    fn test_partial_tree() {
+        type TestPartialTree =
+            PartialVerkleTree<KEY_LEN, KEY_DATA_TYPE, KZGT, U256, G1, GeneralEvaluationDomain<F>>;
+        let point_gen = KZGRandomPointGenerator::<G1>::default();
+        let crs = KZGT::setup(256, &point_gen).unwrap();
+
         let mut tree: TestTree = TestTree::new();
+        let (present, other_present) = ([10, 1, 5], [10, 1, 200]);
+        for key in [present, other_present, [10, 2, 3], [20, 0, 0]] {
+            tree.insert_single(key, random_u256()).unwrap();
+        }
+        for _ in 0..20 {
+            let key = random_key(255, None);
+            tree.insert_single([key[0].max(40), key[1], key[2]], random_u256())
+                .unwrap();
+        }
+
+        let unset_suffix = [10, 1, 7];
+        let empty_internal_slot = [10, 3, 1];
+        let other_stem = [20, 5, 1];
+        let empty_root_slot = [30, 0, 0];
+        let keys = [
+            present,
+            unset_suffix,
+            empty_internal_slot,
+            other_stem,
+            empty_root_slot,
+            other_present,
+        ];
+        let root = tree.commitment(&crs).unwrap();
+        let proof = tree.prove_keys(&crs, &keys).unwrap();
+        let mut claims: Vec<(KEYT, Option<U256>)> = keys
+            .iter()
+            .map(|k| (*k, tree.get_single(k).unwrap().cloned()))
+            .collect();
+
+        let mut partial =
+            TestPartialTree::from_proof(&crs, &root, &proof, &claims, ExtensionEncoding::Generic)
+                .unwrap();
+        assert!(partial.commitment(&crs).unwrap() == root);
+        for (key, value) in claims.iter() {
+            assert!(partial.get(key).unwrap() == value.as_ref());
+        }
+        let unproven = [10, 1, 6];
+        assert!(matches!(
+            partial.get(&unproven),
+            Err(VerkleError::UnprovenKey)
+        ));
+        assert!(matches!(
+            partial.insert(unproven, random_u256()),
+            Err(VerkleError::UnprovenKey)
+        ));
+
+        // The post-state root matches the root of the full tree after the same writes
+        for key in keys {
+            let value = random_u256();
+            partial.insert(key, value.clone()).unwrap();
+            tree.insert_single(key, value).unwrap();
+            assert!(partial.commitment(&crs).unwrap() == tree.commitment(&crs).unwrap());
+        }
+
+        // A proof of other claims is rejected
+        claims[0].1 = Some(random_u256());
+        assert!(matches!(
+            TestPartialTree::from_proof(&crs, &root, &proof, &claims, ExtensionEncoding::Generic),
+            Err(VerkleError::InvalidProof)
+        ));
+    }
+
+    #[test]
+    fn test_prune() {
+        let point_gen = KZGRandomPointGenerator::<G1>::default();
+        let crs = KZGT::setup(256, &point_gen).unwrap();
+
+        let mut tree: TestTree = TestTree::new();
+        let (internal_key, extension_key) = ([5, 1, 1], [9, 3, 3]);
+        for key in [internal_key, [5, 2, 1], extension_key] {
+            tree.insert_single(key, random_u256()).unwrap();
+        }
+        for _ in 0..20 {
+            let key = random_key(255, None);
+            tree.insert_single([key[0].max(20), key[1], key[2]], random_u256())
+                .unwrap();
+        }
+        let root = tree.commitment(&crs).unwrap();
+        let mut full = tree.clone();
+
+        tree.prune(&[5]).unwrap();
+        tree.prune(&[9]).unwrap();
+        assert!(tree.commitment(&crs).unwrap() == root);
+        assert!(matches!(tree.prune(&[7]), Err(VerkleError::InvalidPath)));
+
+        // Keys that may be below a pruned subtree cannot be accessed
+        let pruned = |result: Result<_, VerkleError>, path: Vec<usize>| matches!(result, Err(VerkleError::PrunedPath(p)) if p == path);
+        assert!(pruned(tree.get_single(&internal_key).map(|_| ()), vec![5]));
+        assert!(pruned(tree.get_single(&[5, 7, 7]).map(|_| ()), vec![5]));
+        assert!(pruned(tree.get_single(&extension_key).map(|_| ()), vec![9]));
+        assert!(pruned(
+            tree.insert_single(internal_key, random_u256()),
+            vec![5]
+        ));
+        assert!(pruned(
+            tree.remove_single(&extension_key).map(|_| ()),
+            vec![9]
+        ));
+        assert!(pruned(
+            tree.prove_keys(&crs, &[internal_key]).map(|_| ()),
+            vec![5]
+        ));
+        assert!(tree.commitment(&crs).unwrap() == root);
+
+        // Other stems are not below the stub of a pruned extension, which moves aside for them
+        let other_stem = [9, 4, 0];
+        assert!(tree.get_single(&other_stem).unwrap().is_none());
+        let value = random_u256();
+        tree.insert_single(other_stem, value.clone()).unwrap();
+        full.insert_single(other_stem, value).unwrap();
+        assert!(matches!(tree.prune(&[9]), Err(VerkleError::PruneError(_))));
+        assert!(tree.commitment(&crs).unwrap() == full.commitment(&crs).unwrap());
+        assert!(pruned(
+            tree.get_single(&extension_key).map(|_| ()),
+            vec![9, 3]
+        ));
+
+        // Stubs are kept in snapshots
+        let mut bytes = Vec::new();
+        tree.write_snapshot(&mut bytes, false).unwrap();
+        let mut restored =
+            TestTree::read_snapshot(bytes.as_slice(), MemoryStore::default()).unwrap();
+        assert!(restored.commitment(&crs).unwrap() == full.commitment(&crs).unwrap());
+
+        tree.remove_single(&other_stem).unwrap();
+        assert!(tree.commitment(&crs).unwrap() == root);
+    }
+
+    #[test]
+    fn test_execution_witness() {
+        use vector_commit::{
+            banderwagon::{Banderwagon, Fr},
+            ipa::{IPAPointGenerator, IPA},
+            linear_domain::LinearDomain,
+            transcript::EthereumHashToField,
+        };
+        use witness::ExecutionWitness;
+
+        // The curve, domain and transcript of the Ethereum verkle spec
+        type D = LinearDomain<Fr>;
+        type TestIPA = IPA<256, Banderwagon, EthereumHashToField, D>;
+        type Value = U256<Fr>;
+        type EthereumTree = VerkleTree<32, u8, TestIPA, Value, Banderwagon, D>;
+        let point_gen = IPAPointGenerator::default();
+        let crs = TestIPA::setup(256, &point_gen).unwrap();
+
+        let key = |prefix: &[u8], suffix: u8| {
+            let mut key = [0; 32];
+            key[0..prefix.len()].copy_from_slice(prefix);
+            key[31] = suffix;
+            key
+        };
+        let mut tree = EthereumTree::with_encoding(256, ExtensionEncoding::Ethereum).unwrap();
+        for k in [
+            key(&[1, 2], 5),
+            key(&[1, 2], 200),
+            key(&[1, 2, 7], 0),
+            key(&[1, 3, 9], 1),
+        ] {
+            tree.insert_single(k, random_u256()).unwrap();
+        }
+        tree.insert_single(key(&[4], 0), random_u256()).unwrap();
+        let root = tree.commitment(&crs).unwrap();
+
+        // Reads and writes of present stems (in both halves of the leaves), another stem and an empty root slot
+        let touched = [
+            key(&[1, 2], 5),
+            key(&[1, 2], 200),
+            key(&[1, 2], 7),
+            key(&[1, 3, 1], 0),
+            key(&[2], 0),
+            key(&[4], 0),
+        ];
+        let values: Vec<_> = touched
+            .iter()
+            .enumerate()
+            .map(|(i, k)| {
+                let new = (i % 2 == 0).then(random_u256);
+                (*k, tree.get_single(k).unwrap().cloned(), new)
+            })
+            .collect();
+        let state_diff = witness::state_diff(&values).unwrap();
+        let keys: Vec<_> = state_diff.iter().flat_map(|s| s.keys()).collect();
+        let proof = tree.prove_keys(&crs, &keys).unwrap();
+        let witness = ExecutionWitness::new(state_diff, &proof).unwrap();
+
+        assert!(witness.verkle_proof.depth_extension_present.len() == 4);
+        assert!(witness.verkle_proof.other_stems.len() == 1);
+
+        let ssz = witness.to_ssz();
+        assert!(ExecutionWitness::from_ssz(&ssz).unwrap() == witness);
+        assert!(ExecutionWitness::from_ssz(&ssz[..ssz.len() - 1]).is_err());
+        let json = witness.to_json();
+        assert!(json.contains("\"depthExtensionPresent\":\"0x"));
+        assert!(ExecutionWitness::from_json(&json).unwrap() == witness);
+
+        let claims = witness.claims::<Value>().unwrap();
+        let decoded = witness.proof::<Banderwagon, TestIPA>().unwrap();
+        let encoding = ExtensionEncoding::Ethereum;
+        assert!(EthereumTree::verify_proof(&crs, &root, &claims, &decoded, encoding).unwrap());
+        assert!(witness.writes::<Value>().unwrap().len() == 3);
+
+        let mut wrong = witness.clone();
+        wrong.state_diff[0].suffix_diffs[0].current_value = Some(random_u256::<Fr>().0);
+        let claims = wrong.claims::<Value>().unwrap();
+        assert!(!EthereumTree::verify_proof(&crs, &root, &claims, &decoded, encoding).unwrap());
+    }
+
+    #[test]
+    fn test_snapshot() {
         let point_gen = KZGRandomPointGenerator::<G1>::default();
         let crs = KZGT::setup(256, &point_gen).unwrap();
 
+        let mut tree = TestTree::with_encoding(256, ExtensionEncoding::Ethereum).unwrap();
+        let key = random_key(255, None);
+        let mut keys = vec![key, random_key(255, Some(&key[0..1]))];
+        keys.extend((0..20).map(|_| random_key(255, None)));
+        for key in keys.iter() {
+            tree.insert_single(*key, random_u256()).unwrap();
+        }
+        let root = tree.commitment(&crs).unwrap();
+
+        let mut bytes = Vec::new();
+        tree.write_snapshot(&mut bytes, true).unwrap();
+        let mut restored =
+            TestTree::read_snapshot(bytes.as_slice(), MemoryStore::default()).unwrap();
+        assert!(restored.encoding() == ExtensionEncoding::Ethereum);
+        assert!(restored.check_commitments(&crs).unwrap());
+        for key in keys.iter() {
+            assert!(restored.get_single(key).unwrap() == tree.get_single(key).unwrap());
+        }
+
+        // Equal trees produce equal snapshots
+        let mut restored_bytes = Vec::new();
+        restored.write_snapshot(&mut restored_bytes, true).unwrap();
+        assert!(restored_bytes == bytes);
+
+        // Changed nodes have no cached commitments in the snapshot, and are committed to again
+        tree.insert_single(key, random_u256()).unwrap();
+        for commitments in [true, false] {
+            let mut bytes = Vec::new();
+            tree.write_snapshot(&mut bytes, commitments).unwrap();
+            let mut restored =
+                TestTree::read_snapshot(bytes.as_slice(), MemoryStore::default()).unwrap();
+            assert!(restored.commitment(&crs).unwrap() == tree.commitment(&crs).unwrap());
+        }
+        assert!(tree.commitment(&crs).unwrap() != root);
+
+        // A leaf that does not match the cached commitments is caught when checked
+        let mut tampered = bytes.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        let mut restored =
+            TestTree::read_snapshot(tampered.as_slice(), MemoryStore::default()).unwrap();
+        assert!(!restored.check_commitments(&crs).unwrap());
+        restored.clear_commitments();
+        assert!(restored.check_commitments(&crs).unwrap());
+        assert!(restored.commitment(&crs).unwrap() != root);
+
+        let mut unsupported = bytes.clone();
+        unsupported[4] = SNAPSHOT_VERSION + 1;
+        assert!(matches!(
+            TestTree::read_snapshot(unsupported.as_slice(), MemoryStore::default()),
+            Err(VerkleError::InvalidSnapshot(_))
+        ));
+        assert!(
+            TestTree::read_snapshot(&bytes[..bytes.len() - 1], MemoryStore::default()).is_err()
+        );
+    }
+
+    #[test]
+    fn test_node_store() {
+        type FileTree = VerkleTree<
+            KEY_LEN,
+            KEY_DATA_TYPE,
+            KZGT,
+            U256,
+            G1,
+            GeneralEvaluationDomain<F>,
+            FileStore,
+        >;
+        let point_gen = KZGRandomPointGenerator::<G1>::default();
+        let crs = KZGT::setup(256, &point_gen).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tree");
+        let open = || {
+            FileTree::with_store(
+                256,
+                ExtensionEncoding::Generic,
+                FileStore::open(&path).unwrap(),
+            )
+        };
+
+        let key = random_key(255, None);
+        let mut entries: HashMap<KEYT, U256> = HashMap::new();
+        entries.insert(key, random_u256());
+        entries.insert(random_key(255, Some(&key[0..1])), random_u256());
+        entries.extend((0..20).map(|_| (random_key(255, None), random_u256())));
+        let keys: Vec<KEYT> = entries.keys().copied().collect();
+
+        let mut tree = open().unwrap();
+        tree.insert_batch(entries.clone()).unwrap();
+        let root = tree.commitment(&crs).unwrap();
+        tree.unload().unwrap();
+        assert!(
+            matches!(tree.root.as_ref(), Node::Internal { children, .. } if children.is_empty())
+        );
+        drop(tree);
+
+        // Reopening only loads the root, and the rest of the tree as it is accessed
+        let mut tree = open().unwrap();
+        assert!(tree.commitment(&crs).unwrap() == root);
+        for (key, value) in entries.iter() {
+            assert!(tree.get_single(key).unwrap() == Some(value));
+        }
+        tree.unload().unwrap();
+
+        // Removing the key sharing a stem prefix collapses its unloaded sibling into their parent's place
+        let sibling = *keys.iter().find(|k| k[0] == key[0] && **k != key).unwrap();
+        assert!(tree.remove_single(&sibling).unwrap() == entries.remove(&sibling));
+        let (added, value) = (random_key(255, None), random_u256());
+        tree.insert_single(added, value.clone()).unwrap();
+        entries.insert(added, value);
+        let root = tree.commitment(&crs).unwrap();
+        tree.unload().unwrap();
+
+        let proof = tree.prove_keys(&crs, &keys).unwrap();
+        let claims: Vec<_> = keys
+            .iter()
+            .map(|k| (*k, tree.get_single(k).unwrap().cloned()))
+            .collect();
+        assert!(
+            FileTree::verify_proof(&crs, &root, &claims, &proof, ExtensionEncoding::Generic)
+                .unwrap()
+        );
+        drop(tree);
+
+        let mut expected = TestTree::new();
+        expected.insert_batch(entries.clone()).unwrap();
+        let mut tree = open().unwrap();
+        assert!(tree.commitment(&crs).unwrap() == expected.commitment(&crs).unwrap());
+        for key in keys.iter().chain([&added]) {
+            assert!(tree.get_single(key).unwrap() == entries.get(key));
+        }
+        drop(tree);
+
+        assert!(matches!(
+            FileTree::with_store(
+                256,
+                ExtensionEncoding::Ethereum,
+                FileStore::open(&path).unwrap()
+            ),
+            Err(VerkleError::StoreError(_))
+        ));
+    }
+
+    #[test]
+    fn test_node_cache() {
+        type FileTree = VerkleTree<
+            KEY_LEN,
+            KEY_DATA_TYPE,
+            KZGT,
+            U256,
+            G1,
+            GeneralEvaluationDomain<F>,
+            FileStore,
+        >;
+        let point_gen = KZGRandomPointGenerator::<G1>::default();
+        let crs = KZGT::setup(256, &point_gen).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tree");
+        let resident = |tree: &FileTree| {
+            let mut stems = vec![];
+            tree.root.collect_stems(&mut stems);
+            stems.len()
+        };
+
+        let mut entries: HashMap<KEYT, U256> = (0..30)
+            .map(|_| (random_key(255, None), random_u256()))
+            .collect();
+        let keys: Vec<KEYT> = entries.keys().copied().collect();
+        let mut tree = FileTree::with_store(
+            256,
+            ExtensionEncoding::Generic,
+            FileStore::open(&path).unwrap(),
+        )
+        .unwrap();
+        tree.set_cache_capacity(Some(4)).unwrap();
+        for (key, value) in entries.iter() {
+            tree.insert_single(*key, value.clone()).unwrap();
+        }
+
+        // Extension nodes without a commitment cannot be evicted yet, and are pinned rather than scanned again
+        assert!(resident(&tree) == keys.len());
+        assert!(tree.cache.as_ref().unwrap().pinned.len() == keys.len());
+        let root = tree.commitment(&crs).unwrap();
+        assert!(resident(&tree) == 4);
+        let stats = tree.cache_stats().unwrap();
+        assert!(stats.evictions == keys.len() as u64 - 4);
+
+        // Evicted nodes are loaded again as they are accessed
+        for (key, value) in entries.iter() {
+            assert!(tree.get_single(key).unwrap() == Some(value));
+            assert!(resident(&tree) <= 4);
+        }
+        assert!(tree.get_single(&keys[0]).unwrap().is_some());
+        let stats = tree.cache_stats().unwrap();
+        assert!(stats.hits >= 1 && stats.misses >= keys.len() as u64 - 4);
+
+        // Changed nodes are written before they are evicted
+        for key in &keys[0..5] {
+            let value = random_u256();
+            tree.insert_single(*key, value.clone()).unwrap();
+            entries.insert(*key, value);
+        }
+        assert!(tree.remove_single(&keys[5]).unwrap().is_some());
+        entries.remove(&keys[5]);
+        let new_root = tree.commitment(&crs).unwrap();
+        assert!(new_root != root);
+        for key in keys.iter() {
+            assert!(tree.get_single(key).unwrap() == entries.get(key));
+        }
+        tree.flush().unwrap();
+        drop(tree);
+
+        let mut expected = TestTree::new();
+        expected.insert_batch(entries.clone()).unwrap();
+        assert!(expected.commitment(&crs).unwrap() == new_root);
+        let mut tree = FileTree::with_store(
+            256,
+            ExtensionEncoding::Generic,
+            FileStore::open(&path).unwrap(),
+        )
+        .unwrap();
+        assert!(tree.commitment(&crs).unwrap() == new_root);
+        for key in keys.iter() {
+            assert!(tree.get_single(key).unwrap() == entries.get(key));
+        }
+        assert!(tree.cache_stats().is_none());
+        assert!(tree.set_cache_capacity(Some(0)).is_err());
+    }
+
+    #[test]
+    fn test_clone_file_tree() {
+        type FileTree = VerkleTree<
+            KEY_LEN,
+            KEY_DATA_TYPE,
+            KZGT,
+            U256,
+            G1,
+            GeneralEvaluationDomain<F>,
+            FileStore,
+        >;
+        let point_gen = KZGRandomPointGenerator::<G1>::default();
+        let crs = KZGT::setup(256, &point_gen).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tree");
+        let file_len = || std::fs::metadata(&path).unwrap().len();
+
+        let entries: HashMap<KEYT, U256> = (0..30)
+            .map(|_| (random_key(255, None), random_u256()))
+            .collect();
+        let keys: Vec<KEYT> = entries.keys().copied().collect();
+        let mut tree = FileTree::with_store(
+            256,
+            ExtensionEncoding::Generic,
+            FileStore::open(&path).unwrap(),
+        )
+        .unwrap();
+        tree.set_cache_capacity(Some(4)).unwrap();
+        tree.insert_batch(entries.clone()).unwrap();
+        let root = tree.commitment(&crs).unwrap();
+        tree.flush().unwrap();
+
+        // The clone reads the records the tree flushed, without writing any of its own
+        let flushed_len = file_len();
+        let mut clone = tree.clone();
+        assert!(Arc::ptr_eq(&clone.root, &tree.root));
+        assert!(Arc::ptr_eq(&clone.journal, &tree.journal));
+        assert!(clone.store().len() == tree.store().len());
+        clone.unload().unwrap();
+        for (key, value) in entries.iter() {
+            assert!(clone.get_single(key).unwrap() == Some(value));
+        }
+        assert!(clone.cache_stats().unwrap().misses >= keys.len() as u64 - 4);
+        assert!(file_len() == flushed_len);
+
+        // Records the clone flushes after changing are its own
+        let new_value = random_u256();
+        clone.insert_single(keys[0], new_value.clone()).unwrap();
+        assert!(clone.remove_single(&keys[1]).unwrap().is_some());
+        let new_root = clone.commitment(&crs).unwrap();
+        clone.flush().unwrap();
+        clone.unload().unwrap();
+        assert!(file_len() > flushed_len);
+
+        tree.unload().unwrap();
+        for (key, value) in entries.iter() {
+            assert!(tree.get_single(key).unwrap() == Some(value));
+        }
+        assert!(tree.commitment(&crs).unwrap() == root);
+        assert!(clone.get_single(&keys[0]).unwrap() == Some(&new_value));
+        assert!(clone.get_single(&keys[1]).unwrap().is_none());
+        assert!(clone.commitment(&crs).unwrap() == new_root);
+
+        // Open checkpoints are shared until either tree records a change in them
+        let checkpoint = clone.checkpoint();
+        let mut fork = clone.clone();
+        assert!(Arc::ptr_eq(&fork.journal, &clone.journal));
+        fork.insert_single(keys[1], random_u256()).unwrap();
+        assert!(!Arc::ptr_eq(&fork.journal, &clone.journal));
+        fork.revert_to(checkpoint).unwrap();
+        assert!(fork.commitment(&crs).unwrap() == new_root);
+        assert!(clone.commit_checkpoint(checkpoint).is_ok());
+    }
+
+    #[test]
+    fn test_path_to_stem() {
+        let mut tree: TestTree = TestTree::new();
+
         let key = random_key(255, None);
         let val = random_u256();
-        tree.insert_single(key, val);
+        tree.insert_single(key, val).unwrap();
 
-        tree.insert_single(random_key(255, Some(&[key[0]])), random_u256());
+        tree.insert_single(random_key(255, Some(&[key[0]])), random_u256())
+            .unwrap();
 
         let path = tree.path_to_stem(&key);
         for (i, p) in path.unwrap().iter().enumerate() {
@@ -348,4 +2027,308 @@ mod tests {
             assert!(p.1 == key[i]);
         }
     }
+
+    #[test]
+    fn test_prove_keys() {
+        let mut tree: TestTree = TestTree::new();
+        let point_gen = KZGRandomPointGenerator::<G1>::default();
+        let crs = KZGT::setup(256, &point_gen).unwrap();
+
+        let key1 = random_key(255, None);
+        let key2 = random_key(255, Some(&[key1[0]]));
+        let key3 = random_key(255, Some(&key1[0..KEY_LEN - 1]));
+        for key in [key1, key2, key3] {
+            tree.insert_single(key, random_u256()).unwrap();
+        }
+
+        let keys = [key1, key2, key3];
+        let proof = tree.prove_keys(&crs, &keys).unwrap();
+
+        // Each node on the paths is included once: the internal nodes below the root, the extensions and their
+        // suffix commitments
+        let mut paths = HashSet::new();
+        for key in keys {
+            let (stem, unit) = key.split();
+            let depth = tree.path_to_stem(&stem).unwrap().len();
+            paths.extend((1..=depth).map(|d| stem[0..d].to_vec()));
+            paths.insert([&stem[0..depth], &[2 + suffix_half(unit.into(), 256) as u8]].concat());
+        }
+        assert!(proof.commitments_by_path.len() == paths.len());
+        assert!(proof.depth_extension_present.len() == 2);
+        assert!(proof
+            .depth_extension_present
+            .iter()
+            .all(|s| s & 0b111 == proof::STATUS_PRESENT));
+    }
+
+    #[test]
+    fn test_verify_proof() {
+        let mut tree: TestTree = TestTree::new();
+        let point_gen = KZGRandomPointGenerator::<G1>::default();
+        let crs = KZGT::setup(256, &point_gen).unwrap();
+
+        let key1 = random_key(255, None);
+        let key2 = random_key(255, Some(&[key1[0]]));
+        let key3 = random_key(255, Some(&key1[0..KEY_LEN - 1]));
+        let key4 = random_key(255, None);
+        let keys = [key1, key2, key3, key4];
+        for key in keys {
+            tree.insert_single(key, random_u256()).unwrap();
+        }
+
+        let root = tree.commitment(&crs).unwrap();
+        let proof = tree.prove_keys(&crs, &keys).unwrap();
+        let mut claims: Vec<(KEYT, Option<U256>)> = keys
+            .iter()
+            .map(|k| (*k, tree.get_single(k).unwrap().cloned()))
+            .collect();
+        assert!(
+            TestTree::verify_proof(&crs, &root, &claims, &proof, ExtensionEncoding::Generic)
+                .unwrap()
+        );
+
+        // Wrong root
+        let wrong_root = root + G1::generator();
+        assert!(!TestTree::verify_proof(
+            &crs,
+            &wrong_root,
+            &claims,
+            &proof,
+            ExtensionEncoding::Generic
+        )
+        .unwrap());
+
+        // Wrong value
+        let original = claims[0].1.clone();
+        claims[0].1 = Some(random_u256());
+        assert!(
+            !TestTree::verify_proof(&crs, &root, &claims, &proof, ExtensionEncoding::Generic)
+                .unwrap()
+        );
+        claims[0].1 = original;
+
+        // Missing claim
+        assert!(!TestTree::verify_proof(
+            &crs,
+            &root,
+            &claims[1..],
+            &proof,
+            ExtensionEncoding::Generic
+        )
+        .unwrap());
+
+        // Claims are in a different order than the proof
+        claims.swap(0, 3);
+        assert!(
+            !TestTree::verify_proof(&crs, &root, &claims, &proof, ExtensionEncoding::Generic)
+                .unwrap()
+        );
+        claims.swap(0, 3);
+
+        // A node is given a different commitment
+        let mut bad_proof = tree.prove_keys(&crs, &keys).unwrap();
+        bad_proof.commitments_by_path[0] += G1::generator();
+        assert!(!TestTree::verify_proof(
+            &crs,
+            &root,
+            &claims,
+            &bad_proof,
+            ExtensionEncoding::Generic
+        )
+        .unwrap());
+
+        // A commitment is missing
+        let mut bad_proof = tree.prove_keys(&crs, &keys).unwrap();
+        bad_proof.commitments_by_path.pop();
+        assert!(!TestTree::verify_proof(
+            &crs,
+            &root,
+            &claims,
+            &bad_proof,
+            ExtensionEncoding::Generic
+        )
+        .unwrap());
+
+        // A path is truncated so its extension is claimed at the depth of an internal node
+        let mut bad_proof = tree.prove_keys(&crs, &keys).unwrap();
+        bad_proof.depth_extension_present[1] -= 1 << proof::STATUS_BITS;
+        assert!(!TestTree::verify_proof(
+            &crs,
+            &root,
+            &claims,
+            &bad_proof,
+            ExtensionEncoding::Generic
+        )
+        .unwrap());
+    }
+
+    #[test]
+    fn test_width() {
+        type WideTree = VerkleTree<KEY_LEN, u16, KZGT, U256, G1, GeneralEvaluationDomain<F>>;
+        let width = 1024;
+        let point_gen = KZGRandomPointGenerator::<G1>::default();
+        let crs = KZGT::setup(width, &point_gen).unwrap();
+
+        assert!(TestTree::with_width(512).is_err());
+        assert!(WideTree::with_width(6).is_ok());
+        assert!(WideTree::with_width(7).is_err());
+
+        let mut tree = WideTree::with_width(width).unwrap();
+        assert!(tree.insert_single([0, 0, 1024], random_u256()).is_err());
+        assert!(matches!(
+            tree.remove_single(&[0, 1024, 0]),
+            Err(VerkleError::UnitOutOfRange { unit: 1024, .. })
+        ));
+        assert!(matches!(
+            tree.get_single(&[0, 1024, 0]),
+            Err(VerkleError::UnitOutOfRange { unit: 1024, .. })
+        ));
+
+        // Leaves in both halves of one extension, and a stem that only differs at a unit above 256
+        let keys: [[u16; KEY_LEN]; 3] = [[700, 3, 5], [700, 3, 1000], [700, 300, 2]];
+        let values: Vec<U256> = keys.iter().map(|_| random_u256()).collect();
+        tree.insert_batch(keys.into_iter().zip(values.iter().cloned()))
+            .unwrap();
+        let root = tree.commitment(&crs).unwrap();
+
+        let mut expected = WideTree::with_width(width).unwrap();
+        for (key, value) in keys.iter().zip(values.iter()).rev() {
+            expected.insert_single(*key, value.clone()).unwrap();
+        }
+        assert!(expected.commitment(&crs).unwrap() == root);
+
+        let absent = [700, 3, 6];
+        let proven = [keys[0], keys[1], keys[2], absent];
+        let proof = tree.prove_keys(&crs, &proven).unwrap();
+        let claims: Vec<([u16; KEY_LEN], Option<U256>)> = proven
+            .iter()
+            .map(|k| (*k, tree.get_single(k).unwrap().cloned()))
+            .collect();
+        assert!(
+            WideTree::verify_proof(&crs, &root, &claims, &proof, ExtensionEncoding::Generic)
+                .unwrap()
+        );
+
+        // The parameters must commit to vectors as wide as the tree
+        let narrow_crs = KZGT::setup(256, &point_gen).unwrap();
+        assert!(matches!(
+            tree.commitment(&narrow_crs),
+            Err(VerkleError::WidthMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_ethereum_encoding() {
+        let point_gen = KZGRandomPointGenerator::<G1>::default();
+        let crs = KZGT::setup(256, &point_gen).unwrap();
+
+        let key = random_key(255, None);
+        let zero_key = [key[0], key[1], key[2].wrapping_add(1)];
+        let value = random_u256();
+
+        // Only the leaf marker distinguishes a leaf set to zero from a missing leaf
+        for encoding in [ExtensionEncoding::Generic, ExtensionEncoding::Ethereum] {
+            let mut tree = TestTree::with_encoding(256, encoding).unwrap();
+            tree.insert_single(key, value.clone()).unwrap();
+            let without_zero = tree.commitment(&crs).unwrap();
+            tree.insert_single(zero_key, U256::zero()).unwrap();
+            let with_zero = tree.commitment(&crs).unwrap();
+            assert!((with_zero != without_zero) == (encoding == ExtensionEncoding::Ethereum));
+
+            let mut expected = TestTree::with_encoding(256, encoding).unwrap();
+            expected
+                .insert_batch([(key, value.clone()), (zero_key, U256::zero())])
+                .unwrap();
+            assert!(expected.commitment(&crs).unwrap() == with_zero);
+        }
+
+        let mut tree = TestTree::with_encoding(256, ExtensionEncoding::Ethereum).unwrap();
+        tree.insert_batch([(key, value.clone()), (zero_key, U256::zero())])
+            .unwrap();
+        let root = tree.commitment(&crs).unwrap();
+        let proof = tree.prove_keys(&crs, &[key, zero_key]).unwrap();
+        let mut claims = vec![(key, Some(value)), (zero_key, Some(U256::zero()))];
+        let verify = |claims: &[(KEYT, Option<U256>)], encoding| {
+            TestTree::verify_proof(&crs, &root, claims, &proof, encoding).unwrap()
+        };
+        assert!(verify(&claims, ExtensionEncoding::Ethereum));
+        assert!(!verify(&claims, ExtensionEncoding::Generic));
+
+        claims[1].1 = None;
+        assert!(!verify(&claims, ExtensionEncoding::Ethereum));
+    }
+
+    #[test]
+    fn test_absence_proofs() {
+        let mut tree: TestTree = TestTree::new();
+        let point_gen = KZGRandomPointGenerator::<G1>::default();
+        let crs = KZGT::setup(256, &point_gen).unwrap();
+
+        let present = [1, 2, 5];
+        tree.insert_single(present, random_u256()).unwrap();
+        tree.insert_single([7, 1, 1], random_u256()).unwrap();
+        tree.insert_single([7, 2, 1], random_u256()).unwrap();
+
+        let unset_suffix = [1, 2, 6];
+        let other_stem = [1, 3, 0];
+        let empty_root_slot = [4, 0, 0];
+        let empty_internal_slot = [7, 3, 0];
+        let keys = [
+            present,
+            unset_suffix,
+            other_stem,
+            empty_root_slot,
+            empty_internal_slot,
+        ];
+
+        let root = tree.commitment(&crs).unwrap();
+        let proof = tree.prove_keys(&crs, &keys).unwrap();
+        // The status byte of each stem is its depth and status (present, other stem, absent)
+        assert!(proof.depth_extension_present == vec![1 << 3 | 2, 1 << 3 | 1, 1 << 3, 2 << 3]);
+        assert!(proof.other_stems == vec![[1, 2, 0]]);
+
+        let mut claims: Vec<(KEYT, Option<U256>)> = keys
+            .iter()
+            .map(|k| (*k, tree.get_single(k).unwrap().cloned()))
+            .collect();
+        assert!(claims[1..].iter().all(|c| c.1.is_none()));
+        assert!(
+            TestTree::verify_proof(&crs, &root, &claims, &proof, ExtensionEncoding::Generic)
+                .unwrap()
+        );
+
+        // Claiming a value for any of the absent keys must fail
+        for i in 1..keys.len() {
+            claims[i].1 = Some(random_u256());
+            assert!(!TestTree::verify_proof(
+                &crs,
+                &root,
+                &claims,
+                &proof,
+                ExtensionEncoding::Generic
+            )
+            .unwrap());
+            claims[i].1 = None;
+        }
+
+        // Claiming the present key is absent must fail
+        let value = claims[0].1.take();
+        assert!(
+            !TestTree::verify_proof(&crs, &root, &claims, &proof, ExtensionEncoding::Generic)
+                .unwrap()
+        );
+        claims[0].1 = value;
+
+        // The other stem must diverge from the claimed stem
+        let mut bad_proof = tree.prove_keys(&crs, &keys).unwrap();
+        bad_proof.other_stems[0] = [1, 3, 0];
+        assert!(!TestTree::verify_proof(
+            &crs,
+            &root,
+            &claims,
+            &bad_proof,
+            ExtensionEncoding::Generic
+        )
+        .unwrap());
+    }
 }
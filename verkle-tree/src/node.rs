@@ -2,49 +2,142 @@ use std::{
     collections::{hash_map::Entry, HashMap},
     fmt::Debug,
     hash::Hash,
+    sync::Arc,
 };
 
+use ark_serialize::SerializationError;
 use bytemuck::Pod;
 use num::{One, Zero};
+use rayon::prelude::*;
 use thiserror::Error;
-use vector_commit::{VCCommitment, VCData, VectorCommitment};
+use vector_commit::{ItemChange, VCCommitment, VCData, VCUniversalParams, VectorCommitment};
 
-use crate::{Key, KeyMethods, SplittableValue};
+use crate::{ExtensionEncoding, Key, KeyMethods, SplittableValue};
 
 #[derive(Error, Debug)]
-pub(crate) enum VerkleError {
+pub enum VerkleError {
     #[error("Invalid path requested")]
     InvalidPath,
+
+    #[error("Vector commitment error: {0}")]
+    CommitmentError(String),
+
+    #[error("Width {0} is not even, smaller than 4 or larger than the key unit type can index")]
+    InvalidWidth(usize),
+
+    #[error(
+        "Tree width {width} differs from the {max_size} items the commitment parameters support"
+    )]
+    WidthMismatch { width: usize, max_size: usize },
+
+    #[error("Key unit {unit} is outside of the tree width {width}")]
+    UnitOutOfRange { unit: usize, width: usize },
+
+    #[error("Snapshot serialization error: {0}")]
+    SerializationError(#[from] SerializationError),
+
+    #[error("Invalid snapshot: {0}")]
+    InvalidSnapshot(String),
+
+    #[error("Node store IO error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Node store error: {0}")]
+    StoreError(String),
+
+    #[error("Checkpoint {0} is not open")]
+    UnknownCheckpoint(usize),
+
+    #[error("The proof does not prove the claimed keys")]
+    InvalidProof,
+
+    #[error("The key is not proven by the proof the partial tree was built from")]
+    UnprovenKey,
+
+    #[error("The subtree at path {0:?} is pruned")]
+    PrunedPath(Vec<usize>),
+
+    #[error("Cannot prune the subtree: {0}")]
+    PruneError(String),
+
+    #[error("Depth {0} is too deep for the status byte of a proof")]
+    ProofDepthOverflow(usize),
+
+    #[error("Invalid execution witness: {0}")]
+    InvalidWitness(String),
 }
 
+/// The path to a stem: for each internal node, the prefix of the key at the node, the index of the next
+/// node on the path and a reference to the internal node itself
+pub(crate) type NodePath<'a, const N: usize, K, VC, T> = Vec<(Vec<K>, K, &'a Node<N, K, VC, T>)>;
+
+/// A stem and the `(unit, value)` leaves to insert into its extension node
+pub(crate) type StemValues<const N: usize, K, T> = (Key<N, K>, Vec<(K, T)>);
+
+/// Once more than `1 / FULL_COMMIT_RATIO` of a vector's items changed, committing to the whole vector again is
+/// cheaper than applying the difference of every changed item
+const FULL_COMMIT_RATIO: usize = 4;
+
 /// The Node provides the recursive structure of the Verkle Tree.
 ///
-/// Both node types store their optional cached commitments.
+/// Both node types store their optional cached commitments. Extension nodes additionally cache the
+/// commitments to their two halves of leaves (`c1` and `c2`).
+///
+/// Once generated, a cached commitment is kept when the node changes, and the children or leaves it committed to
+/// are recorded in `changed` instead. Regenerating the commitment then only applies the difference of the changed items.
+///
+/// Children are reference counted, so that versions of a tree share the subtrees they have in common. A node
+/// shared with another version is copied before it changes (see `Arc::make_mut`), along with the path to it.
+///
+/// Children of an internal node may only be held by the tree's `NodeStore`, in which case just their commitments
+/// are kept in `unloaded`. The path to a stem must be loaded (see `load_path`) before it is traversed.
+/// `persisted` is set while the record of the node in the store is up to date.
+///
+/// A pruned subtree is replaced by a stub, which only keeps the commitment of the subtree's root, so that its
+/// parent can still be committed to. The stub of an extension node keeps its stem as well, which proves that
+/// other stems are not below it.
 /// The below table provides some more information
 /// on the node types:
 /// | Type      | Description |
 /// |-----------|-------------|
-/// | Internal  | Stores children that are other Internal OR Extension nodes |
+/// | Internal  | Stores children that are other Internal, Extension OR Stub nodes |
 /// | Extension | Stores the actual data of the tree. Additionally stores the stem that all leaves of this node share |
+/// | Stub      | Stores the commitment of a pruned subtree, and the stem of a pruned extension node |
 ///
 /// Methods are implemented for:
 /// - Storing and retrieving values
 /// - Grabbing commitments
 ///
 /// All of these methods are called recursively on children when required
-#[derive(Clone)]
 pub(crate) enum Node<const N: usize, K, VC, T>
 where
     VC: VectorCommitment,
 {
     Internal {
         commit: Option<VC::Commitment>,
-        children: HashMap<K, Node<N, K, VC, T>>,
+        children: HashMap<K, Arc<Node<N, K, VC, T>>>,
+        /// The commitments of the children that are only held by the store
+        unloaded: HashMap<K, VC::Commitment>,
+        /// The item of each child slot that changed since `commit` was generated, as it was committed to
+        changed: HashMap<K, <VC::Data as VCData>::Item>,
+        persisted: bool,
     },
     Extension {
         stem: Key<N, K>, // TODO: Each stem has an extra unit because we cannot do N-1
         commit: Option<VC::Commitment>,
+        c1: Option<VC::Commitment>,
+        c2: Option<VC::Commitment>,
         leaves: HashMap<K, T>, // Sparse implementation...but at what cost :(
+        /// The value of each leaf that changed since `commit` was generated, as it was committed to (`None` if the
+        /// leaf was missing). The encoding of the values into items is only known when committing
+        changed: HashMap<K, Option<T>>,
+        persisted: bool,
+    },
+    Stub {
+        commit: VC::Commitment,
+        /// The stem of the pruned node, if it is an extension node
+        stem: Option<Key<N, K>>,
+        persisted: bool,
     },
 }
 
@@ -59,27 +152,49 @@ where
         Self::Extension {
             stem,
             commit: None,
+            c1: None,
+            c2: None,
             leaves: values.into_iter().collect(),
+            changed: HashMap::new(),
+            persisted: false,
         }
     }
 
     pub(crate) fn new_internal(nodes: Vec<(K, Self)>) -> Self {
         Self::Internal {
             commit: None,
-            children: nodes.into_iter().map(|v| (v.0, v.1)).collect(),
+            children: nodes.into_iter().map(|v| (v.0, Arc::new(v.1))).collect(),
+            unloaded: HashMap::new(),
+            changed: HashMap::new(),
+            persisted: false,
         }
     }
 
-    /// Get the extension node equal to this stem. If it does not exist, return None
-    pub(crate) fn get_stem(&self, stem: &Key<N, K>, mut cur_depth: usize) -> Option<&Self> {
+    /// Marks whether the record of this node in the store is up to date
+    pub(crate) fn set_persisted(&mut self, value: bool) {
+        match self {
+            Self::Internal { persisted, .. } => *persisted = value,
+            Self::Extension { persisted, .. } => *persisted = value,
+            Self::Stub { persisted, .. } => *persisted = value,
+        }
+    }
+
+    /// Get the extension node equal to this stem. If it does not exist, return None.
+    ///
+    /// Errors with `PrunedPath` if the stem may be below a pruned subtree.
+    pub(crate) fn get_stem(
+        &self,
+        stem: &Key<N, K>,
+        mut cur_depth: usize,
+    ) -> Result<Option<&Self>, VerkleError> {
         match self {
             Self::Extension {
                 stem: self_stem, ..
             } => {
                 if stem == self_stem {
-                    Some(self)
+                    Ok(Some(self))
                 } else {
-                    None
+                    Ok(None)
                 }
             }
             Self::Internal { children, .. } => match children.get(&stem[cur_depth]) {
@@ -87,12 +202,20 @@ where
                     cur_depth += 1;
                     c.get_stem(stem, cur_depth)
                 }
-                None => None,
+                None => Ok(None),
             },
+            Self::Stub {
+                stem: Some(self_stem),
+                ..
+            } if stem != self_stem => Ok(None),
+            Self::Stub { .. } => Err(pruned_path(&stem[0..cur_depth])),
         }
     }
 
-    /// Recursively finds the path that must be taken in the tree to get the `stem`
+    /// Recursively finds the path that must be taken in the tree to get the `stem`. The path ends at the
+    /// internal node whose child at the stem's unit is either an extension node (which may not share the stem),
+    /// or missing. Errors with `PrunedPath` if the path reaches a pruned subtree, as the nodes below it are
+    /// not known.
     ///
     /// Each node in the path will store:
     /// - The prefix of the key at the node
@@ -101,20 +224,41 @@ where
     pub(crate) fn path_to_stem<'a>(
         &'a self,
         stem: &Key<N, K>,
-        path: &mut Vec<(Vec<K>, K, &'a Self)>,
+        path: &mut NodePath<'a, N, K, VC, T>,
     ) -> Result<(), VerkleError> {
         match self {
             Self::Extension { .. } => Ok(()),
 
             Self::Internal { children, .. } => {
                 let depth = path.len();
-                if let Some(child) = children.get(&stem[depth]) {
-                    path.push((stem[0..depth + 1].to_vec(), stem[depth], self));
-                    child.path_to_stem(stem, path)
-                } else {
-                    Err(VerkleError::InvalidPath)
+                path.push((stem[0..depth + 1].to_vec(), stem[depth], self));
+                match children.get(&stem[depth]) {
+                    Some(child) => child.path_to_stem(stem, path),
+                    None => Ok(()),
                 }
             }
+
+            Self::Stub { .. } => Err(pruned_path(&stem[0..path.len()])),
+        }
+    }
+
+    /// Gets the child of an internal node at `unit`
+    ///
+    /// ! Panics if called on an extension node
+    pub(crate) fn get_child(&self, unit: K) -> Option<&Self> {
+        match self {
+            Self::Internal { children, .. } => children.get(&unit).map(Arc::as_ref),
+            _ => panic!("Called get_child on non-internal node"),
+        }
+    }
+
+    /// Gets the stem of an extension node
+    ///
+    /// ! Panics if called on an internal node
+    pub(crate) fn get_extension_stem(&self) -> &Key<N, K> {
+        match self {
+            Self::Extension { stem, .. } => stem,
+            _ => panic!("Called get_extension_stem on non-extension node"),
         }
     }
 
@@ -129,154 +273,850 @@ where
     }
 
     /// Recursively insert the `values` into an extension node that has its stem equal to `stem`
-    /// This function will **clear the stored commitment** of all touched nodes, as they are no longer valid
+    /// This function will **record the changed items** of all touched nodes, as their commitments are no longer valid
+    ///
+    /// The stub of a pruned extension node with another stem moves to a deeper path like the extension would.
+    ///
+    /// ! Panics if the stem is below a pruned subtree (see `get_stem`)
     pub(crate) fn insert(&mut self, stem: Key<N, K>, values: Vec<(K, T)>, cur_depth: usize) {
         match self {
             Self::Extension {
                 stem: self_stem,
                 commit,
                 leaves,
+                changed,
+                persisted,
+                ..
             } => {
                 // This function should only ever be called on an Extension node to insert values. I.e internal nodes
                 // will never call into here. Therefore self_stem should ALWAYS be equal to stem
                 if self_stem != &stem {
                     panic!("Traversed to extension node with differing stem");
                 }
-                *commit = None;
+                *persisted = false;
                 values.into_iter().for_each(|v| {
+                    Self::record_leaf_change(commit, leaves, changed, v.0);
                     leaves.insert(v.0, v.1);
                 });
             }
 
             // If we are an internal node, then we will be inserting the value into a child node
             Self::Internal {
-                commit, children, ..
+                commit,
+                children,
+                changed,
+                persisted,
+                ..
             } => {
-                // Set commit to None as the commit is no longer accurate
-                *commit = None;
                 let k = stem[cur_depth];
+                *persisted = false;
+                // The commit is no longer accurate for the child at `k`
+                Self::record_child_change(commit, children, changed, k);
                 let child = children.entry(k);
                 match child {
                     // If we have a child that matches on the current unit of the key
                     Entry::Occupied(mut o) => {
-                        let child_as_node = o.get_mut();
-                        match child_as_node {
+                        match o.get().as_ref() {
                             // If the child is an extension node, then we will either insert into it if the stem's match,
                             // or we must create a new branch resulting in a new inner node containing two extension nodes
                             Self::Extension {
                                 stem: child_stem, ..
+                            }
+                            | Self::Stub {
+                                stem: Some(child_stem),
+                                ..
                             } => {
                                 // The stems match, and therefore simply insert into the extension node
                                 if &stem == child_stem || cur_depth == N - 2 {
-                                    child_as_node.insert(stem, values, cur_depth + 1);
+                                    Arc::make_mut(o.get_mut()).insert(stem, values, cur_depth + 1);
 
                                 // The stems differ, so a new inner node is created with the children hashmap key
                                 // equal to the differing unit
                                 } else {
                                     let depth = child_stem.next_diff_depth(&stem, cur_depth);
+                                    let child_unit = child_stem[depth];
+                                    // The existing extension moves to a deeper path
+                                    let mut moved = Arc::unwrap_or_clone(o.remove());
+                                    moved.set_persisted(false);
                                     let nodes = vec![
                                         (stem[depth], Self::new_extension(stem, values)),
-                                        (child_stem[depth], o.remove()),
+                                        (child_unit, moved),
                                     ];
 
-                                    let new_internal = Self::new_internal(nodes);
-                                    children.insert(k, new_internal);
+                                    // Every unit the two stems share past this depth requires its own internal node
+                                    let mut new_internal = Self::new_internal(nodes);
+                                    for d in (cur_depth + 1..depth).rev() {
+                                        new_internal =
+                                            Self::new_internal(vec![(stem[d], new_internal)]);
+                                    }
+                                    children.insert(k, Arc::new(new_internal));
                                 }
                             }
-                            Self::Internal { .. } => {
-                                child_as_node.insert(stem, values, cur_depth + 1);
+                            Self::Internal { .. } | Self::Stub { .. } => {
+                                Arc::make_mut(o.get_mut()).insert(stem, values, cur_depth + 1);
                             }
                         }
                     }
                     Entry::Vacant(v) => {
-                        v.insert(Self::new_extension(stem, values));
+                        v.insert(Arc::new(Self::new_extension(stem, values)));
+                    }
+                }
+            }
+
+            Self::Stub { .. } => panic!("Traversed to a pruned subtree"),
+        }
+    }
+
+    /// Insert the values of many stems into the children of this internal node. `stems` must be sorted, so that
+    /// all stems sharing a child are adjacent.
+    ///
+    /// Stems under different children touch disjoint subtrees, so each child is taken out of the node, has its
+    /// stems inserted in parallel with the other children, and is then put back.
+    ///
+    /// ! Panics if called on an extension node
+    pub(crate) fn insert_sorted_stems(&mut self, stems: Vec<StemValues<N, K, T>>)
+    where
+        K: Send + Sync,
+        T: Send + Sync,
+        VC::Commitment: Send + Sync,
+        <VC::Data as VCData>::Item: Send + Sync,
+    {
+        let (commit, children, changed) = match self {
+            Self::Internal {
+                commit,
+                children,
+                changed,
+                persisted,
+                ..
+            } => {
+                *persisted = false;
+                (commit, children, changed)
+            }
+            _ => panic!("Called insert_sorted_stems on non-internal node"),
+        };
+
+        let mut subtrees = Vec::<(K, Option<Arc<Self>>, Vec<_>)>::new();
+        for (stem, values) in stems {
+            match subtrees.last_mut() {
+                Some((k, _, group)) if *k == stem[0] => group.push((stem, values)),
+                _ => {
+                    Self::record_child_change(commit, children, changed, stem[0]);
+                    subtrees.push((stem[0], children.remove(&stem[0]), vec![(stem, values)]));
+                }
+            }
+        }
+
+        let updated: Vec<(K, Arc<Self>)> = subtrees
+            .into_par_iter()
+            .map(|(k, child, group)| {
+                // Inserting through a temporary parent reuses the splitting logic of `insert` for the child
+                let mut parent = Self::new_internal(
+                    child
+                        .map(|c| vec![(k, Arc::unwrap_or_clone(c))])
+                        .unwrap_or_default(),
+                );
+                for (stem, values) in group {
+                    parent.insert(stem, values, 0);
+                }
+                match parent {
+                    Self::Internal { mut children, .. } => (k, children.remove(&k).unwrap()),
+                    _ => unreachable!(),
+                }
+            })
+            .collect();
+
+        children.extend(updated);
+    }
+
+    /// Recursively remove the leaf at `unit` from the extension node with stem `stem`, returning its value.
+    /// This function will **record the changed items** of all nodes on the path if a leaf was removed.
+    ///
+    /// Extension nodes left without leaves are dropped, and an internal child left with a single extension
+    /// node as its only child is collapsed into its place. This keeps the tree in the same shape as if the
+    /// leaf had never been inserted. The paths of all nodes dropped or moved are pushed to `removed`, so that
+    /// their records can be deleted from the store. The only other child of every internal node on the path
+    /// must be loaded. The stub of a pruned extension node is collapsed like the extension would be, while a stem
+    /// below a pruned subtree is not found.
+    pub(crate) fn remove(
+        &mut self,
+        stem: &Key<N, K>,
+        unit: K,
+        cur_depth: usize,
+        removed: &mut Vec<Vec<K>>,
+    ) -> Option<T> {
+        match self {
+            Self::Extension {
+                stem: self_stem,
+                commit,
+                leaves,
+                changed,
+                persisted,
+                ..
+            } => {
+                if self_stem != stem || !leaves.contains_key(&unit) {
+                    return None;
+                }
+                Self::record_leaf_change(commit, leaves, changed, unit);
+                *persisted = false;
+
+                leaves.remove(&unit)
+            }
+
+            Self::Stub { .. } => None,
+
+            Self::Internal {
+                commit,
+                children,
+                changed,
+                persisted,
+                ..
+            } => {
+                let k = stem[cur_depth];
+                let child = Arc::make_mut(children.get_mut(&k)?);
+                let old_commit = child.commitment().cloned();
+                let removed_value = child.remove(stem, unit, cur_depth + 1, removed)?;
+                *persisted = false;
+                if commit.is_some() {
+                    changed
+                        .entry(k)
+                        .or_insert_with(|| Self::commitment_item(old_commit.as_ref()));
+                }
+
+                let path = || stem[0..=cur_depth].to_vec();
+                match child {
+                    Self::Extension { leaves, .. } if leaves.is_empty() => {
+                        children.remove(&k);
+                        removed.push(path());
                     }
+                    Self::Internal {
+                        children: grandchildren,
+                        unloaded,
+                        ..
+                    } if unloaded.is_empty() => {
+                        if grandchildren.is_empty() {
+                            children.remove(&k);
+                            removed.push(path());
+                        } else if grandchildren.len() == 1
+                            && matches!(
+                                grandchildren.values().next().map(Arc::as_ref),
+                                Some(Self::Extension { .. } | Self::Stub { stem: Some(_), .. })
+                            )
+                        {
+                            let (u, mut extension) = grandchildren.drain().next().unwrap();
+                            Arc::make_mut(&mut extension).set_persisted(false);
+                            let mut moved_from = path();
+                            moved_from.push(u);
+                            removed.push(moved_from);
+                            children.insert(k, extension);
+                        }
+                    }
+                    _ => {}
                 }
+
+                Some(removed_value)
             }
         }
     }
 
-    /// Generates a commitment recursively. If all of a Node's children have `Some` commitment,
-    /// then there is no need to recurse further.
+    /// Records the item of the child slot `k` that `commit` was generated with, before the child changes.
+    /// Only the first change since the commitment was generated is recorded, and nothing is recorded if
+    /// there is no commitment to update.
+    fn record_child_change(
+        commit: &Option<VC::Commitment>,
+        children: &HashMap<K, Arc<Self>>,
+        changed: &mut HashMap<K, <VC::Data as VCData>::Item>,
+        k: K,
+    ) {
+        if commit.is_some() {
+            changed.entry(k).or_insert_with(|| {
+                Self::commitment_item(children.get(&k).and_then(|c| c.commitment()))
+            });
+        }
+    }
+
+    /// Records the value of the leaf at `unit` that `commit` was generated with, before the leaf changes.
+    /// Follows the same rules as `record_child_change`.
+    fn record_leaf_change(
+        commit: &Option<VC::Commitment>,
+        leaves: &HashMap<K, T>,
+        changed: &mut HashMap<K, Option<T>>,
+        unit: K,
+    ) {
+        if commit.is_some() {
+            changed
+                .entry(unit)
+                .or_insert_with(|| leaves.get(&unit).cloned());
+        }
+    }
+
+    /// The item a child slot commits to. Missing children are zero
+    fn commitment_item(commit: Option<&VC::Commitment>) -> <VC::Data as VCData>::Item {
+        commit.map_or(<VC::Data as VCData>::Item::zero(), |c| c.to_data_item())
+    }
+
+    /// Returns the cached commitment of this node, if it has been generated
+    pub(crate) fn commitment(&self) -> Option<&VC::Commitment> {
+        match self {
+            Self::Internal { commit, .. } => commit.as_ref(),
+            Self::Extension { commit, .. } => commit.as_ref(),
+            Self::Stub { commit, .. } => Some(commit),
+        }
+    }
+
+    /// Returns the cached commitment of this node if it has no changes since it was generated
+    pub(crate) fn clean_commitment(&self) -> Option<&VC::Commitment> {
+        match self {
+            Self::Internal { changed, .. } if !changed.is_empty() => None,
+            Self::Extension { changed, .. } if !changed.is_empty() => None,
+            _ => self.commitment(),
+        }
+    }
+
+    /// Returns the cached commitment to one half of an extension node's leaves (`0` for `c1`, `1` for `c2`)
+    ///
+    /// ! Panics if called on an internal node
+    pub(crate) fn suffix_commitment(&self, half: usize) -> Option<&VC::Commitment> {
+        match self {
+            Self::Extension { c1, c2, .. } => {
+                if half == 0 {
+                    c1.as_ref()
+                } else {
+                    c2.as_ref()
+                }
+            }
+            _ => panic!("Called suffix_commitment on non-extension node"),
+        }
+    }
+
+    /// Returns the vector of `width` items this node commits to. The commitments of all children
+    /// (including the suffix commitments of an extension) must have already been generated.
+    pub(crate) fn commitment_values(
+        &self,
+        width: usize,
+        encoding: ExtensionEncoding,
+    ) -> Vec<<VC::Data as VCData>::Item> {
+        match self {
+            Self::Internal {
+                children, unloaded, ..
+            } => Self::internal_values(children, unloaded, width),
+            Self::Extension { stem, c1, c2, .. } => Self::extension_values(
+                stem,
+                c1.as_ref().expect("c1 has not been generated"),
+                c2.as_ref().expect("c2 has not been generated"),
+                width,
+                encoding,
+            ),
+            Self::Stub { .. } => panic!("Called commitment_values on a pruned node"),
+        }
+    }
+
+    /// Returns the vector of `width` items that one half of an extension node's leaves commit to
+    ///
+    /// ! Panics if called on an internal node
+    pub(crate) fn suffix_values(
+        &self,
+        half: usize,
+        width: usize,
+        encoding: ExtensionEncoding,
+    ) -> Vec<<VC::Data as VCData>::Item> {
+        match self {
+            Self::Extension { leaves, .. } => Self::leaf_values(leaves, half, width, encoding),
+            _ => panic!("Called suffix_values on non-extension node"),
+        }
+    }
+
+    /// Each child's commitment is placed at the index of its unit
+    fn internal_values(
+        children: &HashMap<K, Arc<Self>>,
+        unloaded: &HashMap<K, VC::Commitment>,
+        width: usize,
+    ) -> Vec<<VC::Data as VCData>::Item> {
+        let mut values = vec![<VC::Data as VCData>::Item::zero(); width];
+        for (&k, child) in children.iter() {
+            values[k.into()] = child
+                .commitment()
+                .expect("Child commitment has not been generated")
+                .to_data_item();
+        }
+        for (&k, commit) in unloaded.iter() {
+            values[k.into()] = commit.to_data_item();
+        }
+
+        values
+    }
+
+    /// The `[1, stem, c1, c2]` vector, padded with zeroes to `width`
+    fn extension_values(
+        stem: &Key<N, K>,
+        c1: &VC::Commitment,
+        c2: &VC::Commitment,
+        width: usize,
+        encoding: ExtensionEncoding,
+    ) -> Vec<<VC::Data as VCData>::Item> {
+        let mut values = vec![<VC::Data as VCData>::Item::zero(); width];
+        values[0] = <VC::Data as VCData>::Item::one();
+        values[1] = encoding.stem_item::<VC::Data, N, K>(stem);
+        values[2] = c1.to_data_item();
+        values[3] = c2.to_data_item();
+
+        values
+    }
+
+    /// The leaves in the `half` of the extension (the first half of units for `0`, the second for `1`),
+    /// each encoded as its lower and upper items at index `2*unit` and `2*unit + 1` (modulo `width`)
+    fn leaf_values(
+        leaves: &HashMap<K, T>,
+        half: usize,
+        width: usize,
+        encoding: ExtensionEncoding,
+    ) -> Vec<<VC::Data as VCData>::Item> {
+        let mut values = vec![<VC::Data as VCData>::Item::zero(); width];
+        for (&index, leaf) in leaves.iter() {
+            let (low_index, high_index) = leaf_indexes(index.into(), width);
+            if suffix_half(index.into(), width) == half {
+                let (low, high) = encoding.leaf_items::<VC::Data, T>(Some(leaf));
+                values[low_index] = low;
+                values[high_index] = high;
+            }
+        }
+
+        values
+    }
+
+    /// Generates a commitment recursively. If a Node has a cached commitment without recorded changes,
+    /// then there is no need to recurse further. Every node commits to a vector as wide as the `crs`.
     ///
     /// An Internal node will simply convert all children commitments to data items, and commit to that array
     ///
     /// The Extension node will generate commitments according to Ethereum's standard. This entails the following:
-    /// 1. All leaves are split into their upper and lower halves (from `SplittableValue`)
-    /// 2. The first half of leaves are committed to by `c1` with upper half preceding the lower half
+    /// 1. All leaves are split into their upper and lower halves (from `SplittableValue`), as
+    ///    specified by the `encoding`
+    /// 2. The first half of leaves are committed to by `c1` with lower half preceding the upper half
     ///     - E.g a leaf at index 1 will set the `VC::Data` array with [..., leaf_1_low, leaf_1_upper, ...]
     /// 3. The upper half of leaves commit to `c2` using the same rules as above
     /// 4. `c1` and `c2` are encoded as `VC::Data::Item`s
     /// 5. The stem is encoded as a data item
     /// 6. Commit to the 4 data item array: `[1, stem, c1, c2]`
+    ///
+    /// A node with a cached commitment only regenerates the children in its changed slots, and updates its
+    /// commitment by the changed items (see `VectorCommitment::update_commitment`).
+    ///
+    /// The subtrees of an internal node are independent, so its children are committed to in parallel before
+    /// the node's own commitment is generated from them. Children with an up to date commitment are left as they
+    /// are, so they stay shared with other versions of the tree.
+    ///
+    /// In a `partial` tree (see `PartialVerkleTree`), a node with a commitment may not hold all of its children or
+    /// leaves, so its commitment is always updated by the changed items, and never committed to again.
     pub(crate) fn gen_commitment(
         &mut self,
         crs: &VC::UniversalParams,
-    ) -> Result<&VC::Commitment, VC::Error> {
+        encoding: ExtensionEncoding,
+        partial: bool,
+    ) -> Result<&VC::Commitment, VerkleError>
+    where
+        K: Send + Sync,
+        T: Send + Sync,
+        VC::Commitment: Send + Sync,
+        VC::UniversalParams: Sync,
+        VC::Error: Send,
+        <VC::Data as VCData>::Item: Send + Sync,
+    {
+        let width = crs.max_size();
         match self {
             Self::Extension {
                 stem,
                 commit,
+                c1,
+                c2,
                 leaves,
+                changed,
+                persisted,
             } => {
-                if commit.is_some() {
+                if commit.is_some() && changed.is_empty() {
                     return Ok(commit.as_ref().unwrap());
                 }
+                // The record of the node in the store includes its commitments
+                *persisted = false;
 
-                let mut c1_values = [<VC::Data as VCData>::Item::zero(); N];
-                let mut c2_values = [<VC::Data as VCData>::Item::zero(); N];
+                // Only the suffix commitments of the halves with changed leaves need to be updated
+                let mut halves = [false, false];
+                for &unit in changed.keys() {
+                    halves[suffix_half(unit.into(), width)] = true;
+                }
+                let updatable = [c1.is_some(), c2.is_some()]
+                    .into_iter()
+                    .zip(halves)
+                    .all(|(present, changed)| present || !changed);
 
-                for (&index, leaf) in leaves.iter() {
-                    let (low, high) = leaf.split();
-                    let index_low: usize = (2 * index.into()) % N;
-                    let index_high: usize = (2 * index.into() + 1) % N;
+                match commit.as_mut() {
+                    Some(commit) if updatable => {
+                        let mut suffix_changes = vec![];
+                        for (half, c) in [&mut *c1, &mut *c2].into_iter().enumerate() {
+                            let c = match c {
+                                Some(c) if halves[half] => c,
+                                _ => continue,
+                            };
+                            let changes: Vec<ItemChange<VC::Data>> = changed
+                                .iter()
+                                .filter(|(&unit, _)| suffix_half(unit.into(), width) == half)
+                                .flat_map(|(&unit, old)| {
+                                    let (low_index, high_index) = leaf_indexes(unit.into(), width);
+                                    let (old_low, old_high) =
+                                        encoding.leaf_items::<VC::Data, T>(old.as_ref());
+                                    let (low, high) =
+                                        encoding.leaf_items::<VC::Data, T>(leaves.get(&unit));
+                                    [(low_index, old_low, low), (high_index, old_high, high)]
+                                })
+                                .collect();
+                            let old_suffix = c.to_data_item();
+                            *c = Self::apply_changes(crs, c, &changes, partial, || {
+                                Self::leaf_values(leaves, half, width, encoding)
+                            })?;
+                            suffix_changes.push((2 + half, old_suffix, c.to_data_item()));
+                        }
+
+                        *commit =
+                            Self::apply_changes(crs, commit, &suffix_changes, partial, || {
+                                Self::extension_values(
+                                    stem,
+                                    c1.as_ref().unwrap(),
+                                    c2.as_ref().unwrap(),
+                                    width,
+                                    encoding,
+                                )
+                            })?;
+                    }
+                    Some(_) if partial => {
+                        return Err(VerkleError::CommitmentError(
+                            "A changed leaf of a partial tree has no suffix commitment".to_string(),
+                        ))
+                    }
+                    _ => {
+                        for (half, c) in [&mut *c1, &mut *c2].into_iter().enumerate() {
+                            let values = Self::leaf_values(leaves, half, width, encoding);
+                            *c = Some(
+                                VC::commit(crs, &<VC::Data as VCData>::from_vec(values))
+                                    .map_err(|e| VerkleError::CommitmentError(e.to_string()))?,
+                            );
+                        }
 
-                    if index.into() < (N / 2) {
-                        c1_values[index_low] = low;
-                        c1_values[index_high] = high;
-                    } else {
-                        c2_values[index_low] = low;
-                        c2_values[index_high] = high;
+                        let extension_data = Self::extension_values(
+                            stem,
+                            c1.as_ref().unwrap(),
+                            c2.as_ref().unwrap(),
+                            width,
+                            encoding,
+                        );
+                        let c = VC::commit(crs, &<VC::Data as VCData>::from_vec(extension_data))
+                            .map_err(|e| VerkleError::CommitmentError(e.to_string()))?;
+                        *commit = Some(c);
                     }
                 }
+                changed.clear();
+
+                Ok(commit.as_ref().unwrap())
+            }
+            Self::Internal {
+                commit,
+                children,
+                unloaded,
+                changed,
+                persisted,
+            } => {
+                if commit.is_some() && changed.is_empty() {
+                    return Ok(commit.as_ref().unwrap());
+                }
+                *persisted = false;
 
-                let c1 = VC::commit(crs, &<VC::Data as VCData>::from_vec(c1_values.to_vec()))?;
-                let c2 = VC::commit(crs, &<VC::Data as VCData>::from_vec(c2_values.to_vec()))?;
+                match commit {
+                    Some(c) => {
+                        // Only the children in changed slots can have changed since the commitment was generated
+                        let dirty: Vec<&mut Self> = children
+                            .iter_mut()
+                            .filter(|(k, child)| {
+                                changed.contains_key(k) && child.clean_commitment().is_none()
+                            })
+                            .map(|(_, child)| Arc::make_mut(child))
+                            .collect();
+                        dirty.into_par_iter().try_for_each(|child| {
+                            child.gen_commitment(crs, encoding, partial).map(|_| ())
+                        })?;
 
-                let extension_data = vec![
-                    <VC::Data as VCData>::Item::one(),
-                    <VC::Data as VCData>::bytes_to_item(&stem.to_bytes()),
-                    c1.to_data_item(),
-                    c2.to_data_item(),
-                ];
+                        let changes: Vec<ItemChange<VC::Data>> = changed
+                            .drain()
+                            .map(|(k, old)| {
+                                // The child may have been unloaded since it was committed to
+                                let new = Self::commitment_item(
+                                    children
+                                        .get(&k)
+                                        .and_then(|c| c.commitment())
+                                        .or_else(|| unloaded.get(&k)),
+                                );
+                                (k.into(), old, new)
+                            })
+                            .collect();
+                        *c = Self::apply_changes(crs, c, &changes, partial, || {
+                            Self::internal_values(children, unloaded, width)
+                        })?;
+                    }
+                    None => {
+                        children
+                            .par_iter_mut()
+                            .filter(|(_, child)| child.clean_commitment().is_none())
+                            .try_for_each(|(_, child)| {
+                                Arc::make_mut(child)
+                                    .gen_commitment(crs, encoding, partial)
+                                    .map(|_| ())
+                            })?;
 
-                let c = VC::commit(crs, &<VC::Data as VCData>::from_vec(extension_data))?;
-                *commit = Some(c);
+                        let vc_data = <VC::Data as VCData>::from_vec(Self::internal_values(
+                            children, unloaded, width,
+                        ));
+                        *commit = Some(
+                            VC::commit(crs, &vc_data)
+                                .map_err(|e| VerkleError::CommitmentError(e.to_string()))?,
+                        );
+                        changed.clear();
+                    }
+                }
 
                 Ok(commit.as_ref().unwrap())
             }
-            Self::Internal { commit, children } => {
-                if commit.is_some() {
-                    return Ok(commit.as_ref().unwrap());
-                }
+            Self::Stub { commit, .. } => Ok(commit),
+        }
+    }
+
+    /// Checks that every cached commitment of this node and its descendants commits to the vector built from its
+    /// children or leaves. Nodes with changes since their commitment was generated are not checked, as they are
+    /// committed to again anyway, but a node with a checked commitment must not have a child without one.
+    /// The commitments of pruned subtrees cannot be checked, so they are trusted.
+    pub(crate) fn check_commitments(
+        &self,
+        crs: &VC::UniversalParams,
+        encoding: ExtensionEncoding,
+    ) -> Result<bool, VC::Error> {
+        let width = crs.max_size();
+        let commits_to = |c: &VC::Commitment, values| -> Result<bool, VC::Error> {
+            Ok(VC::commit(crs, &<VC::Data as VCData>::from_vec(values))? == *c)
+        };
 
-                // HACK FIXME THIS IS HARDCODED NEED TO FIX
-                let mut vc_vec = vec![<VC::Data as VCData>::Item::zero(); 256];
-                for (&k, child) in children.iter_mut() {
-                    let cc = child.gen_commitment(crs)?;
-                    vc_vec[k.into()] = cc.to_data_item();
+        match self {
+            Self::Internal {
+                commit,
+                children,
+                unloaded,
+                changed,
+                ..
+            } => {
+                for child in children.values() {
+                    if !child.check_commitments(crs, encoding)? {
+                        return Ok(false);
+                    }
+                }
+                match commit {
+                    Some(c) if changed.is_empty() => {
+                        if children.values().any(|child| child.commitment().is_none()) {
+                            return Ok(false);
+                        }
+                        commits_to(c, Self::internal_values(children, unloaded, width))
+                    }
+                    _ => Ok(true),
                 }
+            }
+            Self::Extension {
+                stem,
+                commit,
+                c1,
+                c2,
+                leaves,
+                changed,
+                ..
+            } => match (commit, c1, c2) {
+                (Some(c), Some(c1), Some(c2)) if changed.is_empty() => {
+                    Ok(
+                        commits_to(c1, Self::leaf_values(leaves, 0, width, encoding))?
+                            && commits_to(c2, Self::leaf_values(leaves, 1, width, encoding))?
+                            && commits_to(
+                                c,
+                                Self::extension_values(stem, c1, c2, width, encoding),
+                            )?,
+                    )
+                }
+                _ => Ok(true),
+            },
+            Self::Stub { .. } => Ok(true),
+        }
+    }
 
-                let vc_data = <VC::Data as VCData>::from_vec(vc_vec);
-                let c = VC::commit(crs, &vc_data)?;
-                *commit = Some(c);
+    /// Drops the cached commitments of this node and its loaded descendants, so they are all generated again.
+    /// Children that are not loaded keep the commitments they were stored with, as do pruned subtrees.
+    pub(crate) fn clear_commitments(&mut self) {
+        match self {
+            Self::Internal {
+                commit,
+                children,
+                changed,
+                persisted,
+                ..
+            } => {
+                *commit = None;
+                *persisted = false;
+                changed.clear();
+                children
+                    .values_mut()
+                    .for_each(|c| Arc::make_mut(c).clear_commitments());
+            }
+            Self::Extension {
+                commit,
+                c1,
+                c2,
+                changed,
+                persisted,
+                ..
+            } => {
+                *persisted = false;
+                *commit = None;
+                *c1 = None;
+                *c2 = None;
+                changed.clear();
+            }
+            Self::Stub { .. } => {}
+        }
+    }
 
-                Ok(commit.as_ref().unwrap())
+    /// Replaces the node at `path` below this internal node at `depth` with a stub of its commitment (and stem, if
+    /// it is an extension node), returning the pruned node. The nodes on the path are written to the store again.
+    ///
+    /// Errors if there is no loaded node at `path`, or it changed since its commitment was generated.
+    pub(crate) fn prune(&mut self, path: &[K], depth: usize) -> Result<Arc<Self>, VerkleError> {
+        let children = match self {
+            Self::Internal { children, .. } => children,
+            _ => return Err(VerkleError::InvalidPath),
+        };
+        let child = children
+            .get_mut(&path[depth])
+            .ok_or(VerkleError::InvalidPath)?;
+        if depth + 1 < path.len() {
+            if !matches!(child.as_ref(), Self::Internal { .. }) {
+                return Err(VerkleError::InvalidPath);
             }
+            let pruned = Arc::make_mut(child).prune(path, depth + 1)?;
+            self.set_persisted(false);
+            return Ok(pruned);
         }
+
+        let commit = child.clean_commitment().cloned().ok_or_else(|| {
+            VerkleError::PruneError(
+                "The subtree changed since its commitment was generated".to_string(),
+            )
+        })?;
+        let stem = match child.as_ref() {
+            Self::Internal { .. } => None,
+            Self::Extension { stem, .. } => Some(*stem),
+            Self::Stub { stem, .. } => *stem,
+        };
+        let stub = Self::Stub {
+            commit,
+            stem,
+            persisted: false,
+        };
+        let pruned = std::mem::replace(child, Arc::new(stub));
+        self.set_persisted(false);
+        Ok(pruned)
+    }
+
+    /// Updates `commit` by the `(index, old, new)` item `changes`. When too many items changed, or `VC` cannot
+    /// update commitments, the `values` are committed to again instead. The commitment of a node in a `partial`
+    /// tree is always updated, as its values are not all known.
+    fn apply_changes(
+        crs: &VC::UniversalParams,
+        commit: &VC::Commitment,
+        changes: &[ItemChange<VC::Data>],
+        partial: bool,
+        values: impl FnOnce() -> Vec<<VC::Data as VCData>::Item>,
+    ) -> Result<VC::Commitment, VerkleError> {
+        let vc_error = |e: VC::Error| VerkleError::CommitmentError(e.to_string());
+        if partial || changes.len() * FULL_COMMIT_RATIO <= crs.max_size() {
+            if let Some(c) = VC::update_commitment(crs, commit, changes).map_err(vc_error)? {
+                return Ok(c);
+            }
+        }
+        if partial {
+            return Err(VerkleError::CommitmentError(
+                "The commitment scheme cannot update the commitments of a partial tree".to_string(),
+            ));
+        }
+        VC::commit(crs, &<VC::Data as VCData>::from_vec(values())).map_err(vc_error)
     }
 }
 
+impl<const N: usize, K, VC, T> Clone for Node<N, K, VC, T>
+where
+    K: Copy,
+    VC: VectorCommitment,
+    T: Clone,
+{
+    /// Copies this node alone: its children are shared with the copy
+    fn clone(&self) -> Self {
+        match self {
+            Self::Internal {
+                commit,
+                children,
+                unloaded,
+                changed,
+                persisted,
+            } => Self::Internal {
+                commit: commit.clone(),
+                children: children.clone(),
+                unloaded: unloaded.clone(),
+                changed: changed.clone(),
+                persisted: *persisted,
+            },
+            Self::Extension {
+                stem,
+                commit,
+                c1,
+                c2,
+                leaves,
+                changed,
+                persisted,
+            } => Self::Extension {
+                stem: *stem,
+                commit: commit.clone(),
+                c1: c1.clone(),
+                c2: c2.clone(),
+                leaves: leaves.clone(),
+                changed: changed.clone(),
+                persisted: *persisted,
+            },
+            Self::Stub {
+                commit,
+                stem,
+                persisted,
+            } => Self::Stub {
+                commit: commit.clone(),
+                stem: *stem,
+                persisted: *persisted,
+            },
+        }
+    }
+}
+
+/// Which half of an extension's leaves (`0` for `c1`, `1` for `c2`) the leaf at `unit` is committed in
+pub(crate) fn suffix_half(unit: usize, width: usize) -> usize {
+    unit / (width / 2)
+}
+
+/// The error of accessing the pruned subtree at `path`
+fn pruned_path<K: Copy + Into<usize>>(path: &[K]) -> VerkleError {
+    VerkleError::PrunedPath(path.iter().map(|&k| k.into()).collect())
+}
+
+/// The indexes inside of its suffix commitment that the lower and upper items of the leaf at `unit` are stored at
+pub(crate) fn leaf_indexes(unit: usize, width: usize) -> (usize, usize) {
+    ((2 * unit) % width, (2 * unit + 1) % width)
+}
+
 impl<const N: usize, K, VC, T> Debug for Node<N, K, VC, T>
 where
     K: Debug,
@@ -289,10 +1129,15 @@ where
             Self::Extension {
                 stem,
                 commit,
+                c1,
+                c2,
                 leaves,
+                ..
             } => {
                 f.write_fmt(format_args!("Extension ({:?}) {{\n", stem))?;
                 f.write_fmt(format_args!("\tCommit: {:?}\n", commit))?;
+                f.write_fmt(format_args!("\tC1: {:?}\n", c1))?;
+                f.write_fmt(format_args!("\tC2: {:?}\n", c2))?;
                 f.write_fmt(format_args!("\tChildren: {{\n"))?;
                 for (i, v) in leaves.iter() {
                     if *v != T::zero() {
@@ -301,13 +1146,26 @@ where
                 }
                 f.write_str("\t}")
             }
-            Self::Internal { commit, children } => {
+            Self::Internal {
+                commit,
+                children,
+                unloaded,
+                ..
+            } => {
                 f.write_fmt(format_args!("Inner {{\n"))?;
                 f.write_fmt(format_args!("\tCommit: {:?}\n", commit))?;
                 f.write_fmt(format_args!("\tChildren: {{\n"))?;
                 for c in children {
                     f.write_fmt(format_args!("\t\t({:?}) {:?}\n", c.0, c.1))?;
                 }
+                for c in unloaded {
+                    f.write_fmt(format_args!("\t\t({:?}) Unloaded({:?})\n", c.0, c.1))?;
+                }
+                f.write_str("\t}")
+            }
+            Self::Stub { commit, stem, .. } => {
+                f.write_fmt(format_args!("Stub ({:?}) {{\n", stem))?;
+                f.write_fmt(format_args!("\tCommit: {:?}\n", commit))?;
                 f.write_str("\t}")
             }
         }
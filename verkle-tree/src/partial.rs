@@ -0,0 +1,258 @@
+//! Partial trees hold only the paths of a tree that a `VerkleProof` proves, e.g. to validate a block statelessly.
+//!
+//! A partial tree is built from a proof that verifies against the root commitment of the full tree. It holds the
+//! internal nodes on the proven paths with their commitments, and the extension nodes at the end of the paths with
+//! the proven leaves and suffix commitments. An extension with another stem than the proven key (proving the key
+//! absent) is a stub, as for a pruned subtree, holding only its stem and commitment. Siblings and leaves off the
+//! proven paths are not known at all, as only the commitment of their parent is.
+//!
+//! Writing proven keys records the changed items of every node on their paths, as in a `VerkleTree`. The root of
+//! the full tree after the same writes is then generated by updating the commitment of each changed node by the
+//! difference of its changed items (see `VectorCommitment::update_commitment`).
+
+use std::{collections::HashSet, fmt::Debug, hash::Hash, marker::PhantomData, sync::Arc};
+
+use ark_ec::Group;
+use ark_poly::EvaluationDomain;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use bytemuck::Pod;
+use num::{One, Zero};
+use vector_commit::{
+    multiproof::{VCCommitmentMultiProof, VectorCommitmentMultiproof},
+    HasPrecompute, VCData, VCProofWithEvaluation, VCUniversalParams, VectorCommitment,
+};
+
+use crate::{
+    node::{suffix_half, Node},
+    ExtensionEncoding, ExtensionStatus, Key, KeyMethods, SplittableValue, VerkleError, VerkleProof,
+    VerkleTree,
+};
+
+/// A tree holding the paths of the keys proven by a proof, whose values can be read and written
+pub struct PartialVerkleTree<const N: usize, K, VC, T, G, Domain>
+where
+    VC: VectorCommitment,
+{
+    root: Node<N, K, VC, T>,
+    width: usize,
+    encoding: ExtensionEncoding,
+    /// The keys whose values (or absence) are known
+    proven: HashSet<Key<N, K>>,
+    _g: PhantomData<G>,
+    _domain: PhantomData<Domain>,
+}
+
+impl<const N: usize, K, VC, T, G, Domain> PartialVerkleTree<N, K, VC, T, G, Domain>
+where
+    K: Eq + Hash + Into<usize> + Zero + Copy + Pod + Send + Sync,
+    G: Group,
+    Domain: EvaluationDomain<G::ScalarField> + Sync + Send,
+    VC: VectorCommitmentMultiproof<G, Domain>,
+    VC::Error: Send,
+    <VC::Data as VCData>::Item: Copy + One + Send + Sync,
+    VC::Commitment: VCCommitmentMultiProof<G::ScalarField>
+        + CanonicalSerialize
+        + CanonicalDeserialize
+        + Send
+        + Sync,
+    VC::UniversalParams: HasPrecompute<G::ScalarField> + Sync,
+    T: SplittableValue<Output = <VC::Data as VCData>::Item>
+        + Zero
+        + Clone
+        + PartialEq
+        + Debug
+        + CanonicalSerialize
+        + CanonicalDeserialize
+        + Send
+        + Sync,
+{
+    /// Builds the partial tree of the `claims` that `proof` proves against the `root` commitment of a tree with the
+    /// extension `encoding`. A claim of `None` is a claim that the key is absent from the tree.
+    ///
+    /// Errors with `InvalidProof` if the proof does not verify (see `VerkleTree::verify_proof`).
+    pub fn from_proof(
+        crs: &VC::UniversalParams,
+        root: &VC::Commitment,
+        proof: &VerkleProof<N, K, VC>,
+        claims: &[(Key<N, K>, Option<T>)],
+        encoding: ExtensionEncoding,
+    ) -> Result<Self, VerkleError>
+    where
+        VC::Proof: VCProofWithEvaluation<G::ScalarField>,
+    {
+        if !VerkleTree::<N, K, VC, T, G, Domain>::verify_proof(crs, root, claims, proof, encoding)?
+        {
+            return Err(VerkleError::InvalidProof);
+        }
+
+        let width = crs.max_size();
+        let keys: Vec<_> = claims.iter().map(|(key, _)| *key).collect();
+        let key_paths = proof
+            .key_paths(&keys, width)
+            .ok_or(VerkleError::InvalidProof)?;
+        let mut tree = Self {
+            root: Node::proven_internal(*root),
+            width,
+            encoding,
+            proven: HashSet::new(),
+            _g: PhantomData,
+            _domain: PhantomData,
+        };
+        for ((key, value), (status, commitments)) in claims.iter().zip(key_paths) {
+            let (stem, unit) = key.split();
+            let leaf = (unit, value.as_ref());
+            tree.root
+                .add_proven_path(&stem, leaf, &status, &commitments, 0, width);
+            tree.proven.insert(*key);
+        }
+
+        Ok(tree)
+    }
+
+    /// The number of children of an internal node, and leaves of an extension node
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The encoding of the stem and leaves of extension nodes
+    pub fn encoding(&self) -> ExtensionEncoding {
+        self.encoding
+    }
+
+    /// Whether the value (or absence) of `key` is known
+    pub fn is_proven(&self, key: &Key<N, K>) -> bool {
+        self.proven.contains(key)
+    }
+
+    /// Gets the value at `key`. Errors with `UnprovenKey` if the key was not proven.
+    pub fn get(&self, key: &Key<N, K>) -> Result<Option<&T>, VerkleError> {
+        if !self.is_proven(key) {
+            return Err(VerkleError::UnprovenKey);
+        }
+        let (stem, unit) = key.split();
+        Ok(self
+            .root
+            .get_stem(&stem, 0)?
+            .and_then(|extension| extension.get_value(unit)))
+    }
+
+    /// Inserts `value` at `key`, overwriting any previous value. Errors with `UnprovenKey` if the key was not
+    /// proven, as the nodes on its path are not known.
+    pub fn insert(&mut self, key: Key<N, K>, value: T) -> Result<(), VerkleError> {
+        if !self.is_proven(&key) {
+            return Err(VerkleError::UnprovenKey);
+        }
+        let (stem, unit) = key.split();
+        self.root.insert(stem, vec![(unit, value)], 0);
+        Ok(())
+    }
+
+    /// Generates the root commitment of the tree after the writes since it was built, by updating the proven
+    /// commitments on the changed paths.
+    ///
+    /// Errors if `crs` does not commit to vectors of the tree's width, or `VC` cannot update commitments.
+    pub fn commitment(&mut self, crs: &VC::UniversalParams) -> Result<VC::Commitment, VerkleError> {
+        if crs.max_size() != self.width {
+            return Err(VerkleError::WidthMismatch {
+                width: self.width,
+                max_size: crs.max_size(),
+            });
+        }
+        Ok(*self.root.gen_commitment(crs, self.encoding, true)?)
+    }
+}
+
+impl<const N: usize, K, VC, T> Node<N, K, VC, T>
+where
+    K: Eq + Hash + Zero + Copy + Pod + Into<usize>,
+    VC: VectorCommitment,
+    <VC::Data as VCData>::Item: Copy + One,
+    T: SplittableValue<Output = <VC::Data as VCData>::Item> + Zero + Clone + PartialEq,
+{
+    /// An internal node of a partial tree, of which only the commitment is known so far
+    fn proven_internal(commit: VC::Commitment) -> Self {
+        let mut node = Self::new_internal(vec![]);
+        if let Self::Internal { commit: c, .. } = &mut node {
+            *c = Some(commit);
+        }
+        node
+    }
+
+    /// An extension node of a partial tree, of which only the stem and commitment are known so far. Its proven
+    /// leaves and suffix commitments are added as they are found.
+    fn proven_extension(stem: Key<N, K>, commit: VC::Commitment) -> Self {
+        let mut node = Self::new_extension(stem, vec![]);
+        if let Self::Extension { commit: c, .. } = &mut node {
+            *c = Some(commit);
+        }
+        node
+    }
+
+    /// Adds the nodes a proof proves on the path to `stem` below this internal node at `depth`, given the `status`
+    /// of the stem and the `commitments` along the path (see `VerkleProof`). The value of the `leaf` is added to
+    /// the extension of the stem if it is present.
+    fn add_proven_path(
+        &mut self,
+        stem: &Key<N, K>,
+        leaf: (K, Option<&T>),
+        status: &ExtensionStatus<N, K>,
+        commitments: &[&VC::Commitment],
+        depth: usize,
+        width: usize,
+    ) {
+        let children = match self {
+            Self::Internal { children, .. } => children,
+            _ => return,
+        };
+        let k = stem[depth];
+
+        // The path continues below an internal child until the commitment of the extension (if any) is reached
+        let internal_depth = match status {
+            ExtensionStatus::Absent => commitments.len(),
+            ExtensionStatus::OtherStem(_) => commitments.len() - 1,
+            ExtensionStatus::Present => commitments.len() - 2,
+        };
+        if depth < internal_depth {
+            let child = children
+                .entry(k)
+                .or_insert_with(|| Arc::new(Self::proven_internal((*commitments[depth]).clone())));
+            return Arc::make_mut(child).add_proven_path(
+                stem,
+                leaf,
+                status,
+                commitments,
+                depth + 1,
+                width,
+            );
+        }
+
+        match status {
+            ExtensionStatus::Absent => {}
+            ExtensionStatus::OtherStem(other) => {
+                children.entry(k).or_insert_with(|| {
+                    Arc::new(Self::Stub {
+                        commit: (*commitments[depth]).clone(),
+                        stem: Some(*other),
+                        persisted: false,
+                    })
+                });
+            }
+            ExtensionStatus::Present => {
+                let child = children.entry(k).or_insert_with(|| {
+                    Arc::new(Self::proven_extension(*stem, (*commitments[depth]).clone()))
+                });
+                if let Self::Extension { c1, c2, leaves, .. } = Arc::make_mut(child) {
+                    let (unit, value) = leaf;
+                    let suffix = Some((*commitments[depth + 1]).clone());
+                    match suffix_half(unit.into(), width) {
+                        0 => *c1 = suffix,
+                        _ => *c2 = suffix,
+                    }
+                    if let Some(value) = value {
+                        leaves.insert(unit, value.clone());
+                    }
+                }
+            }
+        }
+    }
+}
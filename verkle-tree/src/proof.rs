@@ -0,0 +1,454 @@
+//! Proofs that keys are stored in a `VerkleTree`.
+//!
+//! Every opening made along the paths of the proven keys is aggregated into a single `Multiproof`, so the
+//! proof size is (nearly) independent of the number of keys proven. The commitments of the nodes on the paths are
+//! included once each, by path, along with a status byte for each stem.
+
+use std::{
+    collections::{btree_map, hash_map::Entry, BTreeMap, BTreeSet, HashMap, HashSet},
+    hash::Hash,
+};
+
+use bytemuck::Pod;
+use num::Zero;
+use vector_commit::{
+    multiproof::{Multiproof, MultiproofProverQuery, MultiproofVerifierQuery},
+    VectorCommitment,
+};
+
+use crate::{node::suffix_half, Key, KeyMethods, VerkleError};
+
+/// How the stem of a proven key was found at the end of its path
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExtensionStatus<const N: usize, K> {
+    /// The slot of the last internal node on the path is empty, and is opened to zero
+    Absent,
+
+    /// An extension node with a different stem occupies the slot, and opens its stem to prove the divergence
+    OtherStem(Key<N, K>),
+
+    /// The extension node of the stem is present. Leaves that are not set are opened to zero.
+    Present,
+}
+
+/// The number of low bits of an extension status byte holding the status, below the depth of the stem
+pub(crate) const STATUS_BITS: u8 = 3;
+pub(crate) const STATUS_ABSENT: u8 = 0;
+pub(crate) const STATUS_OTHER_STEM: u8 = 1;
+pub(crate) const STATUS_PRESENT: u8 = 2;
+
+/// A proof of the values (or absence) of a set of keys in a `VerkleTree`. Nodes shared by the paths of several
+/// keys are only included once, so the proof size scales with the number of distinct nodes on the paths.
+pub struct VerkleProof<const N: usize, K, VC: VectorCommitment> {
+    /// For each distinct stem of the proven keys, in the order they are first proven: the number of internal
+    /// nodes on its path (including the root) shifted left by `STATUS_BITS`, and how it was found (`0` absent,
+    /// `1` another stem, `2` present)
+    pub(crate) depth_extension_present: Vec<u8>,
+
+    /// The stems of the extension nodes found instead of absent stems, matched to them by the path of their slot
+    pub(crate) other_stems: Vec<Key<N, K>>,
+
+    /// The commitments of every node on the paths of the proven keys except the root, sorted by path. The suffix
+    /// commitments `c1` and `c2` of an extension are sorted as if at its path extended by `2` and `3`, their
+    /// indexes in the extension's vector.
+    pub(crate) commitments_by_path: Vec<VC::Commitment>,
+
+    /// The aggregated opening of every node on the paths of the proven keys
+    pub(crate) multiproof: Multiproof<VC::Proof, VC::Commitment>,
+}
+
+impl<const N: usize, K, VC: VectorCommitment> VerkleProof<N, K, VC> {
+    pub fn depth_extension_present(&self) -> &[u8] {
+        &self.depth_extension_present
+    }
+
+    pub fn other_stems(&self) -> &[Key<N, K>] {
+        &self.other_stems
+    }
+
+    pub fn commitments_by_path(&self) -> &[VC::Commitment] {
+        &self.commitments_by_path
+    }
+
+    pub fn multiproof(&self) -> &Multiproof<VC::Proof, VC::Commitment> {
+        &self.multiproof
+    }
+}
+
+/// How the stem of a key was found, and the commitments on its path below the root: those of the internal nodes,
+/// followed by those of the extension node and the suffix commitment (`c1` or `c2`) storing its leaf, if they are
+/// part of the proof
+pub(crate) type KeyPath<const N: usize, K, C> = (ExtensionStatus<N, K>, Vec<C>);
+
+impl<const N: usize, K, VC> VerkleProof<N, K, VC>
+where
+    K: Eq + Hash + Zero + Copy + Pod + Into<usize>,
+    VC: VectorCommitment,
+{
+    /// Builds the proof of `keys` from the path of each key, including each commitment once
+    ///
+    /// Errors with `ProofDepthOverflow` if a stem is too deep for its status byte.
+    pub(crate) fn from_key_paths(
+        keys: &[Key<N, K>],
+        key_paths: Vec<KeyPath<N, K, VC::Commitment>>,
+        multiproof: Multiproof<VC::Proof, VC::Commitment>,
+        width: usize,
+    ) -> Result<Self, VerkleError> {
+        let mut stems = HashSet::new();
+        let mut depth_extension_present = vec![];
+        let mut other_stems = vec![];
+        let mut commitments_by_path = BTreeMap::new();
+
+        for (key, (status, commitments)) in keys.iter().zip(key_paths) {
+            let (stem, unit) = key.split();
+            let status_byte = status.to_byte(commitments.len())?;
+            if stems.insert(stem) {
+                depth_extension_present.push(status_byte);
+            }
+            if let ExtensionStatus::OtherStem(other) = status {
+                if !other_stems.contains(&other) {
+                    other_stems.push(other);
+                }
+            }
+            let paths = commitment_paths(&stem, status_byte, unit, width);
+            commitments_by_path.extend(paths.into_iter().zip(commitments));
+        }
+
+        Ok(Self {
+            depth_extension_present,
+            other_stems,
+            commitments_by_path: commitments_by_path.into_values().collect(),
+            multiproof,
+        })
+    }
+
+    /// Rebuilds the path of each of `keys` from the proof. Returns `None` if the proof does not hold exactly the
+    /// stems and commitments of the paths of the keys.
+    pub(crate) fn key_paths(
+        &self,
+        keys: &[Key<N, K>],
+        width: usize,
+    ) -> Option<Vec<KeyPath<N, K, &VC::Commitment>>> {
+        let mut stem_statuses = HashMap::<_, (u8, ExtensionStatus<N, K>)>::new();
+        let mut statuses = self.depth_extension_present.iter();
+        let mut found_others = HashSet::new();
+        let mut paths = Vec::with_capacity(keys.len());
+
+        for key in keys {
+            let (stem, unit) = key.split();
+            let (status_byte, status) = match stem_statuses.entry(stem) {
+                Entry::Occupied(o) => o.get().clone(),
+                Entry::Vacant(v) => {
+                    let status_byte = *statuses.next()?;
+                    let status = self.decode_status(&stem, status_byte)?;
+                    if let ExtensionStatus::OtherStem(other) = status {
+                        found_others.insert(other);
+                    }
+                    v.insert((status_byte, status)).clone()
+                }
+            };
+            paths.push((status, commitment_paths(&stem, status_byte, unit, width)));
+        }
+        if statuses.next().is_some() || found_others.len() != self.other_stems.len() {
+            return None;
+        }
+
+        let all_paths: BTreeSet<&Vec<usize>> = paths.iter().flat_map(|(_, p)| p).collect();
+        if all_paths.len() != self.commitments_by_path.len() {
+            return None;
+        }
+        let by_path: HashMap<_, _> = all_paths
+            .into_iter()
+            .zip(self.commitments_by_path.iter())
+            .collect();
+
+        Some(
+            paths
+                .iter()
+                .map(|(status, p)| (status.clone(), p.iter().map(|path| by_path[path]).collect()))
+                .collect(),
+        )
+    }
+
+    /// The status of `stem` given its status byte, or `None` if the byte is invalid or no other stem shares the
+    /// path of its slot
+    fn decode_status(&self, stem: &Key<N, K>, status_byte: u8) -> Option<ExtensionStatus<N, K>> {
+        let depth = (status_byte >> STATUS_BITS) as usize;
+        if depth == 0 || depth >= N {
+            return None;
+        }
+        match status_byte & ((1 << STATUS_BITS) - 1) {
+            STATUS_ABSENT => Some(ExtensionStatus::Absent),
+            STATUS_OTHER_STEM => self
+                .other_stems
+                .iter()
+                .find(|other| other[0..depth] == stem[0..depth] && *other != stem)
+                .map(|other| ExtensionStatus::OtherStem(*other)),
+            STATUS_PRESENT => Some(ExtensionStatus::Present),
+            _ => None,
+        }
+    }
+}
+
+impl<const N: usize, K> ExtensionStatus<N, K> {
+    /// The status byte of a stem found with this status, given the number of commitments on its path
+    fn to_byte(&self, commitment_count: usize) -> Result<u8, VerkleError> {
+        let (depth, status) = match self {
+            Self::Absent => (commitment_count + 1, STATUS_ABSENT),
+            Self::OtherStem(_) => (commitment_count, STATUS_OTHER_STEM),
+            Self::Present => (commitment_count - 1, STATUS_PRESENT),
+        };
+        if depth >= 1 << (8 - STATUS_BITS) {
+            return Err(VerkleError::ProofDepthOverflow(depth));
+        }
+        Ok((depth as u8) << STATUS_BITS | status)
+    }
+}
+
+/// The paths of the commitments on the path to the key `unit` of `stem`, as units, in the order of `KeyPath`
+fn commitment_paths<const N: usize, K: Copy + Into<usize>>(
+    stem: &Key<N, K>,
+    status_byte: u8,
+    unit: K,
+    width: usize,
+) -> Vec<Vec<usize>> {
+    let depth = (status_byte >> STATUS_BITS) as usize;
+    let status = status_byte & ((1 << STATUS_BITS) - 1);
+    let path = |len: usize| stem[0..len].iter().map(|&u| u.into()).collect::<Vec<_>>();
+
+    let mut paths: Vec<_> = (1..depth).map(path).collect();
+    if status != STATUS_ABSENT {
+        paths.push(path(depth));
+    }
+    if status == STATUS_PRESENT {
+        let mut suffix_path = path(depth);
+        suffix_path.push(2 + suffix_half(unit.into(), width));
+        paths.push(suffix_path);
+    }
+    paths
+}
+
+/// Identifies a vector that is opened in a proof by the path of its node
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(crate) enum VectorId<K> {
+    /// The vector an internal or extension node commits to
+    Node(Vec<K>),
+
+    /// One half (`0` for `c1`, `1` for `c2`) of an extension node's leaves
+    Suffix(Vec<K>, usize),
+}
+
+impl<K: Copy + Into<usize>> VectorId<K> {
+    /// The position of the vector in the order the spec opens vectors: by path, with the suffix vectors of an
+    /// extension after the extension's own vector
+    fn order(&self) -> (Vec<usize>, usize) {
+        let units = |path: &[K]| path.iter().map(|&u| u.into()).collect();
+        match self {
+            Self::Node(path) => (units(path), 0),
+            Self::Suffix(path, half) => (units(path), 1 + half),
+        }
+    }
+}
+
+/// The order of an opening: that of its vector, then its evaluation point
+type OpeningOrder = ((Vec<usize>, usize), usize);
+
+/// Collects the openings of a proof. Each opened vector is only stored once, and opened once at each point, no
+/// matter how many keys share the node it belongs to.
+pub(crate) struct ProverOpenings<K, C, D, F> {
+    vectors: Vec<(D, C)>,
+    ids: HashMap<VectorId<K>, usize>,
+
+    /// (index into `vectors`, evaluation) of each opening
+    openings: BTreeMap<OpeningOrder, (usize, F)>,
+}
+
+impl<K, C, D, F> ProverOpenings<K, C, D, F>
+where
+    K: Eq + Hash + Copy + Into<usize>,
+    C: Clone,
+    F: Clone,
+{
+    pub(crate) fn new() -> Self {
+        Self {
+            vectors: Vec::new(),
+            ids: HashMap::new(),
+            openings: BTreeMap::new(),
+        }
+    }
+
+    /// Open the vector identified by `id` at `z` to `y`. The `data` closure is only called the first time
+    /// a vector is opened, and an opening at a point the vector was already opened at is skipped.
+    pub(crate) fn open(
+        &mut self,
+        id: VectorId<K>,
+        commit: &C,
+        data: impl FnOnce() -> D,
+        z: usize,
+        y: F,
+    ) {
+        let order = (id.order(), z);
+        let vectors = &mut self.vectors;
+        let index = *self.ids.entry(id).or_insert_with(|| {
+            vectors.push((data(), commit.clone()));
+            vectors.len() - 1
+        });
+        self.openings.entry(order).or_insert((index, y));
+    }
+
+    /// The queries of the openings, in the order of the spec: by the path of the opened vector, then by point
+    pub(crate) fn queries(&self) -> Vec<MultiproofProverQuery<'_, C, D, F>> {
+        self.openings
+            .iter()
+            .map(|((_, z), (index, y))| {
+                let (data, commit) = &self.vectors[*index];
+                MultiproofProverQuery::new(data, commit, *z, y.clone())
+            })
+            .collect()
+    }
+}
+
+/// Rebuilds the openings of a proof from its commitments. Every opening is checked against previous
+/// openings of the same node, so that a proof cannot claim two different commitments (or evaluations)
+/// for the same path, and is only queried the first time it is made.
+pub(crate) struct VerifierOpenings<'a, K, C, F> {
+    commits: HashMap<VectorId<K>, &'a C>,
+    openings: BTreeMap<OpeningOrder, (&'a C, F)>,
+    internal_paths: HashSet<Vec<K>>,
+    extension_paths: HashSet<Vec<K>>,
+}
+
+impl<'a, K, C, F> VerifierOpenings<'a, K, C, F>
+where
+    K: Eq + Hash + Copy + Into<usize>,
+    C: PartialEq,
+    F: PartialEq + Clone,
+{
+    pub(crate) fn new() -> Self {
+        Self {
+            commits: HashMap::new(),
+            openings: BTreeMap::new(),
+            internal_paths: HashSet::new(),
+            extension_paths: HashSet::new(),
+        }
+    }
+
+    /// Open the vector identified by `id` at `z` to `y`. Returns false if this conflicts with a previous opening.
+    pub(crate) fn open(&mut self, id: VectorId<K>, commit: &'a C, z: usize, y: F) -> bool {
+        match self.commits.entry(id.clone()) {
+            Entry::Occupied(o) => {
+                if *o.get() != commit {
+                    return false;
+                }
+            }
+            Entry::Vacant(v) => {
+                v.insert(commit);
+            }
+        }
+
+        match self.openings.entry((id.order(), z)) {
+            btree_map::Entry::Occupied(o) => {
+                if o.get().1 != y {
+                    return false;
+                }
+            }
+            btree_map::Entry::Vacant(v) => {
+                v.insert((commit, y));
+            }
+        }
+        true
+    }
+
+    /// Record that the node at `path` is an internal node
+    pub(crate) fn internal_at(&mut self, path: &[K]) {
+        self.internal_paths.insert(path.to_vec());
+    }
+
+    /// Record that the node at `path` is an extension node
+    pub(crate) fn extension_at(&mut self, path: &[K]) {
+        self.extension_paths.insert(path.to_vec());
+    }
+
+    /// Returns true if no node is claimed to be an extension node while also being (or being an
+    /// ancestor of) an internal node
+    pub(crate) fn is_consistent(&self) -> bool {
+        self.internal_paths
+            .iter()
+            .all(|path| (0..=path.len()).all(|len| !self.extension_paths.contains(&path[0..len])))
+    }
+
+    /// The queries of the openings, in the same order as `ProverOpenings::queries`
+    pub(crate) fn queries(&self) -> Vec<MultiproofVerifierQuery<'a, C, F>> {
+        self.openings
+            .iter()
+            .map(|((_, z), (commit, y))| MultiproofVerifierQuery::new(*commit, *z, y.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openings_once_per_point() {
+        let root = VectorId::<u8>::Node(vec![]);
+        let extension = VectorId::<u8>::Node(vec![3]);
+
+        // Two keys below the same extension open the root and the extension at the same points
+        let mut prover = ProverOpenings::<u8, u32, Vec<u32>, u32>::new();
+        for _ in 0..2 {
+            prover.open(root.clone(), &7, Vec::new, 3, 9);
+            prover.open(extension.clone(), &9, Vec::new, 0, 1);
+            prover.open(extension.clone(), &9, Vec::new, 1, 5);
+        }
+        assert!(prover.queries().len() == 3);
+
+        let (root_commit, extension_commit) = (7, 9);
+        let mut verifier = VerifierOpenings::<u8, u32, u32>::new();
+        for _ in 0..2 {
+            assert!(verifier.open(root.clone(), &root_commit, 3, 9));
+            assert!(verifier.open(extension.clone(), &extension_commit, 0, 1));
+            assert!(verifier.open(extension.clone(), &extension_commit, 1, 5));
+        }
+        assert!(verifier.queries().len() == 3);
+        assert!(!verifier.open(extension, &extension_commit, 1, 6));
+    }
+
+    #[test]
+    fn test_openings_order() {
+        // Opened key by key, the vectors are queried by path, and the suffix vectors after their extension
+        let mut prover = ProverOpenings::<u8, u32, Vec<u32>, u32>::new();
+        for (id, commit, z) in [
+            (VectorId::Node(vec![]), 1, 5),
+            (VectorId::Node(vec![5]), 2, 0),
+            (VectorId::Suffix(vec![5], 1), 3, 10),
+            (VectorId::Node(vec![5]), 2, 3),
+            (VectorId::Node(vec![]), 1, 2),
+            (VectorId::Node(vec![2]), 4, 1),
+            (VectorId::Suffix(vec![5], 0), 5, 4),
+            (VectorId::Node(vec![5]), 2, 2),
+        ] {
+            prover.open(id, &commit, Vec::new, z, 0);
+        }
+
+        let order: Vec<_> = prover
+            .openings
+            .iter()
+            .map(|((_, z), (index, _))| (prover.vectors[*index].1, *z))
+            .collect();
+        assert!(
+            order
+                == [
+                    (1, 2),
+                    (1, 5),
+                    (4, 1),
+                    (2, 0),
+                    (2, 2),
+                    (2, 3),
+                    (5, 4),
+                    (3, 10)
+                ]
+        );
+    }
+}
@@ -0,0 +1,371 @@
+//! Snapshots persist a `VerkleTree` to a versioned binary format, so that a restarted process does not have to
+//! rebuild (and recommit) the tree from scratch.
+//!
+//! A snapshot starts with a header:
+//! - The magic bytes `VKTS` and the format version
+//! - The key length `N`, the size of a key unit, the tree width and the extension encoding
+//! - Whether cached commitments are included
+//!
+//! followed by the nodes of the tree in pre-order. Each node is a tag (`0` for internal, `1` for extension, `2` for
+//! the stub of a pruned subtree), its cached commitments if included, and then:
+//! - For an internal node: the number of children, and the unit and node of each child
+//! - For an extension node: its stem, the number of leaves, and the unit and value of each leaf
+//! - For a stub: whether it has a stem, and the stem if so
+//!
+//! The commitment of a stub is always included, as it cannot be generated again.
+//!
+//! Children and leaves are written in ascending order of their unit, so equal trees produce equal snapshots.
+//! All integers and values are written with `CanonicalSerialize` (in compressed mode), and units as their raw bytes.
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    io::{Read, Write},
+};
+
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use bytemuck::{bytes_of, pod_read_unaligned, Pod};
+use num::Zero;
+use vector_commit::{VCData, VectorCommitment};
+
+use crate::{node::Node, ExtensionEncoding, Key, SplittableValue, VerkleError};
+
+pub(crate) const SNAPSHOT_MAGIC: &[u8; 4] = b"VKTS";
+pub(crate) const SNAPSHOT_VERSION: u8 = 1;
+
+pub(crate) const INTERNAL_TAG: u8 = 0;
+const EXTENSION_TAG: u8 = 1;
+const STUB_TAG: u8 = 2;
+
+/// The fields of a snapshot header that must match the tree being restored
+pub(crate) struct SnapshotHeader {
+    pub(crate) width: usize,
+    pub(crate) encoding: ExtensionEncoding,
+    pub(crate) commitments: bool,
+}
+
+impl SnapshotHeader {
+    pub(crate) fn write<const N: usize, K, W: Write>(
+        &self,
+        mut writer: W,
+    ) -> Result<(), VerkleError> {
+        writer
+            .write_all(SNAPSHOT_MAGIC)
+            .map_err(SerializationError::from)?;
+        SNAPSHOT_VERSION.serialize_compressed(&mut writer)?;
+        (N as u64).serialize_compressed(&mut writer)?;
+        (std::mem::size_of::<K>() as u64).serialize_compressed(&mut writer)?;
+        (self.width as u64).serialize_compressed(&mut writer)?;
+        let encoding: u8 = match self.encoding {
+            ExtensionEncoding::Generic => 0,
+            ExtensionEncoding::Ethereum => 1,
+        };
+        encoding.serialize_compressed(&mut writer)?;
+        self.commitments.serialize_compressed(&mut writer)?;
+
+        Ok(())
+    }
+
+    /// Reads the header, checking that the snapshot is of a supported version and stores keys of type `Key<N, K>`
+    pub(crate) fn read<const N: usize, K, R: Read>(mut reader: R) -> Result<Self, VerkleError> {
+        let mut magic = [0u8; 4];
+        reader
+            .read_exact(&mut magic)
+            .map_err(SerializationError::from)?;
+        if &magic != SNAPSHOT_MAGIC {
+            return Err(VerkleError::InvalidSnapshot(
+                "Missing snapshot magic".to_string(),
+            ));
+        }
+        let version = u8::deserialize_compressed(&mut reader)?;
+        if version != SNAPSHOT_VERSION {
+            return Err(VerkleError::InvalidSnapshot(format!(
+                "Unsupported version {}",
+                version
+            )));
+        }
+
+        let key_len = u64::deserialize_compressed(&mut reader)?;
+        let unit_size = u64::deserialize_compressed(&mut reader)?;
+        if key_len != N as u64 || unit_size != std::mem::size_of::<K>() as u64 {
+            return Err(VerkleError::InvalidSnapshot(format!(
+                "Snapshot stores keys of {} units of {} bytes",
+                key_len, unit_size
+            )));
+        }
+
+        let width = u64::deserialize_compressed(&mut reader)? as usize;
+        let encoding = match u8::deserialize_compressed(&mut reader)? {
+            0 => ExtensionEncoding::Generic,
+            1 => ExtensionEncoding::Ethereum,
+            e => {
+                return Err(VerkleError::InvalidSnapshot(format!(
+                    "Unknown extension encoding {}",
+                    e
+                )))
+            }
+        };
+        let commitments = bool::deserialize_compressed(&mut reader)?;
+
+        Ok(Self {
+            width,
+            encoding,
+            commitments,
+        })
+    }
+}
+
+pub(crate) fn write_unit<K: Pod, W: Write>(unit: &K, writer: &mut W) -> Result<(), VerkleError> {
+    writer
+        .write_all(bytes_of(unit))
+        .map_err(SerializationError::from)?;
+    Ok(())
+}
+
+pub(crate) fn read_unit<K: Pod, R: Read>(reader: &mut R) -> Result<K, VerkleError> {
+    let mut bytes = vec![0u8; std::mem::size_of::<K>()];
+    reader
+        .read_exact(&mut bytes)
+        .map_err(SerializationError::from)?;
+    Ok(pod_read_unaligned(&bytes))
+}
+
+/// Reads a unit and checks that it indexes a slot of a node `width` wide
+pub(crate) fn read_slot<K: Pod + Into<usize>, R: Read>(
+    reader: &mut R,
+    width: usize,
+) -> Result<K, VerkleError> {
+    let unit: K = read_unit(reader)?;
+    if unit.into() >= width {
+        return Err(VerkleError::UnitOutOfRange {
+            unit: unit.into(),
+            width,
+        });
+    }
+    Ok(unit)
+}
+
+/// Writes `commits` if they are all present, otherwise marks them as missing
+pub(crate) fn write_commitments<C: CanonicalSerialize, W: Write>(
+    commits: &[&Option<C>],
+    writer: &mut W,
+) -> Result<(), VerkleError> {
+    let present = commits.iter().all(|c| c.is_some());
+    present.serialize_compressed(&mut *writer)?;
+    if present {
+        for c in commits.iter() {
+            c.as_ref().unwrap().serialize_compressed(&mut *writer)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads `count` commitments if they are present
+pub(crate) fn read_commitments<C: CanonicalDeserialize, R: Read>(
+    count: usize,
+    reader: &mut R,
+) -> Result<Vec<Option<C>>, VerkleError> {
+    if !bool::deserialize_compressed(&mut *reader)? {
+        return Ok((0..count).map(|_| None).collect());
+    }
+    (0..count)
+        .map(|_| Ok(Some(C::deserialize_compressed(&mut *reader)?)))
+        .collect()
+}
+
+impl<const N: usize, K, VC, T> Node<N, K, VC, T>
+where
+    K: Eq + Hash + Zero + Copy + Pod + Into<usize>,
+    VC: VectorCommitment,
+    VC::Commitment: CanonicalSerialize + CanonicalDeserialize,
+    <VC::Data as VCData>::Item: Copy + num::One,
+    T: SplittableValue<Output = <VC::Data as VCData>::Item>
+        + Zero
+        + Clone
+        + PartialEq
+        + CanonicalSerialize
+        + CanonicalDeserialize,
+{
+    /// Writes this node at `path` and its descendants. The cached commitments of a node are only written if
+    /// `commitments` is set and the node has no changes since they were generated. Children that are not loaded
+    /// are read with `load` as they are written, and dropped again afterwards.
+    pub(crate) fn write_snapshot<W, L>(
+        &self,
+        writer: &mut W,
+        commitments: bool,
+        path: &mut Vec<K>,
+        load: &L,
+    ) -> Result<(), VerkleError>
+    where
+        W: Write,
+        L: Fn(&[K]) -> Result<Self, VerkleError>,
+    {
+        match self {
+            Self::Internal {
+                children, unloaded, ..
+            } => {
+                INTERNAL_TAG.serialize_compressed(&mut *writer)?;
+                if commitments {
+                    write_commitments(&[&self.clean_commitment().cloned()], writer)?;
+                }
+
+                let mut sorted: Vec<_> = children.keys().chain(unloaded.keys()).copied().collect();
+                sorted.sort_by_key(|k| (*k).into());
+                (sorted.len() as u64).serialize_compressed(&mut *writer)?;
+                for k in sorted {
+                    write_unit(&k, writer)?;
+                    path.push(k);
+                    match children.get(&k) {
+                        Some(child) => child.write_snapshot(writer, commitments, path, load)?,
+                        None => load(path)?.write_snapshot(writer, commitments, path, load)?,
+                    }
+                    path.pop();
+                }
+            }
+            Self::Extension {
+                stem,
+                commit,
+                c1,
+                c2,
+                leaves,
+                changed,
+                ..
+            } => {
+                EXTENSION_TAG.serialize_compressed(&mut *writer)?;
+                if commitments {
+                    if changed.is_empty() {
+                        write_commitments(&[commit, c1, c2], writer)?;
+                    } else {
+                        write_commitments::<VC::Commitment, W>(&[&None], writer)?;
+                    }
+                }
+
+                for unit in stem.iter() {
+                    write_unit(unit, writer)?;
+                }
+                let mut sorted: Vec<_> = leaves.iter().collect();
+                sorted.sort_by_key(|(k, _)| (**k).into());
+                (sorted.len() as u64).serialize_compressed(&mut *writer)?;
+                for (k, value) in sorted {
+                    write_unit(k, writer)?;
+                    value.serialize_compressed(&mut *writer)?;
+                }
+            }
+            Self::Stub { commit, stem, .. } => {
+                STUB_TAG.serialize_compressed(&mut *writer)?;
+                commit.serialize_compressed(&mut *writer)?;
+                stem.is_some().serialize_compressed(&mut *writer)?;
+                for unit in stem.iter().flatten() {
+                    write_unit(unit, writer)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads a node written by `write_snapshot` at `path`, the units of the key leading to it. The stems of
+    /// extension nodes are checked to extend their path, and every unit to be within `width`.
+    pub(crate) fn read_snapshot<R: Read>(
+        reader: &mut R,
+        path: &mut Vec<K>,
+        width: usize,
+        commitments: bool,
+    ) -> Result<Self, VerkleError> {
+        let tag = u8::deserialize_compressed(&mut *reader)?;
+        match tag {
+            INTERNAL_TAG => {
+                if path.len() >= N - 1 {
+                    return Err(VerkleError::InvalidSnapshot(
+                        "Internal node is deeper than the stem length".to_string(),
+                    ));
+                }
+                let commit = match commitments {
+                    true => read_commitments(1, reader)?.remove(0),
+                    false => None,
+                };
+
+                let count = u64::deserialize_compressed(&mut *reader)?;
+                let mut children = HashMap::new();
+                for _ in 0..count {
+                    let k: K = read_slot(reader, width)?;
+                    path.push(k);
+                    let child = Self::read_snapshot(reader, path, width, commitments)?;
+                    path.pop();
+                    if children.insert(k, child).is_some() {
+                        return Err(VerkleError::InvalidSnapshot(
+                            "Duplicate child of an internal node".to_string(),
+                        ));
+                    }
+                }
+
+                let mut node = Self::new_internal(children.into_iter().collect());
+                if let Self::Internal { commit: c, .. } = &mut node {
+                    *c = commit;
+                }
+                Ok(node)
+            }
+            EXTENSION_TAG => {
+                let mut commits = match commitments {
+                    true => read_commitments(3, reader)?,
+                    false => vec![None, None, None],
+                };
+
+                let stem = read_stem(reader, path, width)?;
+
+                let count = u64::deserialize_compressed(&mut *reader)?;
+                let mut leaves = Vec::new();
+                for _ in 0..count {
+                    let unit: K = read_slot(reader, width)?;
+                    leaves.push((unit, T::deserialize_compressed(&mut *reader)?));
+                }
+
+                let mut node = Self::new_extension(stem, leaves);
+                if let Self::Extension { commit, c1, c2, .. } = &mut node {
+                    *c2 = commits.pop().unwrap();
+                    *c1 = commits.pop().unwrap();
+                    *commit = commits.pop().unwrap();
+                }
+                Ok(node)
+            }
+            STUB_TAG => {
+                let commit = VC::Commitment::deserialize_compressed(&mut *reader)?;
+                let stem = match bool::deserialize_compressed(&mut *reader)? {
+                    true => Some(read_stem(reader, path, width)?),
+                    false => None,
+                };
+                Ok(Self::Stub {
+                    commit,
+                    stem,
+                    persisted: false,
+                })
+            }
+            t => Err(VerkleError::InvalidSnapshot(format!(
+                "Unknown node tag {}",
+                t
+            ))),
+        }
+    }
+}
+
+/// Reads the stem of an extension node at `path`, checking that it extends the path
+fn read_stem<const N: usize, K, R>(
+    reader: &mut R,
+    path: &[K],
+    width: usize,
+) -> Result<Key<N, K>, VerkleError>
+where
+    K: Pod + Zero + Eq + Into<usize>,
+    R: Read,
+{
+    let mut stem: Key<N, K> = [K::zero(); N];
+    for unit in stem.iter_mut() {
+        *unit = read_slot(reader, width)?;
+    }
+    if stem[..path.len()] != path[..] || !stem[N - 1].is_zero() {
+        return Err(VerkleError::InvalidSnapshot(
+            "Extension stem does not match its path".to_string(),
+        ));
+    }
+    Ok(stem)
+}
@@ -0,0 +1,557 @@
+//! Node stores hold the nodes of a `VerkleTree` outside of memory, so that trees larger than memory can page
+//! subtrees in and out.
+//!
+//! Every node is stored as a record at its path, the units of the key leading to it (as bytes). The record of a
+//! node only holds the node itself: an internal node lists the units of its children along with their
+//! commitments, so a child can stay unloaded until a key below it is accessed. The record of the root is
+//! preceded by a snapshot header, describing the tree the store holds.
+//!
+//! Records are encoded like the nodes of a snapshot (see the `snapshot` module), with the cached commitments
+//! of all nodes without changes included.
+
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    hash::Hash,
+    io::{BufReader, Read, Seek, SeekFrom, Write},
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use bytemuck::{cast_slice, Pod};
+use num::Zero;
+use vector_commit::{VCData, VectorCommitment};
+
+use crate::{
+    node::Node,
+    snapshot::{read_commitments, read_slot, write_commitments, write_unit, INTERNAL_TAG},
+    Key, SplittableValue, VerkleError,
+};
+
+/// A key-value store of encoded nodes, indexed by the bytes of their path in the tree
+pub trait NodeStore {
+    /// Returns the node stored at `path`, if any
+    fn get(&self, path: &[u8]) -> Result<Option<Vec<u8>>, VerkleError>;
+
+    /// Stores `node` at `path`, replacing any node already stored there
+    fn put(&mut self, path: &[u8], node: Vec<u8>) -> Result<(), VerkleError>;
+
+    /// Removes the node stored at `path`. Removing a path without a node does nothing
+    fn delete(&mut self, path: &[u8]) -> Result<(), VerkleError>;
+
+    /// Makes every change to the store durable. Called at the end of every flush of the tree
+    fn sync(&mut self) -> Result<(), VerkleError> {
+        Ok(())
+    }
+}
+
+/// A store that keeps all nodes in memory. Trees use it by default, where it only holds the nodes that are
+/// explicitly flushed.
+///
+/// Cloning a store is O(1): the clone shares every record with the store, until either of them replaces it.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStore {
+    nodes: im::HashMap<Vec<u8>, Arc<[u8]>>,
+}
+
+impl MemoryStore {
+    /// The number of stored nodes
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+impl NodeStore for MemoryStore {
+    fn get(&self, path: &[u8]) -> Result<Option<Vec<u8>>, VerkleError> {
+        Ok(self.nodes.get(path).map(|node| node.to_vec()))
+    }
+
+    fn put(&mut self, path: &[u8], node: Vec<u8>) -> Result<(), VerkleError> {
+        self.nodes.insert(path.to_vec(), node.into());
+        Ok(())
+    }
+
+    fn delete(&mut self, path: &[u8]) -> Result<(), VerkleError> {
+        self.nodes.remove(path);
+        Ok(())
+    }
+}
+
+/// The value length of a record that deletes its path
+const DELETED: u32 = u32::MAX;
+
+/// A store that appends every change to a single file. Each record is written as
+/// `[path length: u32][node length: u32][path][node]` (little endian), where a node length of `u32::MAX` marks
+/// the deletion of the path, and the latest record of a path replaces all earlier ones.
+///
+/// Only the location of the latest node of each path is kept in memory. The space of replaced and deleted
+/// nodes is not reclaimed.
+///
+/// Cloning a store is O(1): the clone shares the file and the location of every node with the store, and each of
+/// them only sees its own changes from then on, e.g. for each version of a tree to flush its own nodes. Opening a
+/// file again replays the records of every clone that wrote to it, in the order they were written, so the tree
+/// loaded from it may find nodes of other versions: those error as they are loaded, as they do not match the
+/// commitment their parent holds.
+#[derive(Clone, Debug)]
+pub struct FileStore {
+    /// Shared by the clones of the store, which append to it and seek it to read in turn
+    file: Arc<Mutex<File>>,
+    /// The offset and length of the latest node stored at each path
+    index: im::HashMap<Vec<u8>, (u64, usize)>,
+}
+
+impl FileStore {
+    /// Opens the store at `path`, creating an empty store if the file does not exist. A record at the end of the
+    /// file that was cut short (e.g. by a crash while appending) is discarded.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, VerkleError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let file_len = file.metadata()?.len();
+
+        let mut index = im::HashMap::new();
+        let mut reader = BufReader::new(&file);
+        let mut offset = 0;
+        while offset + 8 <= file_len {
+            let mut lengths = [0u8; 8];
+            reader.read_exact(&mut lengths)?;
+            let path_len = u32::from_le_bytes(lengths[0..4].try_into().unwrap()) as u64;
+            let node_len = u32::from_le_bytes(lengths[4..8].try_into().unwrap());
+            let node_size = if node_len == DELETED {
+                0
+            } else {
+                node_len as u64
+            };
+            let end = offset + 8 + path_len + node_size;
+            if end > file_len {
+                break;
+            }
+
+            let mut path = vec![0u8; path_len as usize];
+            reader.read_exact(&mut path)?;
+            reader.seek_relative(node_size as i64)?;
+            if node_len == DELETED {
+                index.remove(&path);
+            } else {
+                index.insert(path, (offset + 8 + path_len, node_len as usize));
+            }
+            offset = end;
+        }
+
+        if offset < file_len {
+            file.set_len(offset)?;
+        }
+
+        Ok(Self {
+            file: Arc::new(Mutex::new(file)),
+            index,
+        })
+    }
+
+    /// The number of stored nodes
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Appends a record to the file, returning the offset of its node
+    fn append(&self, path: &[u8], node_len: u32, node: &[u8]) -> Result<u64, VerkleError> {
+        let path_len = u32::try_from(path.len())
+            .map_err(|_| VerkleError::StoreError("Path is too long to store".to_string()))?;
+        let mut record = Vec::with_capacity(8 + path.len() + node.len());
+        record.extend(path_len.to_le_bytes());
+        record.extend(node_len.to_le_bytes());
+        record.extend(path);
+        record.extend(node);
+
+        let mut file = self.lock()?;
+        let offset = file.seek(SeekFrom::End(0))?;
+        file.write_all(&record)?;
+        Ok(offset + 8 + path.len() as u64)
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, File>, VerkleError> {
+        self.file.lock().map_err(|_| {
+            VerkleError::StoreError("The store file was poisoned by a failed access".to_string())
+        })
+    }
+}
+
+impl NodeStore for FileStore {
+    fn get(&self, path: &[u8]) -> Result<Option<Vec<u8>>, VerkleError> {
+        let (offset, len) = match self.index.get(path) {
+            Some(location) => *location,
+            None => return Ok(None),
+        };
+
+        let mut node = vec![0u8; len];
+        let mut file = self.lock()?;
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut node)?;
+        Ok(Some(node))
+    }
+
+    fn put(&mut self, path: &[u8], node: Vec<u8>) -> Result<(), VerkleError> {
+        let node_len = u32::try_from(node.len())
+            .ok()
+            .filter(|&l| l != DELETED)
+            .ok_or_else(|| VerkleError::StoreError("Node is too large to store".to_string()))?;
+        let offset = self.append(path, node_len, &node)?;
+        self.index.insert(path.to_vec(), (offset, node.len()));
+        Ok(())
+    }
+
+    fn delete(&mut self, path: &[u8]) -> Result<(), VerkleError> {
+        if self.index.remove(path).is_some() {
+            self.append(path, DELETED, &[])?;
+        }
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<(), VerkleError> {
+        self.lock()?.sync_data()?;
+        Ok(())
+    }
+}
+
+/// The bytes of a path that a node is stored at
+pub(crate) fn path_bytes<K: Pod>(path: &[K]) -> Vec<u8> {
+    cast_slice(path).to_vec()
+}
+
+impl<const N: usize, K, VC, T> Node<N, K, VC, T>
+where
+    K: Eq + Hash + Zero + Copy + Pod + Into<usize>,
+    VC: VectorCommitment,
+    VC::Commitment: CanonicalSerialize + CanonicalDeserialize,
+    <VC::Data as VCData>::Item: Copy + num::One,
+    T: SplittableValue<Output = <VC::Data as VCData>::Item>
+        + Zero
+        + Clone
+        + PartialEq
+        + CanonicalSerialize
+        + CanonicalDeserialize,
+{
+    pub(crate) fn is_persisted(&self) -> bool {
+        match self {
+            Self::Internal { persisted, .. } => *persisted,
+            Self::Extension { persisted, .. } => *persisted,
+            Self::Stub { persisted, .. } => *persisted,
+        }
+    }
+
+    /// Writes the record of this node. Children are only written as their units and clean commitments.
+    fn write_record<W: Write>(&self, writer: &mut W) -> Result<(), VerkleError> {
+        match self {
+            Self::Internal {
+                children, unloaded, ..
+            } => {
+                INTERNAL_TAG.serialize_compressed(&mut *writer)?;
+                write_commitments(&[&self.clean_commitment().cloned()], writer)?;
+
+                let mut sorted: Vec<(K, Option<VC::Commitment>)> = children
+                    .iter()
+                    .map(|(k, child)| (*k, child.clean_commitment().cloned()))
+                    .chain(unloaded.iter().map(|(k, c)| (*k, Some(c.clone()))))
+                    .collect();
+                sorted.sort_by_key(|(k, _)| (*k).into());
+                (sorted.len() as u64).serialize_compressed(&mut *writer)?;
+                for (k, commit) in sorted {
+                    write_unit(&k, writer)?;
+                    write_commitments(&[&commit], writer)?;
+                }
+                Ok(())
+            }
+            Self::Extension { .. } | Self::Stub { .. } => {
+                self.write_snapshot(writer, true, &mut vec![], &|_: &[K]| unreachable!())
+            }
+        }
+    }
+
+    /// Reads the record of the node at `path`. Children stored with a commitment are left unloaded, while the
+    /// others are loaded from `store` right away, as their commitment has to be generated.
+    fn read_record<R: Read, S: NodeStore>(
+        reader: &mut R,
+        path: &mut Vec<K>,
+        store: &S,
+        width: usize,
+    ) -> Result<Self, VerkleError> {
+        let mut tag = [0u8];
+        reader
+            .read_exact(&mut tag)
+            .map_err(ark_serialize::SerializationError::from)?;
+        let mut node = if tag[0] == INTERNAL_TAG {
+            let commit = read_commitments(1, reader)?.remove(0);
+            let count = u64::deserialize_compressed(&mut *reader)?;
+            let mut children = HashMap::new();
+            let mut unloaded = HashMap::new();
+            for _ in 0..count {
+                let k: K = read_slot(reader, width)?;
+                match read_commitments::<VC::Commitment, _>(1, reader)?.remove(0) {
+                    Some(c) => {
+                        unloaded.insert(k, c);
+                    }
+                    None => {
+                        path.push(k);
+                        children.insert(k, Arc::new(Self::load(store, path, width)?));
+                        path.pop();
+                    }
+                }
+            }
+
+            Self::Internal {
+                commit,
+                children,
+                unloaded,
+                changed: HashMap::new(),
+                persisted: false,
+            }
+        } else {
+            Self::read_snapshot(&mut tag.as_slice().chain(reader), path, width, true)?
+        };
+
+        node.set_persisted(true);
+        Ok(node)
+    }
+
+    /// Loads the node stored at `path`, erroring if there is none
+    pub(crate) fn load<S: NodeStore>(
+        store: &S,
+        path: &mut Vec<K>,
+        width: usize,
+    ) -> Result<Self, VerkleError> {
+        let record = store.get(&path_bytes(path))?.ok_or_else(|| {
+            VerkleError::StoreError(format!("No node is stored at {:?}", path_bytes(path)))
+        })?;
+        Self::read_record(&mut record.as_slice(), path, store, width)
+    }
+
+    /// Loads the root stored in `store` after the `header` it is preceded by, or returns None if the store is empty
+    pub(crate) fn load_root<S: NodeStore, H>(
+        store: &S,
+        width: usize,
+        header: impl FnOnce(&mut &[u8]) -> Result<H, VerkleError>,
+    ) -> Result<Option<(H, Self)>, VerkleError> {
+        let record = match store.get(&[])? {
+            Some(r) => r,
+            None => return Ok(None),
+        };
+        let mut reader = record.as_slice();
+        let header = header(&mut reader)?;
+        let root = Self::read_record(&mut reader, &mut vec![], store, width)?;
+        Ok(Some((header, root)))
+    }
+
+    /// Whether `load_path` has nothing to load below this node at `depth`
+    pub(crate) fn is_path_loaded(&self, stem: &[K], depth: usize, siblings: bool) -> bool {
+        match self {
+            Self::Internal {
+                children, unloaded, ..
+            } => {
+                let k = stem[depth];
+                let sibling_unloaded =
+                    siblings && children.len() + unloaded.len() == 2 && !unloaded.is_empty();
+                !unloaded.contains_key(&k)
+                    && !sibling_unloaded
+                    && children
+                        .get(&k)
+                        .is_none_or(|c| c.is_path_loaded(stem, depth + 1, siblings))
+            }
+            Self::Extension { .. } | Self::Stub { .. } => true,
+        }
+    }
+
+    /// Loads every unloaded child on the path to `stem` from `store`, starting at this node at `depth`. The stems
+    /// of all extension nodes loaded are pushed to `loaded`. Only the nodes that get a child loaded are copied if
+    /// they are shared with another version of the tree.
+    ///
+    /// With `siblings`, the other child of every internal node on the path with two children is loaded too, as
+    /// `remove` collapses it into the place of its parent if it is an extension node.
+    pub(crate) fn load_path<S: NodeStore>(
+        &mut self,
+        stem: &[K],
+        depth: usize,
+        store: &S,
+        width: usize,
+        siblings: bool,
+        loaded: &mut Vec<Key<N, K>>,
+    ) -> Result<(), VerkleError> {
+        let (children, unloaded) = match self {
+            Self::Internal {
+                children, unloaded, ..
+            } => (children, unloaded),
+            Self::Extension { .. } | Self::Stub { .. } => return Ok(()),
+        };
+
+        let k = stem[depth];
+        let mut to_load = vec![k];
+        if siblings && children.len() + unloaded.len() == 2 {
+            to_load.extend(unloaded.keys().copied());
+        }
+        for u in to_load {
+            if let Some(commit) = unloaded.get(&u) {
+                let mut path = stem[0..depth].to_vec();
+                path.push(u);
+                let child = Self::load(store, &mut path, width)?;
+                // The records of another version of the tree may have replaced the child's in a shared store
+                if child.clean_commitment() != Some(commit) {
+                    return Err(VerkleError::StoreError(format!(
+                        "The node stored at {:?} does not match its parent",
+                        path_bytes(&path)
+                    )));
+                }
+                child.collect_stems(loaded);
+                children.insert(u, Arc::new(child));
+                unloaded.remove(&u);
+            }
+        }
+
+        match children.get_mut(&k) {
+            Some(child) if !child.is_path_loaded(stem, depth + 1, siblings) => {
+                Arc::make_mut(child).load_path(stem, depth + 1, store, width, siblings, loaded)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Pushes the stems of this node and its loaded descendants that are extension nodes to `stems`
+    pub(crate) fn collect_stems(&self, stems: &mut Vec<Key<N, K>>) {
+        match self {
+            Self::Internal { children, .. } => {
+                children.values().for_each(|c| c.collect_stems(stems));
+            }
+            Self::Extension { stem, .. } => stems.push(*stem),
+            Self::Stub { .. } => {}
+        }
+    }
+
+    /// Writes the record of this node, preceded by `prefix`, and of its descendants to `store`, if they changed
+    /// since they were last written. Children are written before their parent, and a node that is persisted has
+    /// no children that are not.
+    pub(crate) fn flush<S: NodeStore>(
+        &mut self,
+        path: &mut Vec<K>,
+        store: &mut S,
+        prefix: Vec<u8>,
+    ) -> Result<(), VerkleError> {
+        if self.is_persisted() {
+            return Ok(());
+        }
+
+        if let Self::Internal { children, .. } = self {
+            for (k, child) in children.iter_mut().filter(|(_, c)| !c.is_persisted()) {
+                path.push(*k);
+                Arc::make_mut(child).flush(path, store, vec![])?;
+                path.pop();
+            }
+        }
+
+        let mut record = prefix;
+        self.write_record(&mut record)?;
+        store.put(&path_bytes(path), record)?;
+        self.set_persisted(true);
+        Ok(())
+    }
+
+    /// Drops every loaded descendant whose record in the store is up to date and includes its commitment, keeping
+    /// only that commitment in its parent.
+    pub(crate) fn unload_children(&mut self) {
+        if let Self::Internal {
+            children, unloaded, ..
+        } = self
+        {
+            let stored: Vec<K> = children
+                .iter()
+                .filter(|(_, c)| c.is_persisted() && c.clean_commitment().is_some())
+                .map(|(k, _)| *k)
+                .collect();
+            for k in stored {
+                let child = children.remove(&k).unwrap();
+                unloaded.insert(k, child.commitment().unwrap().clone());
+            }
+            children
+                .values_mut()
+                .filter(|c| matches!(c.as_ref(), Self::Internal { .. }))
+                .for_each(|c| Arc::make_mut(c).unload_children());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nodes");
+
+        let mut store = FileStore::open(&path).unwrap();
+        store.put(&[], vec![1, 2, 3]).unwrap();
+        store.put(&[7], vec![4]).unwrap();
+        store.put(&[7, 8], vec![5, 6]).unwrap();
+        store.put(&[7], vec![9, 9]).unwrap();
+        store.delete(&[7, 8]).unwrap();
+        store.delete(&[42]).unwrap();
+        store.sync().unwrap();
+
+        assert!(store.get(&[]).unwrap() == Some(vec![1, 2, 3]));
+        assert!(store.get(&[7]).unwrap() == Some(vec![9, 9]));
+        assert!(store.get(&[7, 8]).unwrap().is_none());
+        assert!(store.len() == 2);
+        drop(store);
+
+        // Every change is replayed on open, and a record cut short is discarded
+        let complete_len = std::fs::metadata(&path).unwrap().len();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[3, 0, 0, 0, 10, 0, 0, 0, 1, 2]).unwrap();
+        drop(file);
+
+        let mut store = FileStore::open(&path).unwrap();
+        assert!(std::fs::metadata(&path).unwrap().len() == complete_len);
+        assert!(store.get(&[]).unwrap() == Some(vec![1, 2, 3]));
+        assert!(store.get(&[7]).unwrap() == Some(vec![9, 9]));
+        assert!(store.get(&[7, 8]).unwrap().is_none());
+
+        store.put(&[7, 8], vec![]).unwrap();
+        assert!(store.get(&[7, 8]).unwrap() == Some(vec![]));
+        assert!(store.len() == 3);
+
+        // Clones share the file, but not their changes
+        let mut clone = store.clone();
+        assert!(Arc::ptr_eq(&clone.file, &store.file));
+        clone.put(&[7], vec![1]).unwrap();
+        clone.delete(&[]).unwrap();
+        assert!(clone.get(&[7]).unwrap() == Some(vec![1]));
+        assert!(clone.get(&[]).unwrap().is_none());
+        assert!(store.get(&[7]).unwrap() == Some(vec![9, 9]));
+        assert!(store.get(&[]).unwrap() == Some(vec![1, 2, 3]));
+        store.put(&[9], vec![2]).unwrap();
+        assert!(store.get(&[9]).unwrap() == Some(vec![2]));
+        assert!(clone.get(&[9]).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_memory_store_clone() {
+        let mut store = MemoryStore::default();
+        store.put(&[1], vec![1, 2]).unwrap();
+        store.put(&[2], vec![3]).unwrap();
+
+        let mut clone = store.clone();
+        assert!(Arc::ptr_eq(&clone.nodes[&vec![1]], &store.nodes[&vec![1]]));
+        clone.put(&[2], vec![4]).unwrap();
+        assert!(store.get(&[2]).unwrap() == Some(vec![3]));
+        assert!(clone.get(&[2]).unwrap() == Some(vec![4]));
+        assert!(Arc::ptr_eq(&clone.nodes[&vec![1]], &store.nodes[&vec![1]]));
+    }
+}
@@ -0,0 +1,238 @@
+//! Derivation of tree keys according to the Ethereum verkle tree spec ([EIP-6800](https://eips.ethereum.org/EIPS/eip-6800)).
+//!
+//! Every account's header fields, code chunks and storage slots are mapped to a 32-byte key. The first 31 bytes (the stem)
+//! are the Pedersen hash of the address and a tree index, and the last byte is the sub index inside of the stem's
+//! extension node. This places an account's header, the start of its code and its first storage slots in the same
+//! extension node.
+//!
+//! The hash is only defined over the Banderwagon CRS of the spec, so the keys match those of the other clients.
+
+use ark_ff::{field_hashers::HashToField, BigInteger, PrimeField};
+use num::BigUint;
+
+use vector_commit::{
+    banderwagon::{Banderwagon, Fr},
+    ipa::{IPAUniversalParams, IPA},
+    lagrange_basis::LagrangeBasis,
+    linear_domain::LinearDomain,
+    VCData, VectorCommitment,
+};
+
+use crate::Key;
+
+/// The length in bytes of an Ethereum tree key
+pub const KEY_LENGTH: usize = 32;
+
+/// An Ethereum address, left-padded to 32 bytes
+pub type Address32 = [u8; 32];
+
+pub const VERSION_LEAF_KEY: u8 = 0;
+pub const BALANCE_LEAF_KEY: u8 = 1;
+pub const NONCE_LEAF_KEY: u8 = 2;
+pub const CODE_KECCAK_LEAF_KEY: u8 = 3;
+pub const CODE_SIZE_LEAF_KEY: u8 = 4;
+pub const HEADER_STORAGE_OFFSET: u64 = 64;
+pub const CODE_OFFSET: u64 = 128;
+pub const VERKLE_NODE_WIDTH: u64 = 256;
+
+/// The main storage offset is `256^31`, which is `2^248`
+const MAIN_STORAGE_OFFSET_BITS: u64 = 248;
+
+/// Left-pad a 20-byte address to 32 bytes
+pub fn to_address32(address: &[u8; 20]) -> Address32 {
+    let mut res = [0u8; 32];
+    res[12..].copy_from_slice(address);
+    res
+}
+
+/// Hashes the 64 bytes of `address || tree_index` by committing to them as five field elements with the first five
+/// points of the CRS:
+/// - `2 + 256 * 64`, encoding the length of the input
+/// - The lower and upper 16 bytes of the address
+/// - The lower and upper 16 bytes of the little-endian tree index
+///
+/// The hash is the commitment mapped to the scalar field, as 32 little-endian bytes.
+///
+/// ! Panics if `tree_index` does not fit into 32 bytes
+pub fn pedersen_hash<const N: usize, H>(
+    crs: &IPAUniversalParams<N, Banderwagon, H>,
+    address: &Address32,
+    tree_index: &BigUint,
+) -> [u8; KEY_LENGTH]
+where
+    H: HashToField<Fr> + Sync,
+{
+    let mut tree_index_bytes = tree_index.to_bytes_le();
+    assert!(
+        tree_index_bytes.len() <= 32,
+        "Tree index must fit into 32 bytes"
+    );
+    tree_index_bytes.resize(32, 0);
+
+    let mut input = vec![Fr::from(2 + 256 * 64u64)];
+    input.extend(
+        address
+            .chunks(16)
+            .chain(tree_index_bytes.chunks(16))
+            .map(Fr::from_le_bytes_mod_order),
+    );
+
+    // The evaluation domain does not change the commitment, as only the first five points are used
+    let data = LagrangeBasis::<Fr, LinearDomain<Fr>>::from_vec(input);
+    let commitment = IPA::<N, Banderwagon, H, LinearDomain<Fr>>::commit(crs, &data).unwrap();
+
+    let mut bytes = [0u8; KEY_LENGTH];
+    bytes.copy_from_slice(&commitment.map_to_scalar_field().into_bigint().to_bytes_le());
+    bytes
+}
+
+/// Derive the tree key of the leaf at `sub_index` in the extension node of `(address, tree_index)`
+pub fn get_tree_key<const N: usize, H>(
+    crs: &IPAUniversalParams<N, Banderwagon, H>,
+    address: &Address32,
+    tree_index: &BigUint,
+    sub_index: u8,
+) -> Key<KEY_LENGTH, u8>
+where
+    H: HashToField<Fr> + Sync,
+{
+    let hash = pedersen_hash(crs, address, tree_index);
+    let mut key = [0u8; KEY_LENGTH];
+    key[0..KEY_LENGTH - 1].copy_from_slice(&hash[0..KEY_LENGTH - 1]);
+    key[KEY_LENGTH - 1] = sub_index;
+    key
+}
+
+/// Derive the tree key of a field in the account header of `address`, e.g `BALANCE_LEAF_KEY`
+pub fn get_tree_key_for_account_header<const N: usize, H>(
+    crs: &IPAUniversalParams<N, Banderwagon, H>,
+    address: &Address32,
+    leaf_key: u8,
+) -> Key<KEY_LENGTH, u8>
+where
+    H: HashToField<Fr> + Sync,
+{
+    get_tree_key(crs, address, &BigUint::from(0u8), leaf_key)
+}
+
+/// Derive the tree key of the 31-byte code chunk at `chunk_id`
+pub fn get_tree_key_for_code_chunk<const N: usize, H>(
+    crs: &IPAUniversalParams<N, Banderwagon, H>,
+    address: &Address32,
+    chunk_id: u64,
+) -> Key<KEY_LENGTH, u8>
+where
+    H: HashToField<Fr> + Sync,
+{
+    let position = BigUint::from(CODE_OFFSET) + chunk_id;
+    get_tree_key_at_position(crs, address, position)
+}
+
+/// Derive the tree key of the storage slot `storage_key`. The first slots are stored alongside the account header,
+/// while the rest are stored in the main storage starting at `256^31`.
+pub fn get_tree_key_for_storage_slot<const N: usize, H>(
+    crs: &IPAUniversalParams<N, Banderwagon, H>,
+    address: &Address32,
+    storage_key: &BigUint,
+) -> Key<KEY_LENGTH, u8>
+where
+    H: HashToField<Fr> + Sync,
+{
+    let position = if *storage_key < BigUint::from(CODE_OFFSET - HEADER_STORAGE_OFFSET) {
+        storage_key + HEADER_STORAGE_OFFSET
+    } else {
+        (BigUint::from(1u8) << MAIN_STORAGE_OFFSET_BITS) + storage_key
+    };
+    get_tree_key_at_position(crs, address, position)
+}
+
+/// Split an absolute `position` in the account's key space into its tree index and sub index
+fn get_tree_key_at_position<const N: usize, H>(
+    crs: &IPAUniversalParams<N, Banderwagon, H>,
+    address: &Address32,
+    position: BigUint,
+) -> Key<KEY_LENGTH, u8>
+where
+    H: HashToField<Fr> + Sync,
+{
+    let width = BigUint::from(VERKLE_NODE_WIDTH);
+    let tree_index = &position / &width;
+    let sub_index = (&position % &width)
+        .to_u64_digits()
+        .first()
+        .copied()
+        .unwrap_or(0) as u8;
+    get_tree_key(crs, address, &tree_index, sub_index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ark_ff::field_hashers::DefaultFieldHasher;
+    use sha2::Sha256;
+    use vector_commit::ipa::IPAPointGenerator;
+
+    type Hasher = DefaultFieldHasher<Sha256>;
+
+    const WIDTH: usize = 256;
+    type TestIPA = IPA<WIDTH, Banderwagon, Hasher, LinearDomain<Fr>>;
+
+    fn crs() -> IPAUniversalParams<WIDTH, Banderwagon, Hasher> {
+        let mut point_gen = IPAPointGenerator::default();
+        point_gen.set_max(WIDTH + 1);
+        TestIPA::setup(WIDTH, &point_gen).unwrap()
+    }
+
+    /// The keys of the zero address over the Ethereum CRS
+    #[test]
+    fn test_tree_key_values() {
+        let crs = crs();
+        let address = [0u8; 32];
+        let key = |key: Key<KEY_LENGTH, u8>| hex::encode(key);
+
+        let version = get_tree_key_for_account_header(&crs, &address, VERSION_LEAF_KEY);
+        assert!(key(version) == "1a100684fd68185060405f3f160e4bb6e034194336b547bdae323f888d533200");
+
+        // Chunk 300 is at position 128 + 300 = 256 + 172
+        let code = get_tree_key_for_code_chunk(&crs, &address, 300);
+        assert!(key(code) == "7c2f303854b78b803e520069bd50708f7924cef74564f861f7abe83edff92aac");
+
+        let slot = get_tree_key_for_storage_slot(&crs, &address, &BigUint::from(0x1234u64));
+        assert!(key(slot) == "ce742028eb00f02a85747508f7d8318f447bb14aa954fed4f00ddec8e27ec334");
+    }
+
+    #[test]
+    fn test_tree_key_layout() {
+        let crs = crs();
+        let address = to_address32(&[0xab; 20]);
+        let other_address = to_address32(&[0xcd; 20]);
+
+        let balance = get_tree_key_for_account_header(&crs, &address, BALANCE_LEAF_KEY);
+        let nonce = get_tree_key_for_account_header(&crs, &address, NONCE_LEAF_KEY);
+        assert!(balance[31] == BALANCE_LEAF_KEY && nonce[31] == NONCE_LEAF_KEY);
+        assert!(balance[0..31] == nonce[0..31]);
+
+        // The first code chunks and storage slots share the stem of the header
+        let code = get_tree_key_for_code_chunk(&crs, &address, 5);
+        assert!(code[0..31] == balance[0..31] && code[31] == 128 + 5);
+        let slot = get_tree_key_for_storage_slot(&crs, &address, &BigUint::from(3u8));
+        assert!(slot[0..31] == balance[0..31] && slot[31] == 64 + 3);
+
+        // Later code chunks move into the next tree index
+        let code = get_tree_key_for_code_chunk(&crs, &address, 130);
+        let expected = get_tree_key(&crs, &address, &BigUint::from(1u8), 2);
+        assert!(code == expected);
+
+        // Main storage starts at 256^31
+        let storage_key = BigUint::from(0x1234u64);
+        let slot = get_tree_key_for_storage_slot(&crs, &address, &storage_key);
+        let tree_index = (BigUint::from(1u8) << 240) + BigUint::from(0x12u8);
+        let expected = get_tree_key(&crs, &address, &tree_index, 0x34);
+        assert!(slot == expected);
+        assert!(slot[0..31] != balance[0..31]);
+
+        let other = get_tree_key_for_account_header(&crs, &other_address, BALANCE_LEAF_KEY);
+        assert!(other[0..31] != balance[0..31]);
+    }
+}
@@ -0,0 +1,113 @@
+//! Tree versions keep the state of a `VerkleTree` at several points in time, e.g. after each of the last blocks,
+//! to serve reorgs and historical proofs.
+//!
+//! Every version is a clone of the tree, so versions share all nodes that did not change between them, and a tree
+//! changed after being kept as a version only copies the nodes on the paths it changes. Dropping a version releases
+//! the nodes that no other version (or tree) holds.
+//!
+//! Versions share the records of their store too, so they can be flushed, unloaded and evicted with a node cache
+//! like any tree: e.g. the versions of a tree with a `FileStore` all append to its file, and each reads the records
+//! of the nodes it flushed or shares.
+
+use std::{collections::BTreeMap, fmt::Debug, hash::Hash};
+
+use num::Zero;
+use vector_commit::{VCData, VectorCommitment};
+
+use crate::{MemoryStore, SplittableValue, VerkleTree};
+
+/// Versions of a tree, by their name (e.g. a block number). Versions are ordered by their name, so the oldest
+/// versions can be dropped at once with `prune_before`.
+pub struct TreeVersions<V, const N: usize, K, VC, T, G, Domain, S = MemoryStore>
+where
+    K: Eq + Hash,
+    VC: VectorCommitment,
+    T: SplittableValue<Output = <VC::Data as VCData>::Item> + Zero + Clone + PartialEq + Debug,
+{
+    versions: BTreeMap<V, VerkleTree<N, K, VC, T, G, Domain, S>>,
+}
+
+impl<V, const N: usize, K, VC, T, G, Domain, S> TreeVersions<V, N, K, VC, T, G, Domain, S>
+where
+    V: Ord,
+    K: Eq + Hash,
+    VC: VectorCommitment,
+    T: SplittableValue<Output = <VC::Data as VCData>::Item> + Zero + Clone + PartialEq + Debug,
+{
+    pub fn new() -> Self {
+        Self {
+            versions: BTreeMap::new(),
+        }
+    }
+
+    /// Keeps `tree` as the version `name`, returning the version it replaces. Pass a clone of a tree to keep
+    /// changing the tree without affecting the version.
+    pub fn insert(
+        &mut self,
+        name: V,
+        tree: VerkleTree<N, K, VC, T, G, Domain, S>,
+    ) -> Option<VerkleTree<N, K, VC, T, G, Domain, S>> {
+        self.versions.insert(name, tree)
+    }
+
+    pub fn get(&self, name: &V) -> Option<&VerkleTree<N, K, VC, T, G, Domain, S>> {
+        self.versions.get(name)
+    }
+
+    /// The version `name`, e.g. to read values or prove keys (which may load nodes from its store)
+    pub fn get_mut(&mut self, name: &V) -> Option<&mut VerkleTree<N, K, VC, T, G, Domain, S>> {
+        self.versions.get_mut(name)
+    }
+
+    /// A copy of the version `name` to build a new version from, e.g. after a reorg. Copying is O(1), and the
+    /// version is unaffected by changes to the copy.
+    pub fn checkout(&self, name: &V) -> Option<VerkleTree<N, K, VC, T, G, Domain, S>>
+    where
+        K: Copy,
+        S: Clone,
+    {
+        self.versions.get(name).cloned()
+    }
+
+    /// The version with the greatest name, if any
+    pub fn latest(&self) -> Option<&VerkleTree<N, K, VC, T, G, Domain, S>> {
+        self.versions.values().next_back()
+    }
+
+    /// Drops the version `name`, returning it
+    pub fn remove(&mut self, name: &V) -> Option<VerkleTree<N, K, VC, T, G, Domain, S>> {
+        self.versions.remove(name)
+    }
+
+    /// Drops every version whose name is less than `name`
+    pub fn prune_before(&mut self, name: &V) {
+        self.versions = self.versions.split_off(name);
+    }
+
+    /// The names of all versions, in ascending order
+    pub fn names(&self) -> impl Iterator<Item = &V> {
+        self.versions.keys()
+    }
+
+    /// The number of versions
+    pub fn len(&self) -> usize {
+        self.versions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.versions.is_empty()
+    }
+}
+
+impl<V, const N: usize, K, VC, T, G, Domain, S> Default
+    for TreeVersions<V, N, K, VC, T, G, Domain, S>
+where
+    V: Ord,
+    K: Eq + Hash,
+    VC: VectorCommitment,
+    T: SplittableValue<Output = <VC::Data as VCData>::Item> + Zero + Clone + PartialEq + Debug,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,656 @@
+//! Execution witnesses carry the values a block reads and writes, and a proof of them against the pre-state root,
+//! in the layout Ethereum clients ship with blocks (EIP-6800), as SSZ or as JSON with hex strings.
+//!
+//! The `state_diff` lists every touched key by stem, with its value before the block (`current_value`) and after it
+//! (`new_value`, if written). The `verkle_proof` is a `VerkleProof` of the current values of all of these keys, in
+//! this order: the status byte of each stem, the other stems found instead of absent stems and the commitments on
+//! the proven paths by path, followed by the aggregated opening of the multiproof (`d` and `ipa_proof`). The
+//! evaluation of the opening is left out, as the verifier computes it itself.
+//!
+//! Points and scalars are 32 bytes in their compressed canonical serialization. Over the IPA of the spec (Banderwagon,
+//! `LinearDomain` and `EthereumHashToField`), the multiproof runs under the "vt" transcript of the spec, and opens
+//! each vector once at each point, by path. The layout matches the execution witness, but proofs have not been
+//! verified against the witnesses of other clients.
+
+use std::collections::BTreeMap;
+
+use ark_ec::Group;
+use ark_ff::Zero;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use serde::{Deserialize, Serialize};
+use vector_commit::{ipa::IPAProof, multiproof::Multiproof, VectorCommitment};
+
+use crate::{tree_key::KEY_LENGTH, VerkleError, VerkleProof};
+
+/// The length in bytes of an Ethereum stem
+pub const STEM_LENGTH: usize = KEY_LENGTH - 1;
+
+/// The number of folding rounds of an IPA proof over vectors of 256 items
+pub const IPA_PROOF_DEPTH: usize = 8;
+
+/// The value (or absence) of each key before a block
+type Claims<T> = Vec<([u8; KEY_LENGTH], Option<T>)>;
+
+/// The value of a key before and after a block. A value of `None` is absent (before), or not written (after).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SuffixStateDiff {
+    pub suffix: u8,
+    #[serde(with = "hex_option")]
+    pub current_value: Option<[u8; 32]>,
+    #[serde(with = "hex_option")]
+    pub new_value: Option<[u8; 32]>,
+}
+
+/// The touched keys of a stem, by their last byte
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StemStateDiff {
+    #[serde(with = "hex_array")]
+    pub stem: [u8; STEM_LENGTH],
+    pub suffix_diffs: Vec<SuffixStateDiff>,
+}
+
+/// The opening of an IPA proof: the left and right commitments of each folding round, and the folded element
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IpaProof {
+    #[serde(with = "hex_rounds")]
+    pub cl: [[u8; 32]; IPA_PROOF_DEPTH],
+    #[serde(with = "hex_rounds")]
+    pub cr: [[u8; 32]; IPA_PROOF_DEPTH],
+    #[serde(with = "hex_array")]
+    pub final_evaluation: [u8; 32],
+}
+
+/// A `VerkleProof` of the keys of a state diff
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WitnessProof {
+    #[serde(with = "hex_list")]
+    pub other_stems: Vec<[u8; STEM_LENGTH]>,
+    #[serde(with = "hex_bytes")]
+    pub depth_extension_present: Vec<u8>,
+    #[serde(with = "hex_list")]
+    pub commitments_by_path: Vec<[u8; 32]>,
+    #[serde(with = "hex_array")]
+    pub d: [u8; 32],
+    pub ipa_proof: IpaProof,
+}
+
+/// The state a block touches, and a proof of its values before the block
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionWitness {
+    pub state_diff: Vec<StemStateDiff>,
+    pub verkle_proof: WitnessProof,
+}
+
+/// Groups `(key, current value, new value)` by stem into a state diff, sorted by stem and suffix
+///
+/// Errors if a key is given twice, or a value does not serialize to 32 bytes.
+pub fn state_diff<T: CanonicalSerialize>(
+    values: &[([u8; KEY_LENGTH], Option<T>, Option<T>)],
+) -> Result<Vec<StemStateDiff>, VerkleError> {
+    let mut stems = BTreeMap::<[u8; STEM_LENGTH], BTreeMap<u8, SuffixStateDiff>>::new();
+    for (key, current, new) in values {
+        let value_bytes = |value: &Option<T>| value.as_ref().map(to_bytes32).transpose();
+        let (stem, suffix) = split_key(key);
+        let diff = SuffixStateDiff {
+            suffix,
+            current_value: value_bytes(current)?,
+            new_value: value_bytes(new)?,
+        };
+        if stems
+            .entry(stem)
+            .or_default()
+            .insert(suffix, diff)
+            .is_some()
+        {
+            return Err(invalid(format!("Key {:?} is given twice", key)));
+        }
+    }
+
+    Ok(stems
+        .into_iter()
+        .map(|(stem, suffixes)| StemStateDiff {
+            stem,
+            suffix_diffs: suffixes.into_values().collect(),
+        })
+        .collect())
+}
+
+impl StemStateDiff {
+    /// The keys of the stem, in the order they are proven
+    pub fn keys(&self) -> impl Iterator<Item = [u8; KEY_LENGTH]> + '_ {
+        self.suffix_diffs
+            .iter()
+            .map(|diff| join_key(&self.stem, diff.suffix))
+    }
+}
+
+impl ExecutionWitness {
+    /// The witness of `state_diff`, given a `proof` of its keys (see `keys`) in the tree before the block
+    ///
+    /// Errors if the proof does not hold a status for each stem, or a point or scalar does not serialize to 32 bytes.
+    pub fn new<G, VC>(
+        state_diff: Vec<StemStateDiff>,
+        proof: &VerkleProof<KEY_LENGTH, u8, VC>,
+    ) -> Result<Self, VerkleError>
+    where
+        G: Group,
+        VC: VectorCommitment<Commitment = G, Proof = IPAProof<G>>,
+    {
+        if proof.depth_extension_present.len() != state_diff.len() {
+            return Err(invalid(format!(
+                "The proof proves {} stems instead of the {} of the state diff",
+                proof.depth_extension_present.len(),
+                state_diff.len()
+            )));
+        }
+
+        let ipa = proof.multiproof.proof();
+        Ok(Self {
+            state_diff,
+            verkle_proof: WitnessProof {
+                other_stems: proof
+                    .other_stems
+                    .iter()
+                    .map(|other| split_key(other).0)
+                    .collect(),
+                depth_extension_present: proof.depth_extension_present.clone(),
+                commitments_by_path: proof
+                    .commitments_by_path
+                    .iter()
+                    .map(to_bytes32)
+                    .collect::<Result<_, _>>()?,
+                d: to_bytes32(proof.multiproof.d())?,
+                ipa_proof: IpaProof {
+                    cl: to_rounds(ipa.l())?,
+                    cr: to_rounds(ipa.r())?,
+                    final_evaluation: to_bytes32(&ipa.tip())?,
+                },
+            },
+        })
+    }
+
+    /// The keys of the state diff, in the order they are proven
+    pub fn keys(&self) -> Vec<[u8; KEY_LENGTH]> {
+        self.state_diff.iter().flat_map(|s| s.keys()).collect()
+    }
+
+    /// The values of the keys before the block, as claimed to `VerkleTree::verify_proof`
+    pub fn claims<T: CanonicalDeserialize>(&self) -> Result<Claims<T>, VerkleError> {
+        self.state_diff
+            .iter()
+            .flat_map(|s| s.keys().zip(s.suffix_diffs.iter()))
+            .map(|(key, diff)| Ok((key, diff.current_value.map(from_bytes32).transpose()?)))
+            .collect()
+    }
+
+    /// The values of the written keys after the block
+    pub fn writes<T: CanonicalDeserialize>(
+        &self,
+    ) -> Result<Vec<([u8; KEY_LENGTH], T)>, VerkleError> {
+        self.state_diff
+            .iter()
+            .flat_map(|s| s.keys().zip(s.suffix_diffs.iter()))
+            .filter_map(|(key, diff)| diff.new_value.map(|v| Ok((key, from_bytes32(v)?))))
+            .collect()
+    }
+
+    /// Decodes the proof of the keys, to be verified with `VerkleTree::verify_proof` against the pre-state root
+    ///
+    /// Errors if a point or scalar does not deserialize.
+    pub fn proof<G, VC>(&self) -> Result<VerkleProof<KEY_LENGTH, u8, VC>, VerkleError>
+    where
+        G: Group,
+        VC: VectorCommitment<Commitment = G, Proof = IPAProof<G>>,
+    {
+        let proof = &self.verkle_proof;
+        let commitments_by_path = proof
+            .commitments_by_path
+            .iter()
+            .map(|bytes| from_bytes32(*bytes))
+            .collect::<Result<_, _>>()?;
+
+        let ipa = &proof.ipa_proof;
+        let from_rounds = |rounds: &[[u8; 32]]| -> Result<Vec<G>, VerkleError> {
+            rounds.iter().map(|b| from_bytes32(*b)).collect()
+        };
+        let opening = IPAProof::new(
+            from_rounds(&ipa.cl)?,
+            from_rounds(&ipa.cr)?,
+            from_bytes32(ipa.final_evaluation)?,
+            G::ScalarField::zero(),
+        );
+
+        Ok(VerkleProof {
+            depth_extension_present: proof.depth_extension_present.clone(),
+            other_stems: proof.other_stems.iter().map(|s| join_key(s, 0)).collect(),
+            commitments_by_path,
+            multiproof: Multiproof::new(opening, from_bytes32(proof.d)?),
+        })
+    }
+
+    pub fn to_ssz(&self) -> Vec<u8> {
+        let state_diff = ssz::variable_list(self.state_diff.iter().map(StemStateDiff::to_ssz));
+        ssz::container(vec![
+            ssz::Field::Variable(state_diff),
+            ssz::Field::Variable(self.verkle_proof.to_ssz()),
+        ])
+    }
+
+    pub fn from_ssz(bytes: &[u8]) -> Result<Self, VerkleError> {
+        let fields = ssz::read_container(bytes, &[None, None])?;
+        Ok(Self {
+            state_diff: ssz::read_variable_list(fields[0])?
+                .into_iter()
+                .map(StemStateDiff::from_ssz)
+                .collect::<Result<_, _>>()?,
+            verkle_proof: WitnessProof::from_ssz(fields[1])?,
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Witnesses always serialize to JSON")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, VerkleError> {
+        serde_json::from_str(json).map_err(|e| invalid(e.to_string()))
+    }
+}
+
+impl SuffixStateDiff {
+    fn to_ssz(&self) -> Vec<u8> {
+        ssz::container(vec![
+            ssz::Field::Fixed(vec![self.suffix]),
+            ssz::Field::Variable(ssz::optional(&self.current_value)),
+            ssz::Field::Variable(ssz::optional(&self.new_value)),
+        ])
+    }
+
+    fn from_ssz(bytes: &[u8]) -> Result<Self, VerkleError> {
+        let fields = ssz::read_container(bytes, &[Some(1), None, None])?;
+        Ok(Self {
+            suffix: fields[0][0],
+            current_value: ssz::read_optional(fields[1])?,
+            new_value: ssz::read_optional(fields[2])?,
+        })
+    }
+}
+
+impl StemStateDiff {
+    fn to_ssz(&self) -> Vec<u8> {
+        let suffix_diffs =
+            ssz::variable_list(self.suffix_diffs.iter().map(SuffixStateDiff::to_ssz));
+        ssz::container(vec![
+            ssz::Field::Fixed(self.stem.to_vec()),
+            ssz::Field::Variable(suffix_diffs),
+        ])
+    }
+
+    fn from_ssz(bytes: &[u8]) -> Result<Self, VerkleError> {
+        let fields = ssz::read_container(bytes, &[Some(STEM_LENGTH), None])?;
+        Ok(Self {
+            stem: ssz::read_array(fields[0])?,
+            suffix_diffs: ssz::read_variable_list(fields[1])?
+                .into_iter()
+                .map(SuffixStateDiff::from_ssz)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl WitnessProof {
+    fn to_ssz(&self) -> Vec<u8> {
+        let ipa = &self.ipa_proof;
+        ssz::container(vec![
+            ssz::Field::Variable(self.other_stems.concat()),
+            ssz::Field::Variable(self.depth_extension_present.clone()),
+            ssz::Field::Variable(self.commitments_by_path.concat()),
+            ssz::Field::Fixed(self.d.to_vec()),
+            ssz::Field::Fixed(
+                [
+                    ipa.cl.concat(),
+                    ipa.cr.concat(),
+                    ipa.final_evaluation.to_vec(),
+                ]
+                .concat(),
+            ),
+        ])
+    }
+
+    fn from_ssz(bytes: &[u8]) -> Result<Self, VerkleError> {
+        let rounds_size = 32 * IPA_PROOF_DEPTH;
+        let fields = ssz::read_container(
+            bytes,
+            &[None, None, None, Some(32), Some(2 * rounds_size + 32)],
+        )?;
+        let (cl, rest) = fields[4].split_at(rounds_size);
+        let (cr, final_evaluation) = rest.split_at(rounds_size);
+        Ok(Self {
+            other_stems: ssz::read_fixed_list(fields[0])?,
+            depth_extension_present: fields[1].to_vec(),
+            commitments_by_path: ssz::read_fixed_list(fields[2])?,
+            d: ssz::read_array(fields[3])?,
+            ipa_proof: IpaProof {
+                cl: read_rounds(cl)?,
+                cr: read_rounds(cr)?,
+                final_evaluation: ssz::read_array(final_evaluation)?,
+            },
+        })
+    }
+}
+
+fn split_key(key: &[u8; KEY_LENGTH]) -> ([u8; STEM_LENGTH], u8) {
+    let mut stem = [0; STEM_LENGTH];
+    stem.copy_from_slice(&key[0..STEM_LENGTH]);
+    (stem, key[STEM_LENGTH])
+}
+
+fn join_key(stem: &[u8; STEM_LENGTH], suffix: u8) -> [u8; KEY_LENGTH] {
+    let mut key = [0; KEY_LENGTH];
+    key[0..STEM_LENGTH].copy_from_slice(stem);
+    key[STEM_LENGTH] = suffix;
+    key
+}
+
+fn to_bytes32<S: CanonicalSerialize>(item: &S) -> Result<[u8; 32], VerkleError> {
+    let mut bytes = Vec::with_capacity(32);
+    item.serialize_compressed(&mut bytes)?;
+    bytes.try_into().map_err(|b: Vec<u8>| {
+        invalid(format!(
+            "An item serializes to {} bytes instead of 32",
+            b.len()
+        ))
+    })
+}
+
+fn from_bytes32<S: CanonicalDeserialize>(bytes: [u8; 32]) -> Result<S, VerkleError> {
+    Ok(S::deserialize_compressed(&bytes[..])?)
+}
+
+fn to_rounds<G: CanonicalSerialize>(
+    points: &[G],
+) -> Result<[[u8; 32]; IPA_PROOF_DEPTH], VerkleError> {
+    let rounds = points
+        .iter()
+        .map(to_bytes32)
+        .collect::<Result<Vec<_>, _>>()?;
+    rounds.try_into().map_err(|r: Vec<_>| {
+        invalid(format!(
+            "The IPA proof has {} rounds instead of {}",
+            r.len(),
+            IPA_PROOF_DEPTH
+        ))
+    })
+}
+
+fn read_rounds(bytes: &[u8]) -> Result<[[u8; 32]; IPA_PROOF_DEPTH], VerkleError> {
+    let rounds = ssz::read_fixed_list(bytes)?;
+    rounds
+        .try_into()
+        .map_err(|r: Vec<_>| invalid(format!("{} rounds are not {}", r.len(), IPA_PROOF_DEPTH)))
+}
+
+fn invalid(message: String) -> VerkleError {
+    VerkleError::InvalidWitness(message)
+}
+
+/// The subset of SSZ the witness is made of
+mod ssz {
+    use super::invalid;
+    use crate::VerkleError;
+
+    const OFFSET_SIZE: usize = 4;
+
+    /// A field of a container. Fixed-size fields are inlined, variable-size ones are appended after all fixed
+    /// parts and referenced by their offset.
+    pub(super) enum Field {
+        Fixed(Vec<u8>),
+        Variable(Vec<u8>),
+    }
+
+    pub(super) fn container(fields: Vec<Field>) -> Vec<u8> {
+        let fixed_size: usize = fields
+            .iter()
+            .map(|field| match field {
+                Field::Fixed(bytes) => bytes.len(),
+                Field::Variable(_) => OFFSET_SIZE,
+            })
+            .sum();
+
+        let mut fixed = Vec::with_capacity(fixed_size);
+        let mut variable = vec![];
+        for field in fields {
+            match field {
+                Field::Fixed(bytes) => fixed.extend(bytes),
+                Field::Variable(bytes) => {
+                    fixed.extend(((fixed_size + variable.len()) as u32).to_le_bytes());
+                    variable.extend(bytes);
+                }
+            }
+        }
+        fixed.extend(variable);
+        fixed
+    }
+
+    /// A list of variable-size items, which are offset like the fields of a container
+    pub(super) fn variable_list(items: impl Iterator<Item = Vec<u8>>) -> Vec<u8> {
+        container(items.map(Field::Variable).collect())
+    }
+
+    /// An optional value, as a union of `None` (selector `0`) and the value (selector `1`)
+    pub(super) fn optional(value: &Option<[u8; 32]>) -> Vec<u8> {
+        match value {
+            None => vec![0],
+            Some(bytes) => [&[1], &bytes[..]].concat(),
+        }
+    }
+
+    /// Splits a container into its fields, given the size of each fixed-size field (`None` for variable-size ones)
+    pub(super) fn read_container<'a>(
+        bytes: &'a [u8],
+        sizes: &[Option<usize>],
+    ) -> Result<Vec<&'a [u8]>, VerkleError> {
+        let too_short = || {
+            invalid(format!(
+                "{} bytes are too short for the container",
+                bytes.len()
+            ))
+        };
+        let mut fields = Vec::with_capacity(sizes.len());
+        let mut offsets = vec![];
+        let mut position = 0;
+        for size in sizes {
+            let size = size.unwrap_or(OFFSET_SIZE);
+            let field = bytes.get(position..position + size).ok_or_else(too_short)?;
+            fields.push(field);
+            position += size;
+        }
+
+        for (i, size) in sizes.iter().enumerate() {
+            if size.is_none() {
+                let offset = u32::from_le_bytes(fields[i].try_into().unwrap()) as usize;
+                offsets.push((i, offset));
+            }
+        }
+        let ends = offsets
+            .iter()
+            .skip(1)
+            .map(|(_, offset)| *offset)
+            .chain(Some(bytes.len()));
+        let mut expected = position;
+        for ((i, offset), end) in offsets.iter().zip(ends) {
+            if *offset != expected || end < *offset {
+                return Err(invalid(format!("Offset {} is out of place", offset)));
+            }
+            fields[*i] = &bytes[*offset..end];
+            expected = end;
+        }
+        if expected != bytes.len() {
+            return Err(invalid(format!(
+                "{} trailing bytes follow the container",
+                bytes.len() - expected
+            )));
+        }
+        Ok(fields)
+    }
+
+    pub(super) fn read_variable_list(bytes: &[u8]) -> Result<Vec<&[u8]>, VerkleError> {
+        if bytes.is_empty() {
+            return Ok(vec![]);
+        }
+        let first = bytes
+            .get(0..OFFSET_SIZE)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize)
+            .ok_or_else(|| invalid("A list offset is cut off".to_string()))?;
+        if first == 0 || first % OFFSET_SIZE != 0 {
+            return Err(invalid(format!("Offset {} is out of place", first)));
+        }
+        read_container(bytes, &vec![None; first / OFFSET_SIZE])
+    }
+
+    pub(super) fn read_fixed_list<const N: usize>(
+        bytes: &[u8],
+    ) -> Result<Vec<[u8; N]>, VerkleError> {
+        if !bytes.len().is_multiple_of(N) {
+            return Err(invalid(format!(
+                "{} bytes are not a list of {} byte items",
+                bytes.len(),
+                N
+            )));
+        }
+        bytes.chunks_exact(N).map(read_array).collect()
+    }
+
+    pub(super) fn read_array<const N: usize>(bytes: &[u8]) -> Result<[u8; N], VerkleError> {
+        bytes
+            .try_into()
+            .map_err(|_| invalid(format!("{} bytes are not {} bytes", bytes.len(), N)))
+    }
+
+    pub(super) fn read_optional(bytes: &[u8]) -> Result<Option<[u8; 32]>, VerkleError> {
+        match bytes.split_first() {
+            Some((0, [])) => Ok(None),
+            Some((1, value)) => Ok(Some(read_array(value)?)),
+            _ => Err(invalid("An optional value is malformed".to_string())),
+        }
+    }
+}
+
+/// Serde of byte arrays as `0x` prefixed hex strings
+mod hex_array {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer, const N: usize>(
+        bytes: &[u8; N],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("0x{}", hex::encode(bytes)))
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>, const N: usize>(
+        deserializer: D,
+    ) -> Result<[u8; N], D::Error> {
+        from_hex(&String::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+
+    pub(super) fn from_hex<const N: usize>(s: &str) -> Result<[u8; N], String> {
+        let bytes = super::hex_bytes::from_hex(s)?;
+        bytes
+            .try_into()
+            .map_err(|b: Vec<u8>| format!("{} bytes are not {} bytes", b.len(), N))
+    }
+}
+
+mod hex_bytes {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("0x{}", hex::encode(bytes)))
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<u8>, D::Error> {
+        from_hex(&String::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+
+    pub(super) fn from_hex(s: &str) -> Result<Vec<u8>, String> {
+        let digits = s
+            .strip_prefix("0x")
+            .ok_or_else(|| format!("{} is not 0x prefixed", s))?;
+        hex::decode(digits).map_err(|e| e.to_string())
+    }
+}
+
+mod hex_option {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(
+        value: &Option<[u8; 32]>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(bytes) => super::hex_array::serialize(bytes, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<[u8; 32]>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|s| super::hex_array::from_hex(&s).map_err(D::Error::custom))
+            .transpose()
+    }
+}
+
+mod hex_list {
+    use serde::{de::Error, ser::SerializeSeq, Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer, const N: usize>(
+        items: &[[u8; N]],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(items.len()))?;
+        for item in items {
+            seq.serialize_element(&format!("0x{}", hex::encode(item)))?;
+        }
+        seq.end()
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>, const N: usize>(
+        deserializer: D,
+    ) -> Result<Vec<[u8; N]>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|s| super::hex_array::from_hex(s).map_err(D::Error::custom))
+            .collect()
+    }
+}
+
+/// Serde of the commitments of the rounds of an IPA proof, as a list of hex strings
+mod hex_rounds {
+    use serde::{de::Error, Deserializer, Serializer};
+
+    use super::IPA_PROOF_DEPTH;
+
+    pub(super) fn serialize<S: Serializer>(
+        rounds: &[[u8; 32]; IPA_PROOF_DEPTH],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        super::hex_list::serialize(&rounds[..], serializer)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<[[u8; 32]; IPA_PROOF_DEPTH], D::Error> {
+        let rounds: Vec<[u8; 32]> = super::hex_list::deserialize(deserializer)?;
+        rounds.try_into().map_err(|r: Vec<_>| {
+            D::Error::custom(format!("{} rounds are not {}", r.len(), IPA_PROOF_DEPTH))
+        })
+    }
+}